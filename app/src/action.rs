@@ -1,6 +1,9 @@
 use crate::buffer::BufferId;
+use crate::lsp::LspEvent;
+use crate::modal::{Mode, Operator};
 use crate::pane_tree::Orientation;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum Direction {
@@ -19,6 +22,15 @@ pub enum Boundary {
     // LineEndExcludingWhitespace,
 }
 
+/// Where the output of a filter command goes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum FilterOutput {
+    /// Replace the filtered text with the output.
+    Replace,
+    /// Put the output in a new buffer.
+    NewBuffer,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum Move {
     Boundary(Boundary),
@@ -43,6 +55,31 @@ pub enum Action {
     NextPane,
     SplitPane(Orientation),
     ClosePane,
+
+    /// Close all panes other than the active one.
+    CloseOtherPanes,
+
+    /// Grow (`Inc`) or shrink (`Dec`) the active pane along an axis.
+    ResizePane(Orientation, Direction),
+
+    /// Make all panes that are side by side the same size.
+    BalancePanes,
+
+    /// Make the pane to the left/right (`Horizontal`) or above/below
+    /// (`Vertical`) of the active pane active.
+    FocusPane(Orientation, Direction),
+
+    /// Swap the active pane's buffer with the neighboring pane's
+    /// buffer. Focus stays with the moved buffer.
+    SwapPane(Orientation, Direction),
+
+    /// Move the active pane to the far side of its neighbor, splitting
+    /// the neighbor's space.
+    MovePane(Orientation, Direction),
+
+    /// Switch the split containing the active pane between horizontal
+    /// and vertical.
+    RotateSplit,
     Confirm,
     OpenShell,
     InteractiveSearch,
@@ -60,23 +97,136 @@ pub enum Action {
     /// Move the cursor in the active pane.
     Move(Move, Direction),
 
+    /// Scroll the active pane by a line without moving the cursor,
+    /// unless it would go out of view.
+    Scroll(Direction),
+
+    /// Scroll the cursor's line to the middle of the active pane.
+    /// Repeating this cycles through the top, bottom and middle.
+    Recenter,
+
+    /// Turn wrapping of long lines in the active pane on or off.
+    ToggleWrap,
+
+    /// With modal editing, switch to another mode.
+    SetMode(Mode),
+
+    /// With modal editing, apply an operator to the text covered by
+    /// the next motion, or to the selection in visual mode.
+    Operator(Operator),
+
+    /// Add a digit to the count for the next action. Bound to the
+    /// digit keys in modal editing's normal mode.
+    CountDigit(u8),
+
+    /// Start a count for the next action. Digits typed after it are
+    /// part of the count.
+    UniversalArgument,
+
+    /// Run the last action run from a key press again, with the same
+    /// count.
+    RepeatLastAction,
+
+    /// Start recording a keyboard macro.
+    StartMacro,
+
+    /// Stop recording the keyboard macro.
+    EndMacro,
+
+    /// Run the last recorded keyboard macro, `count` times.
+    ReplayMacro,
+
+    /// Prompt for a name to save the last recorded keyboard macro
+    /// under.
+    SaveMacro,
+
+    /// Prompt for the name of a saved keyboard macro to run.
+    RunSavedMacro,
+
+    /// Run the saved keyboard macro with this name.
+    RunMacro(String),
+
+    /// Set the mark at the cursor in the active pane. The text
+    /// between the mark and the cursor is the selection.
+    SetMark,
+
+    /// Prompt for a command, then send the selection (or the whole
+    /// buffer if nothing is selected) through it.
+    FilterRegion(FilterOutput),
+
+    /// Open a popup listing every action, and run the one chosen.
+    OpenCommandPalette,
+
     /// Interactively switch to a different buffer.
     SwitchToBuffer,
 
+    /// Interactively open a file anywhere in the active buffer's
+    /// project.
+    FindFileInProject,
+
+    /// In a directory listing, re-read the directory.
+    RefreshDirectory,
+
+    /// In a directory listing, prompt for the name of a new file or
+    /// directory to create.
+    CreateDirEntry,
+
+    /// In a directory listing, prompt for a new name for the entry at
+    /// the cursor.
+    RenameDirEntry,
+
+    /// In a directory listing, delete the entry at the cursor after
+    /// asking for confirmation.
+    DeleteDirEntry,
+
+    /// A batch of files found by the walk with the given search ID,
+    /// relative to the project root.
+    FoundFiles(String, Vec<PathBuf>),
+
+    /// Show the bindings that could complete the pending key
+    /// sequence, if it's still the sequence with this ID. Sent a
+    /// short while after a prefix is typed.
+    ShowKeyHints(u64),
+
     /// Cancel the current operation, e.g. opening a file.
     Cancel,
 
     /// Try to autocomplete something, e.g. a file path.
     Autocomplete,
 
+    /// Open a popup listing completions for the text before the
+    /// cursor.
+    Complete,
+
+    /// In a popup with a list of candidates, select the next or
+    /// previous candidate.
+    SelectCandidate(Direction),
+
+    /// Jump to the definition of the symbol at the cursor.
+    GoToDefinition,
+
+    /// Show information about the symbol at the cursor.
+    ShowHover,
+
     RunNonInteractiveProcess,
 
+    /// In a prompt with history, replace the input with the previous
+    /// (older) history entry.
+    PreviousHistoryItem,
+
+    /// In a prompt with history, replace the input with the next
+    /// (newer) history entry.
+    NextHistoryItem,
+
     // TODO: maybe not the right level of specificity
     AppendToBuffer(BufferId, String),
 
     /// A background process completed.
     ProcessFinished(BufferId),
 
+    /// A message from a language server.
+    Lsp(LspEvent),
+
     /// In a buffer with a process, re-run the process. If the process
     /// is already running, it will be killed and started anew.
     RerunProcess,
@@ -2,7 +2,9 @@ pub use crate::rope::{AbsChar, AbsLine, LinesIterItem, RelChar, RelLine};
 
 use crate::action::{Boundary, Direction, Move};
 use crate::command_line::CommandLine;
+use crate::dir_listing::DirListing;
 use crate::grapheme::{next_grapheme_boundary, prev_grapheme_boundary};
+use crate::lsp::protocol;
 use crate::message::MessageWriter;
 use crate::pane_tree::{Pane, PaneId};
 use crate::process::NonInteractiveProcess;
@@ -12,6 +14,7 @@ use crate::theme::Theme;
 use crate::util;
 use aho_corasick::AhoCorasick;
 use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::{self};
@@ -23,6 +26,10 @@ use syntect::highlighting::{
 };
 use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
 
+/// Syntax definitions, loaded on first use since that's slow.
+static SYNTAX_SET: Lazy<SyntaxSet> =
+    Lazy::new(SyntaxSet::load_defaults_newlines);
+
 // TODO: not sure where we want these.
 pub const PROMPT_END: &str = "prompt_end";
 pub const COMPLETION_START: &str = "completion_start";
@@ -106,7 +113,9 @@ impl LinePosition {
         mut num_graphemes: usize,
     ) {
         let line = buf.text().line(self.line);
-        let num_chars = line.len_chars();
+        // Stop before the newline so that a short line doesn't put
+        // the position on the next line.
+        let num_chars = line_len_without_newline(buf.text(), self.line);
         self.offset = RelChar::zero();
         while num_graphemes > 0 {
             self.offset = next_grapheme_boundary(&line, self.offset.0);
@@ -119,6 +128,28 @@ impl LinePosition {
     }
 }
 
+/// Get the number of chars in a line, not counting the newline.
+pub(crate) fn line_len_without_newline(text: &Rope, line: AbsLine) -> usize {
+    let line = text.line(line);
+    let len = line.len_chars();
+    if len > 0 && line.slice(len - 1..).to_string() == "\n" {
+        len - 1
+    } else {
+        len
+    }
+}
+
+/// Map `pos` in `old` to the position in `new` with the same line and
+/// column, clamped to the end of the line (or last line) if needed.
+fn remap_position(old: &Rope, new: &Rope, pos: AbsChar) -> AbsChar {
+    let line = old.char_to_line(pos);
+    let column = pos.0 - old.line_to_char(line);
+
+    let line = std::cmp::min(line, new.max_line_index());
+    let column = std::cmp::min(column, line_len_without_newline(new, line));
+    AbsChar(new.line_to_char(line) + column)
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct StyleSpan {
     pub len: usize,
@@ -129,6 +160,38 @@ pub struct StyleSpan {
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct StyledLine(pub Vec<StyleSpan>);
 
+/// An edit to the buffer's text, recorded so that it can be sent to a
+/// language server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TextChange {
+    /// The text in `range` was replaced with `text`. The range is
+    /// relative to the text as it was just before this change.
+    Edit {
+        range: protocol::Range,
+        text: String,
+    },
+
+    /// The text was changed in a way that isn't tracked as an edit,
+    /// e.g. undo. The whole text must be resent.
+    Reset,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// A problem reported by a language server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub range: Range<AbsChar>,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum ActionType {
     None,
@@ -149,6 +212,10 @@ struct HistoryItem {
 
     // Each pane showing this buffer has its own cursor.
     cursors: CursorMap,
+
+    // Each pane may also have a mark. The text between a pane's mark
+    // and its cursor is the selection.
+    marks: CursorMap,
 }
 
 /// Matching spans within a line.
@@ -194,23 +261,50 @@ impl SearchState {
     }
 }
 
+/// Cursor and scroll position of a pane showing a buffer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ViewPosition {
+    pub cursor: AbsChar,
+    pub top_line: AbsLine,
+}
+
 pub struct Buffer {
     id: BufferId,
 
     path: Option<PathBuf>,
 
+    /// Name shown for a buffer that has no path, e.g. "*scratch*".
+    name: Option<String>,
+
     history: Vec<HistoryItem>,
     active_history_index: usize,
     last_action_type: ActionType,
 
+    /// Set while edits are grouped into a single undo step. True once
+    /// the group's history item has been stored.
+    undo_group: Option<bool>,
+
     // TODO: think about a smarter structure
     // TODO: put in arc for async update
     style_spans: LineDataVec<StyledLine>,
 
     search: Option<SearchState>,
 
+    /// Edits not yet sent to a language server. `None` if the buffer
+    /// isn't being tracked by a language server.
+    pending_changes: Option<Vec<TextChange>>,
+
+    diagnostics: Vec<Diagnostic>,
+
+    /// Position of the last pane that stopped showing the buffer, so
+    /// that the position can be restored when a pane shows it again.
+    last_view: ViewPosition,
+
     _shell: Option<Shell>,
     non_interactive_process: Option<NonInteractiveProcess>,
+
+    /// Set for a read-only buffer listing a directory.
+    dir_listing: Option<DirListing>,
 }
 
 impl fmt::Debug for Buffer {
@@ -230,14 +324,21 @@ impl Buffer {
                 text,
                 markers: HashMap::new(),
                 cursors: CursorMap::new(),
+                marks: CursorMap::new(),
             }],
             active_history_index: 0,
             last_action_type: ActionType::None,
+            undo_group: None,
             path,
+            name: None,
             style_spans: LineDataVec::new(AbsLine::zero()),
             search: None,
+            pending_changes: None,
+            diagnostics: Vec::new(),
+            last_view: ViewPosition::default(),
             _shell: None,
             non_interactive_process: None,
+            dir_listing: None,
         };
 
         // TODO, async
@@ -264,10 +365,69 @@ impl Buffer {
         Ok(Self::new(BufferId::new(), text, Some(path.into())))
     }
 
+    /// Create a read-only buffer listing the contents of `dir`. The
+    /// cursor starts on the first entry.
+    pub fn from_directory(dir: &Path) -> Result<Self> {
+        let listing = DirListing::read(dir)?;
+        let text = Rope::from_str(&listing.to_text());
+        let mut buf = Self::new(BufferId::new(), text, None);
+        buf.last_view.cursor =
+            AbsChar(buf.text().line_to_char(listing.first_entry_line()));
+        buf.dir_listing = Some(listing);
+        Ok(buf)
+    }
+
     pub fn id(&self) -> &BufferId {
         &self.id
     }
 
+    pub fn dir_listing(&self) -> Option<&DirListing> {
+        self.dir_listing.as_ref()
+    }
+
+    /// Whether the text can't be edited directly.
+    pub fn is_read_only(&self) -> bool {
+        self.dir_listing.is_some()
+    }
+
+    /// Re-read the directory of a listing buffer. Cursors stay on the
+    /// same entry if it still exists, otherwise on the same line.
+    pub fn refresh_dir_listing(&mut self) -> Result<()> {
+        let Some(old) = &self.dir_listing else {
+            return Ok(());
+        };
+        let new = DirListing::read(old.dir())?;
+        let old_text = self.text();
+        let new_text = Rope::from_str(&new.to_text());
+
+        let remap = |pos: AbsChar| {
+            let line = old_text.char_to_line(pos);
+            let Some(new_line) = old
+                .entry_at_line(line)
+                .and_then(|entry| new.line_of_entry(&entry.name))
+            else {
+                return remap_position(old_text, &new_text, pos);
+            };
+            let column = (pos.0 - old_text.line_to_char(line))
+                .min(line_len_without_newline(&new_text, new_line));
+            AbsChar(new_text.line_to_char(new_line) + column)
+        };
+        let cursors: Vec<_> = self
+            .cursors()
+            .iter()
+            .map(|(pane_id, pos)| (pane_id.clone(), remap(*pos)))
+            .collect();
+        let last_view_cursor = remap(self.last_view.cursor);
+
+        self.set_text(&new_text.to_string());
+        for (pane_id, pos) in cursors {
+            self.set_cursor(&pane_id, pos);
+        }
+        self.last_view.cursor = last_view_cursor;
+        self.dir_listing = Some(new);
+        Ok(())
+    }
+
     pub fn run_non_interactive_process(
         &mut self,
         command_line: CommandLine,
@@ -317,6 +477,51 @@ impl Buffer {
         self.path.as_deref()
     }
 
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_owned());
+    }
+
+    /// Get a short name for the buffer to show in the UI: the file
+    /// name for a file, the directory name for a listing, the command
+    /// for a process buffer, otherwise the name set with `set_name`.
+    pub fn display_name(&self) -> String {
+        if let Some(listing) = &self.dir_listing {
+            let dir = listing.dir();
+            let name = dir.file_name().unwrap_or(dir.as_os_str());
+            return format!(
+                "{}/",
+                name.to_string_lossy().trim_end_matches('/')
+            );
+        }
+        if let Some(name) = self.path().and_then(Path::file_name) {
+            return name.to_string_lossy().into_owned();
+        }
+        if let Some(proc) = &self.non_interactive_process {
+            let command = proc.command_line().to_string();
+            if !command.is_empty() {
+                return format!("*{command}*");
+            }
+        }
+        self.name.clone().unwrap_or_else(|| "*untitled*".to_owned())
+    }
+
+    /// Get the directory associated with the buffer. For a file this
+    /// is the directory containing the file, for a listing it's the
+    /// listed directory, for a process it's the directory the process
+    /// runs in.
+    pub fn directory(&self) -> Option<&Path> {
+        if let Some(path) = &self.path {
+            return path.parent();
+        }
+        if let Some(listing) = &self.dir_listing {
+            return Some(listing.dir());
+        }
+
+        self.non_interactive_process
+            .as_ref()
+            .and_then(|proc| proc.command_line().current_dir())
+    }
+
     pub fn style_spans(&self) -> &LineDataVec<StyledLine> {
         &self.style_spans
     }
@@ -325,6 +530,48 @@ impl Buffer {
         &self.search
     }
 
+    /// Start recording changes to the text. See `take_changes`.
+    pub fn track_changes(&mut self) {
+        self.pending_changes.get_or_insert_with(Vec::new);
+    }
+
+    /// Get the changes made since the last call, or since
+    /// `track_changes` was called.
+    pub fn take_changes(&mut self) -> Vec<TextChange> {
+        self.pending_changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Record that `range` is about to be replaced with `text`.
+    fn record_change(&mut self, range: Range<AbsChar>, text: &str) {
+        if self.pending_changes.is_none() {
+            return;
+        }
+        let range = protocol::Range::from_abs_range(self.text(), range);
+        if let Some(changes) = &mut self.pending_changes {
+            changes.push(TextChange::Edit {
+                range,
+                text: text.to_owned(),
+            });
+        }
+    }
+
+    fn record_reset(&mut self) {
+        if let Some(changes) = &mut self.pending_changes {
+            changes.push(TextChange::Reset);
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn set_diagnostics(&mut self, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics = diagnostics;
+    }
+
     pub fn get_marker(&self, name: &str) -> Option<AbsChar> {
         self.active_history_item().markers.get(name).copied()
     }
@@ -344,44 +591,58 @@ impl Buffer {
             .unwrap_or_else(|| panic!("no cursor for {pane_id}"))
     }
 
+    /// Move the cursor one step. A pane's height isn't known here, so
+    /// `Move::Page` only moves one line; `Pane::move_cursor` moves by
+    /// the pane's height instead.
     pub fn move_cursor(
         &mut self,
         pane_id: &PaneId,
         step: Move,
         dir: Direction,
     ) {
-        let mut cursor = self.cursor(pane_id);
-
         match step {
             Move::Boundary(boundary) => {
-                cursor = self.find_boundary(cursor, boundary, dir);
+                let cursor = self.cursor(pane_id);
+                let cursor = self.find_boundary(cursor, boundary, dir);
+                self.set_cursor_within_limits(pane_id, cursor);
             }
-            Move::Line | Move::Page => {
-                let offset =
-                    RelLine::new(if step == Move::Line { 1 } else { 20 });
+            Move::Line | Move::Page => self.move_cursor_lines(pane_id, 1, dir),
+        }
+    }
 
-                let mut lp = LinePosition::from_abs_char(cursor, self);
+    /// Move the cursor `lines` lines up or down, staying in about the
+    /// same column.
+    pub fn move_cursor_lines(
+        &mut self,
+        pane_id: &PaneId,
+        lines: usize,
+        dir: Direction,
+    ) {
+        let offset = RelLine::new(lines);
+        let mut lp = LinePosition::from_abs_char(self.cursor(pane_id), self);
 
-                // When moving between lines, use grapheme offset
-                // rather than char offset to keep the cursor more or
-                // less visually horizontally aligned. Probably would
-                // need to be more sophisticated for non-monospace
-                // fonts though.
-                let num_graphemes = lp.grapheme_offset(self);
+        // When moving between lines, use grapheme offset rather than
+        // char offset to keep the cursor more or less visually
+        // horizontally aligned. Probably would need to be more
+        // sophisticated for non-monospace fonts though.
+        let num_graphemes = lp.grapheme_offset(self);
 
-                if dir == Direction::Dec {
-                    lp.line = lp.line.saturating_sub(offset);
-                } else {
-                    lp.line = std::cmp::min(
-                        lp.line + offset,
-                        self.text().max_line_index(),
-                    );
-                }
-                lp.set_offset_in_graphemes(self, num_graphemes);
-                cursor = lp.to_abs_char(self);
-            }
+        if dir == Direction::Dec {
+            lp.line = lp.line.saturating_sub(offset);
+        } else {
+            lp.line =
+                std::cmp::min(lp.line + offset, self.text().max_line_index());
         }
+        lp.set_offset_in_graphemes(self, num_graphemes);
+        let cursor = lp.to_abs_char(self);
+        self.set_cursor_within_limits(pane_id, cursor);
+    }
 
+    fn set_cursor_within_limits(
+        &mut self,
+        pane_id: &PaneId,
+        mut cursor: AbsChar,
+    ) {
         // Prevent the cursor from going before the prompt end or after
         // the completion start.
         //
@@ -417,28 +678,76 @@ impl Buffer {
     }
 
     pub fn remove_cursor(&mut self, pane: &Pane) {
-        // Remove the cursor from all history items.
+        if let Some(cursor) = self.cursors().get(pane.id()) {
+            self.last_view = ViewPosition {
+                cursor: *cursor,
+                top_line: pane.top_line(),
+            };
+        }
+
+        // Remove the cursor (and mark) from all history items.
         for item in &mut self.history {
             item.cursors.remove(pane.id());
+            item.marks.remove(pane.id());
         }
     }
 
+    /// Get the position of the last pane that stopped showing the
+    /// buffer, clamped to the current text.
+    pub fn last_view(&self) -> ViewPosition {
+        let text = self.text();
+        ViewPosition {
+            cursor: AbsChar(self.last_view.cursor.0.min(text.len_chars())),
+            top_line: AbsLine(
+                self.last_view.top_line.0.min(text.max_line_index().0),
+            ),
+        }
+    }
+
+    pub fn set_last_view(&mut self, view: ViewPosition) {
+        self.last_view = view;
+    }
+
     pub fn cursors(&self) -> &CursorMap {
         &self.active_history_item().cursors
     }
 
+    pub fn mark(&self, pane_id: &PaneId) -> Option<AbsChar> {
+        self.active_history_item().marks.get(pane_id).copied()
+    }
+
+    pub fn set_mark(&mut self, pane_id: &PaneId, mark: AbsChar) {
+        self.active_history_item_mut()
+            .marks
+            .insert(pane_id.clone(), mark);
+    }
+
+    pub fn clear_mark(&mut self, pane_id: &PaneId) {
+        self.active_history_item_mut().marks.remove(pane_id);
+    }
+
+    /// Get the selected range of text in a pane, i.e. the text
+    /// between the pane's mark and its cursor. Returns `None` if the
+    /// pane has no mark.
+    pub fn selection(&self, pane_id: &PaneId) -> Option<Range<AbsChar>> {
+        let mark = self.mark(pane_id)?;
+        let cursor = self.cursor(pane_id);
+        Some(std::cmp::min(mark, cursor)..std::cmp::max(mark, cursor))
+    }
+
     /// Remove all text from the buffer.
     #[expect(unused)] // TODO
     fn clear(&mut self) {
         self.maybe_store_history_item(ActionType::Clear);
 
+        self.record_reset();
         *self.text_mut().unwrap() = Rope::new();
 
         // TODO: async style recalc
         self.recalc_style_spans();
 
         // Update all cursors.
-        for cursor in self.cursors_mut().values_mut() {
+        for cursor in self.positions_mut() {
             cursor.0 = 0;
         }
     }
@@ -455,6 +764,13 @@ impl Buffer {
         &mut self.history[self.active_history_index].cursors
     }
 
+    /// Get all cursors and marks in the active history item. These
+    /// all need updating when text is edited.
+    fn positions_mut(&mut self) -> impl Iterator<Item = &mut AbsChar> {
+        let item = &mut self.history[self.active_history_index];
+        item.cursors.values_mut().chain(item.marks.values_mut())
+    }
+
     fn maybe_store_history_item(&mut self, action_type: ActionType) {
         // Check if the active history item is not most recent history
         // item. That means the user has run undo one or more times,
@@ -466,6 +782,21 @@ impl Buffer {
             // Reset the last_action_type; whatever action is occuring
             // now should not be merged into the top history item.
             self.last_action_type = ActionType::None;
+
+            // Likewise an undo inside a group starts a new item.
+            if let Some(stored) = &mut self.undo_group {
+                *stored = false;
+            }
+        }
+
+        // Within a group, all edits go into the group's item.
+        if let Some(stored) = self.undo_group {
+            if !stored {
+                self.history.push(self.history.last().unwrap().clone());
+                self.active_history_index = self.history.len() - 1;
+                self.undo_group = Some(true);
+            }
+            return;
         }
 
         // If the action type is unchanged then we don't store a new
@@ -490,9 +821,21 @@ impl Buffer {
         }
     }
 
+    /// Group all edits until `end_undo_group` into a single undo
+    /// step.
+    pub fn begin_undo_group(&mut self) {
+        self.undo_group = Some(false);
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.undo_group = None;
+        self.last_action_type = ActionType::None;
+    }
+
     pub fn undo(&mut self) {
         if self.active_history_index > 0 {
             self.active_history_index -= 1;
+            self.record_reset();
         }
 
         // TODO: async style recalc
@@ -502,6 +845,7 @@ impl Buffer {
     pub fn redo(&mut self) {
         if self.active_history_index + 1 < self.history.len() {
             self.active_history_index += 1;
+            self.record_reset();
         }
 
         // TODO: async style recalc
@@ -552,10 +896,11 @@ impl Buffer {
     pub fn delete_text(&mut self, range: Range<AbsChar>) {
         self.maybe_store_history_item(ActionType::Deletion);
 
+        self.record_change(range.clone(), "");
         self.text_mut().unwrap().remove(range.clone());
 
         // Update all cursors in this buffer.
-        for cursor in self.cursors_mut().values_mut() {
+        for cursor in self.positions_mut() {
             if range.contains(cursor) {
                 *cursor = range.start;
             } else if *cursor >= range.end {
@@ -571,6 +916,7 @@ impl Buffer {
     pub fn insert_char(&mut self, c: char, pos: AbsChar) {
         self.maybe_store_history_item(ActionType::InsertChar);
 
+        self.record_change(pos..pos, &c.to_string());
         self.text_mut().unwrap().insert(pos, &c.to_string());
 
         // Update the associated style span to account for the new
@@ -590,17 +936,81 @@ impl Buffer {
         self.recalc_style_spans();
 
         // Update all cursors in this buffer.
-        for cursor in self.cursors_mut().values_mut() {
+        for cursor in self.positions_mut() {
             if cursor.0 >= pos.0 {
                 cursor.0 += 1;
             }
         }
     }
 
+    /// Replace the text in `range` with `text`. This is always stored
+    /// as its own undo step.
+    ///
+    /// Cursors after the range are shifted to stay on the same
+    /// text. Cursors inside the range are kept at the same line and
+    /// column relative to the start of the range where possible.
+    pub fn replace_text(&mut self, range: Range<AbsChar>, text: &str) {
+        let old_region =
+            Rope::from_str(&self.text().slice(range.clone()).to_string());
+        let new_region = Rope::from_str(text);
+
+        self.maybe_store_history_item(ActionType::None);
+
+        self.record_change(range.clone(), text);
+        let rope = self.text_mut().unwrap();
+        rope.remove(range.clone());
+        rope.insert(range.start, text);
+
+        for cursor in self.positions_mut() {
+            if range.contains(cursor) {
+                let offset = AbsChar(cursor.0 - range.start.0);
+                let offset = remap_position(&old_region, &new_region, offset);
+                cursor.0 = range.start.0 + offset.0;
+            } else if *cursor >= range.end {
+                cursor.0 = cursor.0 - range.end.0
+                    + range.start.0
+                    + new_region.len_chars();
+            }
+        }
+
+        // TODO: async style recalc
+        self.recalc_style_spans();
+    }
+
+    /// Replace the entire contents of the buffer with `text` as a
+    /// single undo step. Unlike `set_text`, only the part of the text
+    /// that actually changed is replaced, so cursors stay as close as
+    /// possible to where they were. Does nothing if the text is
+    /// unchanged.
+    pub fn update_text(&mut self, text: &str) {
+        let old: Vec<char> = self.text().to_string().chars().collect();
+        let new: Vec<char> = text.chars().collect();
+
+        let prefix_len =
+            old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        if prefix_len == old.len() && prefix_len == new.len() {
+            return;
+        }
+        let suffix_len = old[prefix_len..]
+            .iter()
+            .rev()
+            .zip(new[prefix_len..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let replacement: String =
+            new[prefix_len..new.len() - suffix_len].iter().collect();
+        self.replace_text(
+            AbsChar(prefix_len)..AbsChar(old.len() - suffix_len),
+            &replacement,
+        );
+    }
+
     /// Replace the entire contents of the buffer with `text`.
     pub fn set_text(&mut self, text: &str) {
         self.maybe_store_history_item(ActionType::None);
 
+        self.record_reset();
         *self.text_mut().unwrap() = Rope::from_str(text);
 
         // TODO: async style recalc
@@ -608,7 +1018,7 @@ impl Buffer {
 
         // Update all cursors in this buffer.
         let len_chars = self.text().len_chars();
-        for cursor in self.cursors_mut().values_mut() {
+        for cursor in self.positions_mut() {
             if cursor.0 > len_chars {
                 cursor.0 = len_chars;
             }
@@ -660,14 +1070,17 @@ impl Buffer {
             .expect("missing plain text syntax")
     }
 
+    /// Get the name of the syntax used to highlight the buffer,
+    /// e.g. "Rust".
+    pub fn syntax_name(&self) -> String {
+        self.get_syntax(&SYNTAX_SET).name.clone()
+    }
+
     // TODO: simple for now
     fn recalc_style_spans(&mut self) {
         self.style_spans.clear();
 
-        // TODO: cache
-        let syntax_set = SyntaxSet::load_defaults_newlines();
-
-        let syntax = self.get_syntax(&syntax_set);
+        let syntax = self.get_syntax(&SYNTAX_SET);
 
         let mut parse_state = ParseState::new(syntax);
         let theme = Theme::current();
@@ -688,7 +1101,7 @@ impl Buffer {
             }
 
             let changes =
-                parse_state.parse_line(&full_line, &syntax_set).unwrap();
+                parse_state.parse_line(&full_line, &SYNTAX_SET).unwrap();
 
             let iter = RangedHighlightIterator::new(
                 &mut highlight_state,
@@ -725,6 +1138,38 @@ mod tests {
         (buf, pane_id)
     }
 
+    #[test]
+    fn test_undo_group() {
+        let (mut buf, pane_id) = create_buf("abc");
+        buf.insert_char('x', AbsChar(0));
+
+        buf.begin_undo_group();
+        buf.set_cursor(&pane_id, AbsChar(4));
+        buf.insert_char('y', AbsChar(4));
+        buf.delete_text(AbsChar(1)..AbsChar(2));
+        buf.set_text("whole new text");
+        buf.end_undo_group();
+        buf.insert_char('z', AbsChar(0));
+
+        buf.undo();
+        assert_eq!(buf.text().to_string(), "whole new text");
+        buf.undo();
+        assert_eq!(buf.text().to_string(), "xabc");
+        buf.redo();
+        assert_eq!(buf.text().to_string(), "whole new text");
+    }
+
+    #[test]
+    fn test_move_cursor_lines_to_shorter_line() {
+        let (mut buf, pane_id) = create_buf("abcdef\nx\nyz");
+        buf.set_cursor(&pane_id, AbsChar(4));
+
+        // The cursor stops at the end of the shorter line instead of
+        // going past its newline.
+        buf.move_cursor_lines(&pane_id, 1, Direction::Inc);
+        assert_eq!(buf.cursor(&pane_id), AbsChar(8));
+    }
+
     #[test]
     fn test_move_cursor_line_end() {
         let (mut buf, pane_id) = create_buf("abc\n");
@@ -737,6 +1182,113 @@ mod tests {
         assert_eq!(buf.cursor(&pane_id), AbsChar(3));
     }
 
+    #[test]
+    fn test_selection() {
+        let (mut buf, pane_id) = create_buf("abcdef");
+        assert_eq!(buf.selection(&pane_id), None);
+
+        buf.set_cursor(&pane_id, AbsChar(4));
+        buf.set_mark(&pane_id, AbsChar(1));
+        assert_eq!(buf.selection(&pane_id), Some(AbsChar(1)..AbsChar(4)));
+
+        // The mark is updated by edits.
+        buf.insert_char('x', AbsChar(0));
+        assert_eq!(buf.selection(&pane_id), Some(AbsChar(2)..AbsChar(5)));
+
+        buf.clear_mark(&pane_id);
+        assert_eq!(buf.selection(&pane_id), None);
+    }
+
+    #[test]
+    fn test_replace_text() {
+        let (mut buf, pane_id) = create_buf("head\nb  b\nccc\ntail");
+        let pane2 = PaneId::new();
+        let pane3 = PaneId::new();
+        // "b" on the second line.
+        buf.set_cursor(&pane_id, AbsChar(8));
+        // Third column of the third line.
+        buf.set_cursor(&pane2, AbsChar(12));
+        // The "a" in "tail".
+        buf.set_cursor(&pane3, AbsChar(15));
+
+        buf.replace_text(AbsChar(5)..AbsChar(14), "b b\nc\n");
+        assert_eq!(buf.text().to_string(), "head\nb b\nc\ntail");
+        assert_eq!(buf.cursor(&pane_id), AbsChar(8));
+        assert_eq!(buf.cursor(&pane2), AbsChar(10));
+        assert_eq!(buf.cursor(&pane3), AbsChar(12));
+
+        // The replacement is a single undo step.
+        buf.undo();
+        assert_eq!(buf.text().to_string(), "head\nb  b\nccc\ntail");
+    }
+
+    #[test]
+    fn test_update_text() {
+        let (mut buf, pane_id) = create_buf("fn  f( ) {\n  x\n}\n");
+        let pane2 = PaneId::new();
+        // "x".
+        buf.set_cursor(&pane_id, AbsChar(13));
+        // End of the buffer.
+        buf.set_cursor(&pane2, AbsChar(17));
+
+        buf.update_text("fn f() {\n    x\n}\n");
+        assert_eq!(buf.text().to_string(), "fn f() {\n    x\n}\n");
+        assert_eq!(buf.cursor(&pane_id), AbsChar(13));
+        assert_eq!(buf.cursor(&pane2), AbsChar(17));
+
+        // No change, so no new undo step.
+        buf.update_text("fn f() {\n    x\n}\n");
+        buf.undo();
+        assert_eq!(buf.text().to_string(), "fn  f( ) {\n  x\n}\n");
+    }
+
+    #[test]
+    fn test_track_changes() {
+        let (mut buf, _pane_id) = create_buf("ab\ncd");
+
+        // Not tracked yet.
+        buf.insert_char('x', AbsChar(0));
+        assert!(buf.take_changes().is_empty());
+
+        let range = |l1, c1, l2, c2| protocol::Range {
+            start: protocol::Position {
+                line: l1,
+                character: c1,
+            },
+            end: protocol::Position {
+                line: l2,
+                character: c2,
+            },
+        };
+
+        buf.track_changes();
+        buf.insert_char('y', AbsChar(5));
+        buf.delete_text(AbsChar(0)..AbsChar(1));
+        buf.replace_text(AbsChar(1)..AbsChar(4), "1\n2");
+        assert_eq!(buf.text().to_string(), "a1\n2yd");
+        assert_eq!(
+            buf.take_changes(),
+            [
+                TextChange::Edit {
+                    range: range(1, 1, 1, 1),
+                    text: "y".to_owned(),
+                },
+                TextChange::Edit {
+                    range: range(0, 0, 0, 1),
+                    text: String::new(),
+                },
+                TextChange::Edit {
+                    range: range(0, 1, 1, 1),
+                    text: "1\n2".to_owned(),
+                },
+            ]
+        );
+        assert!(buf.take_changes().is_empty());
+
+        buf.undo();
+        assert_eq!(buf.take_changes(), [TextChange::Reset]);
+    }
+
     #[test]
     fn test_move_cursor_line_end_no_newline() {
         let (mut buf, pane_id) = create_buf("abc");
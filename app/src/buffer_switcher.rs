@@ -0,0 +1,229 @@
+//! Popup for switching the active pane to another open buffer.
+//!
+//! Buffers are listed most recently used first and filtered by fuzzy
+//! matching against their names and paths as the user types.
+
+use crate::action::Direction;
+use crate::buffer::{Buffer, BufferId};
+use crate::candidate_list::{self, CandidateList};
+use crate::key_map::KeyMap;
+use crate::pane_tree::{Pane, Rect};
+use crate::widget::Widget;
+use crate::{LineHeight, fuzzy};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Maximum number of buffers shown at once.
+pub const MAX_VISIBLE_BUFFERS: usize = 10;
+
+pub struct BufferEntry {
+    pub id: BufferId,
+    pub name: String,
+    pub path: Option<String>,
+}
+
+impl BufferEntry {
+    fn score(&self, query: &str) -> Option<i64> {
+        let name_score = fuzzy::score(query, &self.name);
+        let path_score = self
+            .path
+            .as_ref()
+            .and_then(|path| fuzzy::score(query, path));
+        name_score.max(path_score)
+    }
+}
+
+pub struct BufferSwitcher {
+    buffer: Buffer,
+    pane: Pane,
+    rect: Rect,
+
+    /// What the target pane showed when the switcher opened, so it
+    /// can be restored if the switch is cancelled.
+    original_buffer_id: BufferId,
+
+    candidates: CandidateList<BufferEntry>,
+}
+
+impl BufferSwitcher {
+    /// Create a switcher for `target_pane`. `recent` is the buffer
+    /// IDs ordered by most recent use. The target pane's own buffer is
+    /// listed last so that the most recently used other buffer is
+    /// selected initially.
+    pub fn new(
+        target_pane: &Pane,
+        buffers: &HashMap<BufferId, Buffer>,
+        recent: &[BufferId],
+    ) -> Self {
+        let mut buffer = Buffer::create_empty();
+        let pane = Pane::create_for_widget(&mut buffer);
+
+        let current_id = target_pane.buffer_id();
+        let mut ids: Vec<&BufferId> = Vec::new();
+        for id in recent {
+            if id != current_id
+                && buffers.contains_key(id)
+                && !ids.contains(&id)
+            {
+                ids.push(id);
+            }
+        }
+        // Buffers that haven't been shown yet go after the recent ones.
+        let mut unused: Vec<&Buffer> = buffers
+            .values()
+            .filter(|buf| buf.id() != current_id && !ids.contains(&buf.id()))
+            .collect();
+        unused.sort_by_key(|buf| (buf.display_name(), buf.id().as_str()));
+        ids.extend(unused.into_iter().map(|buf| buf.id()));
+        ids.push(current_id);
+
+        let entries = ids
+            .into_iter()
+            .filter_map(|id| buffers.get(id))
+            .map(|buf| BufferEntry {
+                id: buf.id().clone(),
+                name: buf.display_name(),
+                path: buf.path().map(|path| path.display().to_string()),
+            })
+            .collect();
+
+        Self {
+            buffer,
+            pane,
+            rect: Rect::default(),
+            original_buffer_id: current_id.clone(),
+            candidates: CandidateList::new(entries),
+        }
+    }
+
+    pub fn original_buffer_id(&self) -> &BufferId {
+        &self.original_buffer_id
+    }
+
+    pub fn candidates(&self) -> &CandidateList<BufferEntry> {
+        &self.candidates
+    }
+
+    pub fn selected_buffer_id(&self) -> Option<&BufferId> {
+        self.candidates.selected().map(|entry| &entry.id)
+    }
+
+    /// Filter the buffers by the text typed in the switcher. The best
+    /// match gets selected.
+    pub fn update_matches(&mut self) {
+        let query = self.buffer.text().to_string();
+        self.candidates.update_matches(|entry| entry.score(&query));
+        self.candidates.select_first();
+    }
+
+    pub fn select(&mut self, dir: Direction) {
+        self.candidates.select(dir);
+    }
+}
+
+impl Widget for BufferSwitcher {
+    fn get_keymap(&self) -> Result<KeyMap> {
+        candidate_list::popup_keymap("switch_buffer", Vec::new())
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_buffer_mut(&mut self) -> (&Pane, &mut Buffer) {
+        (&self.pane, &mut self.buffer)
+    }
+
+    fn pane_mut_buffer_mut(&mut self) -> (&mut Pane, &mut Buffer) {
+        (&mut self.pane, &mut self.buffer)
+    }
+
+    fn recalc_layout(&mut self, width: f64, line_height: LineHeight) {
+        // Prompt, input line, then one line per visible buffer.
+        let num_rows = self.candidates.len().min(MAX_VISIBLE_BUFFERS);
+        self.rect = candidate_list::popup_layout(
+            &mut self.pane,
+            width,
+            line_height,
+            num_rows,
+        );
+    }
+
+    fn rect(&self) -> &Rect {
+        &self.rect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_switcher() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = |name: &str| -> Result<Buffer> {
+            let path = tmp_dir.path().join(name);
+            std::fs::write(&path, "")?;
+            Buffer::from_path(&path)
+        };
+        let mut scratch = Buffer::create_empty();
+        scratch.set_name("*scratch*");
+        let main = path("main.rs")?;
+        let lib = path("lib.rs")?;
+        let readme = path("README.md")?;
+
+        let mut current = path("current.rs")?;
+        let pane = Pane::create_for_widget(&mut current);
+
+        let recent = vec![
+            current.id().clone(),
+            lib.id().clone(),
+            // Deleted buffer.
+            BufferId::from_string("buffer-deleted".into()),
+            main.id().clone(),
+        ];
+        let buffers: HashMap<BufferId, Buffer> =
+            [scratch, main, lib, readme, current]
+                .into_iter()
+                .map(|buf| (buf.id().clone(), buf))
+                .collect();
+
+        let mut switcher = BufferSwitcher::new(&pane, &buffers, &recent);
+        let names = |switcher: &BufferSwitcher| -> Vec<String> {
+            switcher
+                .candidates()
+                .matches()
+                .map(|entry| entry.name.clone())
+                .collect()
+        };
+        // Recent first, then unused, with the current buffer last.
+        assert_eq!(
+            names(&switcher),
+            ["lib.rs", "main.rs", "*scratch*", "README.md", "current.rs"]
+        );
+        assert_eq!(switcher.selected_buffer_id(), Some(&recent[1]));
+
+        // Both names and paths are matched.
+        switcher.buffer_mut().set_text("README");
+        switcher.update_matches();
+        assert_eq!(names(&switcher), ["README.md"]);
+        let dir_name = tmp_dir.path().file_name().unwrap().to_str().unwrap();
+        switcher.buffer_mut().set_text(dir_name);
+        switcher.update_matches();
+        assert_eq!(switcher.candidates().len(), 4);
+
+        switcher.buffer_mut().set_text("zzz");
+        switcher.update_matches();
+        assert_eq!(switcher.selected_buffer_id(), None);
+
+        Ok(())
+    }
+}
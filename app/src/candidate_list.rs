@@ -0,0 +1,281 @@
+use crate::LineHeight;
+use crate::action::{Action, Direction};
+use crate::key_map::KeyMap;
+use crate::pane_tree::{Pane, Rect};
+use anyhow::Result;
+
+/// A list of candidates filtered by how well they match some input,
+/// with one of the matches selected. Used by popups that let the user
+/// pick from a list, e.g. completion.
+pub struct CandidateList<T> {
+    candidates: Vec<T>,
+
+    /// Indices into `candidates` of the candidates that match, along
+    /// with their scores, best match first.
+    matches: Vec<(usize, i64)>,
+
+    /// Index into `matches`.
+    selected: usize,
+}
+
+impl<T> CandidateList<T> {
+    pub fn new(candidates: Vec<T>) -> Self {
+        Self {
+            matches: (0..candidates.len()).map(|index| (index, 0)).collect(),
+            candidates,
+            selected: 0,
+        }
+    }
+
+    pub fn candidates(&self) -> &[T] {
+        &self.candidates
+    }
+
+    pub fn candidates_mut(&mut self) -> &mut Vec<T> {
+        &mut self.candidates
+    }
+
+    /// Recalculate which candidates match. `score` returns `None` for
+    /// candidates that don't match, otherwise higher is better. Ties
+    /// keep the order of the candidates. The selection stays on the
+    /// same candidate if it still matches.
+    pub fn update_matches(&mut self, score: impl Fn(&T) -> Option<i64>) {
+        let prev_selected = self.selected_candidate_index();
+        self.matches = self.score_range(0, &score);
+        self.restore_selection(prev_selected);
+    }
+
+    /// Add more candidates, scoring only the new ones. `score` should
+    /// be the same as the last call to `update_matches`. The selection
+    /// stays on the same candidate.
+    pub fn extend(
+        &mut self,
+        candidates: impl IntoIterator<Item = T>,
+        score: impl Fn(&T) -> Option<i64>,
+    ) {
+        let prev_selected = self.selected_candidate_index();
+        let start = self.candidates.len();
+        self.candidates.extend(candidates);
+        let added = self.score_range(start, &score);
+
+        // Merge the sorted lists. On a tie the existing match comes
+        // first, since it's earlier in `candidates`.
+        let old = std::mem::take(&mut self.matches);
+        let mut merged = Vec::with_capacity(old.len() + added.len());
+        let mut old = old.into_iter().peekable();
+        let mut added = added.into_iter().peekable();
+        loop {
+            let next = match (old.peek(), added.peek()) {
+                (Some(a), Some(b)) if b.1 > a.1 => added.next(),
+                (Some(_), _) => old.next(),
+                (None, _) => added.next(),
+            };
+            let Some(next) = next else {
+                break;
+            };
+            merged.push(next);
+        }
+        self.matches = merged;
+
+        self.restore_selection(prev_selected);
+    }
+
+    /// Score the candidates from `start` onwards, returning the ones
+    /// that match, best first.
+    fn score_range(
+        &self,
+        start: usize,
+        score: impl Fn(&T) -> Option<i64>,
+    ) -> Vec<(usize, i64)> {
+        let mut scored: Vec<(usize, i64)> = self.candidates[start..]
+            .iter()
+            .enumerate()
+            .filter_map(|(index, c)| score(c).map(|s| (start + index, s)))
+            .collect();
+        scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        scored
+    }
+
+    /// Index into `candidates` of the selected candidate.
+    fn selected_candidate_index(&self) -> Option<usize> {
+        self.matches.get(self.selected).map(|(index, _)| *index)
+    }
+
+    /// Select the candidate at `index` in `candidates` if it matches,
+    /// otherwise the best match.
+    fn restore_selection(&mut self, index: Option<usize>) {
+        self.selected = index
+            .and_then(|prev| self.matches.iter().position(|m| m.0 == prev))
+            .unwrap_or(0);
+    }
+
+    /// Number of matching candidates.
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    /// Iterate over the matching candidates, best match first.
+    pub fn matches(&self) -> impl Iterator<Item = &T> {
+        self.matches.iter().map(|(i, _)| &self.candidates[*i])
+    }
+
+    pub fn selected(&self) -> Option<&T> {
+        self.selected_candidate_index().map(|i| &self.candidates[i])
+    }
+
+    /// Index of the selected candidate within the matches.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Select the best match.
+    pub fn select_first(&mut self) {
+        self.selected = 0;
+    }
+
+    /// Move the selection to the next or previous match, wrapping
+    /// around at the ends.
+    pub fn select(&mut self, dir: Direction) {
+        let len = self.matches.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = match dir {
+            Direction::Inc => (self.selected + 1) % len,
+            Direction::Dec => (self.selected + len - 1) % len,
+        };
+    }
+
+    /// Get the range of matches to show when there's only room for
+    /// `num_rows`, such that the selected match is visible.
+    pub fn visible_range(&self, num_rows: usize) -> std::ops::Range<usize> {
+        let start = (self.selected + 1).saturating_sub(num_rows);
+        start..(start + num_rows).min(self.matches.len())
+    }
+}
+
+/// Keymap for a popup that picks from a candidate list, with
+/// `extra` bindings for anything specific to the popup.
+pub(crate) fn popup_keymap(
+    name: &'static str,
+    extra: Vec<(&str, Action)>,
+) -> Result<KeyMap> {
+    KeyMap::from_pairs(
+        name,
+        [
+            ("<ret>", Action::Confirm),
+            ("<ctrl>m", Action::Confirm),
+            ("<ctrl>n", Action::SelectCandidate(Direction::Inc)),
+            ("<down>", Action::SelectCandidate(Direction::Inc)),
+            ("<ctrl>p", Action::SelectCandidate(Direction::Dec)),
+            ("<up>", Action::SelectCandidate(Direction::Dec)),
+        ]
+        .into_iter()
+        .chain(extra),
+    )
+}
+
+/// Lay out a popup at the top of the window: a prompt, then the
+/// input line in `pane`, then `num_rows` candidates. Returns the
+/// popup's rect.
+pub(crate) fn popup_layout(
+    pane: &mut Pane,
+    width: f64,
+    line_height: LineHeight,
+    num_rows: usize,
+) -> Rect {
+    pane.set_rect(Rect {
+        x: 0.0,
+        y: line_height.0,
+        width,
+        height: line_height.0,
+    });
+    Rect {
+        x: 0.0,
+        y: 0.0,
+        width,
+        height: line_height.0 * (2 + num_rows) as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzzy;
+
+    #[test]
+    fn test_candidate_list() {
+        let mut list = CandidateList::new(vec!["abc", "xyz", "aaa", "ab"]);
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.selected(), Some(&"abc"));
+
+        list.update_matches(|c| fuzzy::score("ab", c));
+        assert_eq!(list.matches().collect::<Vec<_>>(), [&"abc", &"ab"]);
+
+        // Selection wraps around.
+        list.select(Direction::Dec);
+        assert_eq!(list.selected(), Some(&"ab"));
+        list.select(Direction::Inc);
+        assert_eq!(list.selected(), Some(&"abc"));
+        list.select(Direction::Inc);
+
+        // The selection follows the candidate when the matches change.
+        list.update_matches(|c| fuzzy::score("b", c));
+        assert_eq!(list.selected(), Some(&"ab"));
+        list.update_matches(|c| fuzzy::score("c", c));
+        assert_eq!(list.selected(), Some(&"abc"));
+
+        list.update_matches(|c| fuzzy::score("q", c));
+        assert!(list.is_empty());
+        assert_eq!(list.selected(), None);
+        list.select(Direction::Inc);
+        assert_eq!(list.selected(), None);
+    }
+
+    #[test]
+    fn test_extend() {
+        let score = |c: &&str| fuzzy::score("ab", c);
+        let mut list = CandidateList::new(vec![]);
+        list.update_matches(score);
+        list.extend(["abc", "xyz"], score);
+        assert_eq!(list.matches().collect::<Vec<_>>(), [&"abc"]);
+        assert_eq!(list.selected(), Some(&"abc"));
+
+        // New matches are merged in by score, and the selection stays
+        // on the same candidate.
+        list.extend(["ab", "aaa", "xabc"], score);
+        assert_eq!(
+            list.matches().collect::<Vec<_>>(),
+            [&"abc", &"ab", &"xabc"]
+        );
+        list.select(Direction::Inc);
+        list.extend(["ab"], score);
+        assert_eq!(
+            list.matches().collect::<Vec<_>>(),
+            [&"abc", &"ab", &"ab", &"xabc"]
+        );
+        assert_eq!(list.selected_index(), 1);
+    }
+
+    #[test]
+    fn test_visible_range() {
+        let mut list = CandidateList::new((0..10).collect());
+        assert_eq!(list.visible_range(3), 0..3);
+        list.select(Direction::Inc);
+        list.select(Direction::Inc);
+        assert_eq!(list.visible_range(3), 0..3);
+        list.select(Direction::Inc);
+        assert_eq!(list.visible_range(3), 1..4);
+        list.select(Direction::Dec);
+        list.select(Direction::Dec);
+        list.select(Direction::Dec);
+        list.select(Direction::Dec);
+        assert_eq!(list.selected(), Some(&9));
+        assert_eq!(list.visible_range(3), 7..10);
+        assert_eq!(list.visible_range(20), 0..10);
+    }
+}
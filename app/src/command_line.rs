@@ -1,29 +1,127 @@
+use anyhow::{Result, anyhow, bail};
 use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 // The `std::process::Command` type is very awkward to work with (it
 // doesn't even support `Clone` for example), so make a more Rust-y
 // wrapper.
-#[derive(Clone, Default)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct CommandLine {
     program: OsString,
     args: Vec<OsString>,
+    env: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+}
+
+/// Split a `VAR=value` token into its name and value. Returns `None`
+/// if the token is not an environment variable assignment.
+fn parse_env_assignment(token: &str) -> Option<(&str, &str)> {
+    let (name, value) = token.split_once('=')?;
+
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    if !(first.is_ascii_alphabetic() || first == '_') {
+        return None;
+    }
+    if !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    Some((name, value))
 }
 
 impl CommandLine {
     pub fn to_command(&self) -> Command {
         let mut cmd = Command::new(&self.program);
         cmd.args(&self.args);
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)));
+        if let Some(dir) = &self.current_dir {
+            cmd.current_dir(dir);
+        }
         cmd
     }
 
-    pub fn from_string(s: &str) -> Self {
-        // TODO: unwrap
-        let parts = shlex::split(s).unwrap();
-        let program = parts.first().map(OsString::from).unwrap_or_default();
-        Self {
-            program,
-            args: parts.iter().skip(1).map(OsString::from).collect(),
+    /// Parse a shell-like command line. Any leading `VAR=value`
+    /// tokens are treated as environment overrides for the process.
+    pub fn from_string(s: &str) -> Result<Self> {
+        let parts = shlex::split(s).ok_or_else(|| {
+            anyhow!("invalid command line: unbalanced quotes")
+        })?;
+
+        let mut env = Vec::new();
+        let mut parts = parts.into_iter().peekable();
+        while let Some(part) = parts.peek() {
+            if let Some((name, value)) = parse_env_assignment(part) {
+                env.push((name.into(), value.into()));
+                parts.next();
+            } else {
+                break;
+            }
         }
+
+        let Some(program) = parts.next() else {
+            bail!("invalid command line: no program specified");
+        };
+
+        Ok(Self {
+            program: program.into(),
+            args: parts.map(OsString::from).collect(),
+            env,
+            current_dir: None,
+        })
+    }
+
+    pub fn current_dir(&self) -> Option<&Path> {
+        self.current_dir.as_deref()
+    }
+
+    pub fn set_current_dir(&mut self, dir: Option<PathBuf>) {
+        self.current_dir = dir;
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.program.to_string_lossy())?;
+        for arg in &self.args {
+            write!(f, " {}", arg.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_string() -> Result<()> {
+        let cl = CommandLine::from_string("echo 'hello world'")?;
+        assert_eq!(cl.program, "echo");
+        assert_eq!(cl.args, ["hello world"]);
+        assert!(cl.env.is_empty());
+
+        let cl = CommandLine::from_string("A=1 _B2='x y' make -j4 C=3")?;
+        assert_eq!(cl.program, "make");
+        assert_eq!(cl.args, ["-j4", "C=3"]);
+        assert_eq!(
+            cl.env,
+            [("A".into(), "1".into()), ("_B2".into(), "x y".into())]
+        );
+
+        // Not a valid variable name, so treated as the program.
+        let cl = CommandLine::from_string("1A=b")?;
+        assert_eq!(cl.program, "1A=b");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_string_errors() {
+        assert!(CommandLine::from_string("echo 'unbalanced").is_err());
+        assert!(CommandLine::from_string("").is_err());
+        assert!(CommandLine::from_string("A=1").is_err());
     }
 }
@@ -1,41 +1,117 @@
 use crate::LineHeight;
-use crate::action::Action;
+use crate::action::{Action, Boundary, Direction, Move};
 use crate::buffer::Buffer;
 use crate::command_line::CommandLine;
 use crate::key_map::KeyMap;
 use crate::pane_tree::{Pane, Rect};
 use crate::widget::Widget;
 use anyhow::Result;
+use std::path::{Path, PathBuf};
 
 // TODO
 pub struct CommandLineWidget {
     buffer: Buffer,
     pane: Pane,
     rect: Rect,
+
+    /// Directory that the command will run in.
+    directory: PathBuf,
+
+    /// Commands previously run in `directory`, most recent first.
+    history: Vec<String>,
+
+    /// Index into `history` of the entry currently shown. `None` if
+    /// the user's own input is shown.
+    history_index: Option<usize>,
+
+    /// The user's own input, saved while browsing the history so
+    /// that it can be restored.
+    saved_input: String,
 }
 
 impl CommandLineWidget {
-    pub fn new() -> Self {
+    pub fn new(directory: PathBuf, history: Vec<String>) -> Self {
         let mut buffer = Buffer::create_empty();
         let pane = Pane::create_for_widget(&mut buffer);
         Self {
             buffer,
             pane,
             rect: Rect::default(),
+            directory,
+            history,
+            history_index: None,
+            saved_input: String::new(),
         }
     }
 
-    pub fn command_line(&self) -> CommandLine {
-        CommandLine::from_string(&self.buffer.text().to_string())
+    pub fn text(&self) -> String {
+        self.buffer.text().to_string()
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn command_line(&self) -> Result<CommandLine> {
+        let mut command_line = CommandLine::from_string(&self.text())?;
+        command_line.set_current_dir(Some(self.directory.clone()));
+        Ok(command_line)
+    }
+
+    /// Replace the input with the next older history entry.
+    pub fn history_previous(&mut self) {
+        let index = match self.history_index {
+            Some(index) => index + 1,
+            None => 0,
+        };
+        if index >= self.history.len() {
+            return;
+        }
+
+        if self.history_index.is_none() {
+            self.saved_input = self.text();
+        }
+        self.history_index = Some(index);
+        self.set_text(&self.history[index].clone());
+    }
+
+    /// Replace the input with the next newer history entry, or with
+    /// the user's own input if already at the newest entry.
+    pub fn history_next(&mut self) {
+        match self.history_index {
+            None => {}
+            Some(0) => {
+                self.history_index = None;
+                self.set_text(&self.saved_input.clone());
+            }
+            Some(index) => {
+                self.history_index = Some(index - 1);
+                self.set_text(&self.history[index - 1].clone());
+            }
+        }
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.buffer.set_text(text);
+        self.buffer.move_cursor(
+            self.pane.id(),
+            Move::Boundary(Boundary::BufferEnd),
+            Direction::Inc,
+        );
     }
 }
 
 impl Widget for CommandLineWidget {
     fn get_keymap(&self) -> Result<KeyMap> {
         KeyMap::from_pairs(
-            "search",
-            vec![("<ret>", Action::Confirm), ("<ctrl>m", Action::Confirm)]
-                .into_iter(),
+            "command_line",
+            vec![
+                ("<ret>", Action::Confirm),
+                ("<ctrl>m", Action::Confirm),
+                ("<alt>p", Action::PreviousHistoryItem),
+                ("<alt>n", Action::NextHistoryItem),
+            ]
+            .into_iter(),
         )
     }
 
@@ -78,3 +154,35 @@ impl Widget for CommandLineWidget {
         &self.rect
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut widget = CommandLineWidget::new(
+            PathBuf::from("/"),
+            vec!["newer".into(), "older".into()],
+        );
+        widget.set_text("input");
+
+        widget.history_previous();
+        assert_eq!(widget.text(), "newer");
+        widget.history_previous();
+        assert_eq!(widget.text(), "older");
+
+        // Already at the oldest entry.
+        widget.history_previous();
+        assert_eq!(widget.text(), "older");
+
+        widget.history_next();
+        assert_eq!(widget.text(), "newer");
+
+        // Back to the user's input.
+        widget.history_next();
+        assert_eq!(widget.text(), "input");
+        widget.history_next();
+        assert_eq!(widget.text(), "input");
+    }
+}
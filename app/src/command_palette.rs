@@ -0,0 +1,459 @@
+//! Popup listing every action by name, with its key binding if it
+//! has one, so that actions can be found and run without knowing
+//! their keys.
+//!
+//! Actions that take a parameter are listed once per value, e.g.
+//! splitting side by side and splitting top and bottom. Actions that
+//! are only sent internally, or that only make sense inside another
+//! popup, aren't listed.
+
+use crate::action::{Action, Boundary, Direction, FilterOutput, Move};
+use crate::buffer::Buffer;
+use crate::candidate_list::{self, CandidateList};
+use crate::key_map::{KeyMap, KeyMapStack};
+use crate::modal::{Mode, Operator};
+use crate::pane_tree::{Orientation, Pane, Rect};
+use crate::widget::Widget;
+use crate::{LineHeight, fuzzy};
+use anyhow::Result;
+
+/// Maximum number of commands shown at once.
+pub const MAX_VISIBLE_COMMANDS: usize = 10;
+
+const DIRECTIONS: [Direction; 2] = [Direction::Dec, Direction::Inc];
+const ORIENTATIONS: [Orientation; 2] =
+    [Orientation::Horizontal, Orientation::Vertical];
+const BOUNDARIES: [Boundary; 3] =
+    [Boundary::Grapheme, Boundary::LineEnd, Boundary::BufferEnd];
+
+/// Get every action the palette lists.
+fn palette_actions() -> Vec<Action> {
+    let mut actions = vec![
+        Action::InsertLineAfter,
+        Action::Exit,
+        Action::OpenFile,
+        Action::SaveFile,
+        Action::PreviousPane,
+        Action::NextPane,
+        Action::ClosePane,
+        Action::CloseOtherPanes,
+        Action::BalancePanes,
+        Action::RotateSplit,
+        Action::OpenShell,
+        Action::InteractiveSearch,
+        Action::SearchNext,
+        Action::Undo,
+        Action::Redo,
+        Action::DeleteBuffer,
+        Action::Recenter,
+        Action::ToggleWrap,
+        Action::UniversalArgument,
+        Action::RepeatLastAction,
+        Action::StartMacro,
+        Action::EndMacro,
+        Action::ReplayMacro,
+        Action::SaveMacro,
+        Action::RunSavedMacro,
+        Action::SetMark,
+        Action::FilterRegion(FilterOutput::Replace),
+        Action::FilterRegion(FilterOutput::NewBuffer),
+        Action::SwitchToBuffer,
+        Action::FindFileInProject,
+        Action::RefreshDirectory,
+        Action::CreateDirEntry,
+        Action::RenameDirEntry,
+        Action::DeleteDirEntry,
+        Action::Complete,
+        Action::GoToDefinition,
+        Action::ShowHover,
+        Action::RunNonInteractiveProcess,
+        Action::RerunProcess,
+        Action::Operator(Operator::Delete),
+        Action::Operator(Operator::Change),
+    ];
+    for orientation in ORIENTATIONS {
+        actions.push(Action::SplitPane(orientation));
+        for dir in DIRECTIONS {
+            actions.extend([
+                Action::ResizePane(orientation, dir),
+                Action::FocusPane(orientation, dir),
+                Action::SwapPane(orientation, dir),
+                Action::MovePane(orientation, dir),
+            ]);
+        }
+    }
+    for dir in DIRECTIONS {
+        for boundary in BOUNDARIES {
+            actions.push(Action::Move(Move::Boundary(boundary), dir));
+            actions.push(Action::Delete(boundary, dir));
+        }
+        actions.push(Action::Move(Move::Line, dir));
+        actions.push(Action::Move(Move::Page, dir));
+        actions.push(Action::Scroll(dir));
+    }
+    for mode in [Mode::Normal, Mode::Insert, Mode::Visual] {
+        actions.push(Action::SetMode(mode));
+    }
+    actions
+}
+
+/// Split a variant name such as "OpenFile" into lowercase words.
+fn words(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() && !out.is_empty() {
+            out.push(' ');
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+/// Get the name of a pane's neighbor in a direction.
+fn side(orientation: Orientation, dir: Direction) -> &'static str {
+    match (orientation, dir) {
+        (Orientation::Horizontal, Direction::Dec) => "left",
+        (Orientation::Horizontal, Direction::Inc) => "right",
+        (Orientation::Vertical, Direction::Dec) => "up",
+        (Orientation::Vertical, Direction::Inc) => "down",
+    }
+}
+
+fn boundary_target(boundary: Boundary, dir: Direction) -> &'static str {
+    match (boundary, dir) {
+        (Boundary::Grapheme, Direction::Dec) => "previous character",
+        (Boundary::Grapheme, Direction::Inc) => "next character",
+        (Boundary::LineEnd, Direction::Dec) => "line start",
+        (Boundary::LineEnd, Direction::Inc) => "line end",
+        (Boundary::BufferEnd, Direction::Dec) => "buffer start",
+        (Boundary::BufferEnd, Direction::Inc) => "buffer end",
+    }
+}
+
+/// Get a human readable name for `action`.
+pub fn action_name(action: &Action) -> String {
+    let name = match action {
+        Action::SplitPane(Orientation::Horizontal) => {
+            "Split pane side by side".to_owned()
+        }
+        Action::SplitPane(Orientation::Vertical) => {
+            "Split pane top and bottom".to_owned()
+        }
+        Action::ResizePane(orientation, dir) => {
+            let how = match (orientation, dir) {
+                (Orientation::Horizontal, Direction::Dec) => "narrower",
+                (Orientation::Horizontal, Direction::Inc) => "wider",
+                (Orientation::Vertical, Direction::Dec) => "shorter",
+                (Orientation::Vertical, Direction::Inc) => "taller",
+            };
+            format!("Make pane {how}")
+        }
+        Action::FocusPane(orientation, dir) => {
+            format!("Focus pane {}", side(*orientation, *dir))
+        }
+        Action::SwapPane(orientation, dir) => {
+            format!("Swap pane {}", side(*orientation, *dir))
+        }
+        Action::MovePane(orientation, dir) => {
+            format!("Move pane {}", side(*orientation, *dir))
+        }
+        Action::Move(Move::Boundary(boundary), dir) => {
+            format!("Move to {}", boundary_target(*boundary, *dir))
+        }
+        Action::Move(Move::Line, Direction::Dec) => "Previous line".to_owned(),
+        Action::Move(Move::Line, Direction::Inc) => "Next line".to_owned(),
+        Action::Move(Move::Page, Direction::Dec) => "Page up".to_owned(),
+        Action::Move(Move::Page, Direction::Inc) => "Page down".to_owned(),
+        Action::Scroll(Direction::Dec) => "Scroll up".to_owned(),
+        Action::Scroll(Direction::Inc) => "Scroll down".to_owned(),
+        Action::Delete(Boundary::Grapheme, dir) => {
+            format!("Delete {}", boundary_target(Boundary::Grapheme, *dir))
+        }
+        Action::Delete(boundary, dir) => {
+            format!("Delete to {}", boundary_target(*boundary, *dir))
+        }
+        Action::FilterRegion(FilterOutput::Replace) => {
+            "Filter region".to_owned()
+        }
+        Action::FilterRegion(FilterOutput::NewBuffer) => {
+            "Filter region to new buffer".to_owned()
+        }
+        Action::SetMode(mode) => {
+            format!("Switch to {} mode", words(&format!("{mode:?}")))
+        }
+        Action::Operator(operator) => {
+            format!("{operator:?} operator")
+        }
+        action => {
+            let debug = format!("{action:?}");
+            let variant = debug.split('(').next().unwrap_or_default();
+            words(variant)
+        }
+    };
+
+    // Sentence case.
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+pub struct CommandEntry {
+    pub action: Action,
+    pub name: String,
+
+    /// Keys bound to the action, if any.
+    pub keys: Option<String>,
+}
+
+pub struct CommandPalette {
+    buffer: Buffer,
+    pane: Pane,
+    rect: Rect,
+
+    /// Count to run the chosen action with.
+    count: usize,
+
+    candidates: CandidateList<CommandEntry>,
+}
+
+impl CommandPalette {
+    /// Create a palette showing the bindings in `keymap_stack`.
+    pub fn new(keymap_stack: &KeyMapStack, count: usize) -> Self {
+        let mut buffer = Buffer::create_empty();
+        let pane = Pane::create_for_widget(&mut buffer);
+
+        let mut entries: Vec<CommandEntry> = palette_actions()
+            .into_iter()
+            .map(|action| CommandEntry {
+                name: action_name(&action),
+                keys: keymap_stack.keys_for(&action).map(|k| k.to_string()),
+                action,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            buffer,
+            pane,
+            rect: Rect::default(),
+            count,
+            candidates: CandidateList::new(entries),
+        }
+    }
+
+    pub fn candidates(&self) -> &CandidateList<CommandEntry> {
+        &self.candidates
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn selected_action(&self) -> Option<&Action> {
+        self.candidates.selected().map(|entry| &entry.action)
+    }
+
+    /// Filter the commands by the text typed in the palette. The best
+    /// match gets selected.
+    pub fn update_matches(&mut self) {
+        let query = self.buffer.text().to_string();
+        self.candidates
+            .update_matches(|entry| fuzzy::score(&query, &entry.name));
+        self.candidates.select_first();
+    }
+
+    pub fn select(&mut self, dir: Direction) {
+        self.candidates.select(dir);
+    }
+}
+
+impl Widget for CommandPalette {
+    fn get_keymap(&self) -> Result<KeyMap> {
+        candidate_list::popup_keymap("command_palette", Vec::new())
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_buffer_mut(&mut self) -> (&Pane, &mut Buffer) {
+        (&self.pane, &mut self.buffer)
+    }
+
+    fn pane_mut_buffer_mut(&mut self) -> (&mut Pane, &mut Buffer) {
+        (&mut self.pane, &mut self.buffer)
+    }
+
+    fn recalc_layout(&mut self, width: f64, line_height: LineHeight) {
+        // Prompt, input line, then one line per visible command.
+        let num_rows = self.candidates.len().min(MAX_VISIBLE_COMMANDS);
+        self.rect = candidate_list::popup_layout(
+            &mut self.pane,
+            width,
+            line_height,
+            num_rows,
+        );
+    }
+
+    fn rect(&self) -> &Rect {
+        &self.rect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::value::Error;
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use std::collections::HashSet;
+
+    /// Deserializer that only records the variant names of the enum
+    /// it's asked for.
+    #[derive(Default)]
+    struct VariantNames(&'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for &mut VariantNames {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(
+            self,
+            _visitor: V,
+        ) -> Result<V::Value, Error> {
+            Err(de::Error::custom("not an enum"))
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            variants: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.0 = variants;
+            Err(de::Error::custom("only getting the variants"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str
+            string bytes byte_buf option unit unit_struct newtype_struct
+            seq tuple tuple_struct map struct identifier ignored_any
+        }
+    }
+
+    /// Test that every action is either listed or deliberately left
+    /// out, so that new actions aren't forgotten.
+    #[test]
+    fn test_palette_covers_actions() {
+        // Actions that are only sent internally, that take input the
+        // palette can't give, or that only make sense in a popup.
+        let unlisted = HashSet::from([
+            "Insert",
+            "CountDigit",
+            "RunMacro",
+            "OpenCommandPalette",
+            "FoundFiles",
+            "ShowKeyHints",
+            "Confirm",
+            "Cancel",
+            "Autocomplete",
+            "SelectCandidate",
+            "PreviousHistoryItem",
+            "NextHistoryItem",
+            "AppendToBuffer",
+            "ProcessFinished",
+            "Lsp",
+        ]);
+
+        let mut variants = VariantNames::default();
+        assert!(Action::deserialize(&mut variants).is_err());
+        let listed: HashSet<String> = palette_actions()
+            .iter()
+            .map(|action| {
+                let debug = format!("{action:?}");
+                debug.split('(').next().unwrap_or_default().to_owned()
+            })
+            .collect();
+
+        for variant in variants.0 {
+            assert!(
+                listed.contains(*variant) != unlisted.contains(variant),
+                "{variant} must be either listed or unlisted"
+            );
+        }
+        assert_eq!(listed.len() + unlisted.len(), variants.0.len());
+    }
+
+    #[test]
+    fn test_action_names() {
+        let actions = palette_actions();
+        let names: HashSet<String> = actions.iter().map(action_name).collect();
+        assert_eq!(names.len(), actions.len(), "names must be unique");
+
+        let name = |action| action_name(&action);
+        assert_eq!(name(Action::OpenFile), "Open file");
+        assert_eq!(
+            name(Action::SplitPane(Orientation::Horizontal)),
+            "Split pane side by side"
+        );
+        assert_eq!(
+            name(Action::FocusPane(Orientation::Vertical, Direction::Dec)),
+            "Focus pane up"
+        );
+        assert_eq!(
+            name(Action::Move(
+                Move::Boundary(Boundary::LineEnd),
+                Direction::Inc
+            )),
+            "Move to line end"
+        );
+        assert_eq!(
+            name(Action::Delete(Boundary::Grapheme, Direction::Dec)),
+            "Delete previous character"
+        );
+        assert_eq!(
+            name(Action::SetMode(Mode::Visual)),
+            "Switch to visual mode"
+        );
+    }
+
+    #[test]
+    fn test_command_palette() -> Result<()> {
+        let mut stack = KeyMapStack::default();
+        stack.push(KeyMap::base());
+        let mut palette = CommandPalette::new(&stack, 1);
+
+        let entry = palette
+            .candidates()
+            .matches()
+            .find(|entry| entry.action == Action::SaveFile)
+            .unwrap();
+        assert_eq!(entry.keys.as_deref(), Some("<ctrl>x+<ctrl>s"));
+        let entry = palette
+            .candidates()
+            .matches()
+            .find(|entry| entry.action == Action::RotateSplit)
+            .unwrap();
+        assert_eq!(entry.keys.as_deref(), Some("<ctrl>x+r"));
+
+        palette.buffer_mut().set_text("split side");
+        palette.update_matches();
+        assert_eq!(
+            palette.selected_action(),
+            Some(&Action::SplitPane(Orientation::Horizontal))
+        );
+
+        palette.buffer_mut().set_text("zzz");
+        palette.update_matches();
+        assert_eq!(palette.selected_action(), None);
+        Ok(())
+    }
+}
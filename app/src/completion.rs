@@ -0,0 +1,552 @@
+//! Completion of the text before the cursor.
+//!
+//! Candidates come from a list of [`CompletionSource`]s, plus the
+//! buffer's language server if it has one. They are shown in a popup
+//! at the cursor and filtered as the user types.
+
+use crate::action::{Action, Direction};
+use crate::buffer::{AbsChar, Buffer, BufferId};
+use crate::candidate_list::{self, CandidateList};
+use crate::key_map::KeyMap;
+use crate::pane_tree::{Pane, PaneId, Rect};
+use crate::rope::Rope;
+use crate::widget::Widget;
+use crate::{LineHeight, fuzzy};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of words the word source returns.
+const MAX_WORDS: usize = 10_000;
+
+/// Maximum number of directory entries the path source returns.
+const MAX_PATHS: usize = 1000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompletionCandidate {
+    /// Text shown in the popup.
+    pub label: String,
+
+    /// Text that replaces the text between `start` and the cursor.
+    pub text: String,
+
+    pub start: AbsChar,
+
+    /// Name of the source of the candidate, shown in the popup.
+    pub source: &'static str,
+}
+
+/// What is being completed.
+pub struct CompletionContext<'a> {
+    pub buffers: &'a HashMap<BufferId, Buffer>,
+    pub buffer: &'a Buffer,
+    pub cursor: AbsChar,
+}
+
+pub trait CompletionSource {
+    fn candidates(&self, ctx: &CompletionContext) -> Vec<CompletionCandidate>;
+}
+
+/// The sources used when nothing else is configured.
+pub fn default_sources() -> Vec<Box<dyn CompletionSource>> {
+    vec![Box::new(WordSource), Box::new(PathSource)]
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Get the start of the word that ends at `pos`.
+pub(crate) fn word_start(text: &Rope, pos: AbsChar) -> AbsChar {
+    let line_start = AbsChar(text.line_to_char(text.char_to_line(pos)));
+    let prefix = text.slice(line_start..pos).to_string();
+    let word_len = prefix
+        .chars()
+        .rev()
+        .take_while(|c| is_word_char(*c))
+        .count();
+    AbsChar(pos.0 - word_len)
+}
+
+/// Call `f` with each word in `text` and the char index it starts at.
+fn for_each_word(text: &str, mut f: impl FnMut(&str, usize)) {
+    let mut word_start = None;
+    for (char_index, (byte_index, c)) in text.char_indices().enumerate() {
+        match (word_start, is_word_char(c)) {
+            (None, true) => word_start = Some((byte_index, char_index)),
+            (Some((start, start_char)), false) => {
+                f(&text[start..byte_index], start_char);
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((start, start_char)) = word_start {
+        f(&text[start..], start_char);
+    }
+}
+
+/// Words in the open buffers. Words in the buffer being completed come
+/// first, closest to the cursor first.
+pub struct WordSource;
+
+impl CompletionSource for WordSource {
+    fn candidates(&self, ctx: &CompletionContext) -> Vec<CompletionCandidate> {
+        let start = word_start(ctx.buffer.text(), ctx.cursor);
+
+        let mut distances: HashMap<String, usize> = HashMap::new();
+        for_each_word(&ctx.buffer.text().to_string(), |word, index| {
+            // Skip the word being completed.
+            if index == start.0 {
+                return;
+            }
+            let distance = index.abs_diff(ctx.cursor.0);
+            distances
+                .entry(word.to_owned())
+                .and_modify(|d| *d = (*d).min(distance))
+                .or_insert(distance);
+        });
+        let mut words: Vec<(String, usize)> = distances.into_iter().collect();
+        words.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        let mut words: Vec<String> =
+            words.into_iter().map(|(word, _)| word).collect();
+
+        let mut other_words = Vec::new();
+        for buf in ctx.buffers.values() {
+            if buf.id() != ctx.buffer.id() {
+                for_each_word(&buf.text().to_string(), |word, _| {
+                    other_words.push(word.to_owned());
+                });
+            }
+        }
+        other_words.sort();
+        words.extend(other_words);
+
+        let mut seen = std::collections::HashSet::new();
+        words
+            .into_iter()
+            .filter(|word| {
+                word.chars().count() > 1 && seen.insert(word.clone())
+            })
+            .take(MAX_WORDS)
+            .map(|word| CompletionCandidate {
+                label: word.clone(),
+                text: word,
+                start,
+                source: "word",
+            })
+            .collect()
+    }
+}
+
+/// Entries of the directory named by the path before the cursor. The
+/// text before the cursor is treated as a path if it contains a "/".
+/// Relative paths are relative to the buffer's directory.
+pub struct PathSource;
+
+impl PathSource {
+    fn resolve_dir(dir: &str, buffer: &Buffer) -> PathBuf {
+        if let Some(rest) = dir.strip_prefix("~/")
+            && let Some(home) = dirs::home_dir()
+        {
+            return home.join(rest);
+        }
+        let dir = Path::new(dir);
+        if dir.is_absolute() {
+            return dir.to_owned();
+        }
+        buffer
+            .directory()
+            .map(|d| d.to_owned())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default()
+            .join(dir)
+    }
+}
+
+impl CompletionSource for PathSource {
+    fn candidates(&self, ctx: &CompletionContext) -> Vec<CompletionCandidate> {
+        let text = ctx.buffer.text();
+        let line_start =
+            AbsChar(text.line_to_char(text.char_to_line(ctx.cursor)));
+        let before = text.slice(line_start..ctx.cursor).to_string();
+        let prefix_len = before
+            .chars()
+            .rev()
+            .take_while(|c| {
+                !c.is_whitespace() && !"\"'`()[]{}<>,;=".contains(*c)
+            })
+            .count();
+        let prefix: String = before
+            .chars()
+            .skip(before.chars().count() - prefix_len)
+            .collect();
+        let Some(slash) = prefix.rfind('/') else {
+            return Vec::new();
+        };
+        let dir = &prefix[..=slash];
+        let show_hidden = prefix[slash + 1..].starts_with('.');
+
+        let Ok(entries) = std::fs::read_dir(Self::resolve_dir(dir, ctx.buffer))
+        else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let mut name = entry.file_name().to_str()?.to_owned();
+                if name.starts_with('.') && !show_hidden {
+                    return None;
+                }
+                if entry.path().is_dir() {
+                    name.push('/');
+                }
+                Some(name)
+            })
+            .take(MAX_PATHS)
+            .collect();
+        names.sort();
+
+        let start = AbsChar(ctx.cursor.0 - prefix_len);
+        names
+            .into_iter()
+            .map(|name| CompletionCandidate {
+                text: format!("{dir}{name}"),
+                label: name,
+                start,
+                source: "path",
+            })
+            .collect()
+    }
+}
+
+/// Popup listing completions for the text before the cursor in a
+/// pane. Text typed while the popup is open goes into the widget's
+/// own buffer and narrows down the candidates; confirming replaces the
+/// completed text in the pane with the selected candidate. If the
+/// popup closes without completing, the typed text is inserted into
+/// the pane instead.
+pub struct CompletionWidget {
+    buffer: Buffer,
+    pane: Pane,
+    rect: Rect,
+
+    target_buffer_id: BufferId,
+    target_pane_id: PaneId,
+    cursor: AbsChar,
+
+    /// Start of the cursor's line in the target buffer.
+    line_start: AbsChar,
+    /// Text between `line_start` and the cursor.
+    line_prefix: String,
+
+    candidates: CandidateList<CompletionCandidate>,
+}
+
+impl CompletionWidget {
+    pub fn new(
+        target_pane: &Pane,
+        target_buffer: &Buffer,
+        candidates: Vec<CompletionCandidate>,
+    ) -> Self {
+        let mut buffer = Buffer::create_empty();
+        let pane = Pane::create_for_widget(&mut buffer);
+
+        let text = target_buffer.text();
+        let cursor = target_buffer.cursor(target_pane.id());
+        let line_start = AbsChar(text.line_to_char(text.char_to_line(cursor)));
+
+        let mut widget = Self {
+            buffer,
+            pane,
+            rect: Rect::default(),
+            target_buffer_id: target_buffer.id().clone(),
+            target_pane_id: target_pane.id().clone(),
+            cursor,
+            line_start,
+            line_prefix: text.slice(line_start..cursor).to_string(),
+            candidates: CandidateList::new(Vec::new()),
+        };
+        widget.add_candidates(candidates);
+        widget
+    }
+
+    pub fn target_buffer_id(&self) -> &BufferId {
+        &self.target_buffer_id
+    }
+
+    pub fn target_pane_id(&self) -> &PaneId {
+        &self.target_pane_id
+    }
+
+    /// Position of the cursor in the target pane when completion
+    /// started.
+    pub fn cursor(&self) -> AbsChar {
+        self.cursor
+    }
+
+    /// Text typed since the popup opened.
+    pub fn query(&self) -> String {
+        self.buffer.text().to_string()
+    }
+
+    pub fn candidates(&self) -> &CandidateList<CompletionCandidate> {
+        &self.candidates
+    }
+
+    /// Get the text that `candidate` is matched against: the text it
+    /// replaces, plus anything typed since the popup opened.
+    fn pattern(&self, candidate: &CompletionCandidate) -> String {
+        let skip = candidate.start.0 - self.line_start.0;
+        let mut pattern: String = self.line_prefix.chars().skip(skip).collect();
+        pattern.push_str(&self.query());
+        pattern
+    }
+
+    /// Add more candidates, e.g. when a language server responds.
+    /// Candidates that don't start on the cursor's line are dropped. A
+    /// candidate with the same text and start as an existing one
+    /// replaces it, so later sources take precedence.
+    pub fn add_candidates(&mut self, candidates: Vec<CompletionCandidate>) {
+        let existing = self.candidates.candidates_mut();
+        for candidate in candidates {
+            if candidate.start < self.line_start
+                || candidate.start > self.cursor
+            {
+                continue;
+            }
+            if let Some(c) = existing.iter_mut().find(|c| {
+                c.start == candidate.start && c.text == candidate.text
+            }) {
+                *c = candidate;
+            } else {
+                existing.push(candidate);
+            }
+        }
+        self.update_matches();
+    }
+
+    pub fn update_matches(&mut self) {
+        let patterns: HashMap<AbsChar, String> = self
+            .candidates
+            .candidates()
+            .iter()
+            .map(|c| (c.start, self.pattern(c)))
+            .collect();
+        self.candidates
+            .update_matches(|c| fuzzy::score(&patterns[&c.start], &c.text));
+    }
+
+    pub fn select(&mut self, dir: Direction) {
+        self.candidates.select(dir);
+    }
+
+    /// Insert the selected candidate into `target`. Returns false if
+    /// nothing is selected or the target cursor has moved.
+    pub fn apply(&self, target: &mut Buffer) -> bool {
+        let Some(candidate) = self.candidates.selected() else {
+            return false;
+        };
+        if target.cursor(&self.target_pane_id) != self.cursor {
+            return false;
+        }
+        target.replace_text(candidate.start..self.cursor, &candidate.text);
+        true
+    }
+
+    /// Insert the text typed since the popup opened at the cursor in
+    /// `target`, for when the popup closes without completing.
+    pub fn restore_query(&self, target: &mut Buffer) {
+        let query = self.query();
+        if query.is_empty() {
+            return;
+        }
+        let cursor = target.cursor(&self.target_pane_id);
+        target.replace_text(cursor..cursor, &query);
+    }
+}
+
+impl Widget for CompletionWidget {
+    fn get_keymap(&self) -> Result<KeyMap> {
+        candidate_list::popup_keymap(
+            "completion",
+            vec![("<ctrl>i", Action::Confirm)],
+        )
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_buffer_mut(&mut self) -> (&Pane, &mut Buffer) {
+        (&self.pane, &mut self.buffer)
+    }
+
+    fn pane_mut_buffer_mut(&mut self) -> (&mut Pane, &mut Buffer) {
+        (&mut self.pane, &mut self.buffer)
+    }
+
+    fn recalc_layout(&mut self, _width: f64, _line_height: LineHeight) {
+        // The popup is drawn at the target cursor, which only the
+        // shell knows the location of.
+    }
+
+    fn rect(&self) -> &Rect {
+        &self.rect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn context_at_end<'a>(
+        buffers: &'a HashMap<BufferId, Buffer>,
+        buffer: &'a Buffer,
+    ) -> CompletionContext<'a> {
+        CompletionContext {
+            buffers,
+            buffer,
+            cursor: AbsChar(buffer.text().len_chars()),
+        }
+    }
+
+    fn texts(candidates: &[CompletionCandidate]) -> Vec<&str> {
+        candidates.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_word_start() {
+        let text = Rope::from_str("a foo_b1");
+        assert_eq!(word_start(&text, AbsChar(8)), AbsChar(2));
+        assert_eq!(word_start(&text, AbsChar(2)), AbsChar(2));
+        assert_eq!(word_start(&text, AbsChar(1)), AbsChar(0));
+    }
+
+    #[test]
+    fn test_word_source() {
+        let mut other = Buffer::create_empty();
+        other.set_text("zebra alpha");
+        let mut buf = Buffer::create_empty();
+        buf.set_text("hello world x hello\nhel");
+        let buffers = HashMap::from([(other.id().clone(), other)]);
+
+        let candidates = WordSource.candidates(&context_at_end(&buffers, &buf));
+        // Nearest first, then other buffers. Single chars and the word
+        // being completed are skipped.
+        assert_eq!(texts(&candidates), ["hello", "world", "alpha", "zebra"]);
+        assert!(candidates.iter().all(|c| c.start == AbsChar(20)));
+    }
+
+    #[test]
+    fn test_path_source() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let tmp_dir = tmp_dir.path();
+        fs::create_dir(tmp_dir.join("subdir"))?;
+        fs::write(tmp_dir.join("file"), "")?;
+        fs::write(tmp_dir.join(".hidden"), "")?;
+
+        let buffers = HashMap::new();
+        let mut buf = Buffer::create_empty();
+        buf.set_text(&format!("x=\"{}/fi", tmp_dir.display()));
+        let candidates = PathSource.candidates(&context_at_end(&buffers, &buf));
+        let dir = format!("{}/", tmp_dir.display());
+        assert_eq!(
+            texts(&candidates),
+            [format!("{dir}file"), format!("{dir}subdir/")]
+        );
+        assert_eq!(candidates[1].label, "subdir/");
+        assert_eq!(candidates[0].start, AbsChar(3));
+
+        buf.set_text(&format!("{dir}."));
+        let candidates = PathSource.candidates(&context_at_end(&buffers, &buf));
+        assert_eq!(
+            texts(&candidates),
+            [
+                format!("{dir}.hidden"),
+                format!("{dir}file"),
+                format!("{dir}subdir/")
+            ]
+        );
+
+        // Not a path.
+        buf.set_text("file");
+        assert!(
+            PathSource
+                .candidates(&context_at_end(&buffers, &buf))
+                .is_empty()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_completion_widget() {
+        let mut target = Buffer::create_empty();
+        target.set_text("a\nx fo");
+        let target_pane = Pane::create_for_widget(&mut target);
+
+        let candidate = |text: &str, start, source| CompletionCandidate {
+            label: text.to_owned(),
+            text: text.to_owned(),
+            start: AbsChar(start),
+            source,
+        };
+        let mut widget = CompletionWidget::new(
+            &target_pane,
+            &target,
+            vec![
+                candidate("foo", 4, "word"),
+                candidate("fox", 4, "word"),
+                candidate("x foo", 2, "word"),
+                // Not on the cursor's line.
+                candidate("a", 0, "word"),
+            ],
+        );
+        let matches = |widget: &CompletionWidget| {
+            widget
+                .candidates()
+                .matches()
+                .map(|c| (c.text.clone(), c.source))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            matches(&widget),
+            [
+                ("x foo".into(), "word"),
+                ("foo".into(), "word"),
+                ("fox".into(), "word")
+            ]
+        );
+
+        // Later candidates replace earlier ones.
+        widget.add_candidates(vec![candidate("foo", 4, "lsp")]);
+        assert_eq!(widget.candidates().len(), 3);
+        assert!(matches(&widget).contains(&("foo".into(), "lsp")));
+
+        // Typing narrows down the matches.
+        widget.buffer_mut().set_text("z");
+        widget.update_matches();
+        assert!(widget.candidates().is_empty());
+        assert!(!widget.apply(&mut target));
+        widget.buffer_mut().set_text("x");
+        widget.update_matches();
+        assert_eq!(matches(&widget), [("fox".into(), "word")]);
+
+        assert!(widget.apply(&mut target));
+        assert_eq!(target.text().to_string(), "a\nx fox");
+        assert_eq!(target.cursor(target_pane.id()), AbsChar(7));
+
+        // The cursor has moved since the widget was created.
+        assert!(!widget.apply(&mut target));
+    }
+}
@@ -1,18 +1,145 @@
+use crate::action::Action;
+use crate::key_map::KeyMap;
 use anyhow::{Result, anyhow};
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn default_font_size() -> f64 {
     12.0
 }
 
+/// A command used to format files when they are saved.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Formatter {
+    /// Glob pattern matched against the file name, e.g. "*.rs". If the
+    /// pattern contains a "/" it is matched against the full path
+    /// instead.
+    #[serde(default)]
+    pub glob: Option<String>,
+
+    /// Syntax name, e.g. "Rust".
+    #[serde(default)]
+    pub syntax: Option<String>,
+
+    /// Command that reads the file contents from stdin and writes the
+    /// formatted contents to stdout, e.g. "rustfmt --emit stdout".
+    pub command: String,
+}
+
+impl Formatter {
+    fn matches(&self, path: &Path, syntax_name: &str) -> bool {
+        if let Some(syntax) = &self.syntax
+            && syntax == syntax_name
+        {
+            return true;
+        }
+
+        if let Some(glob) = &self.glob {
+            let Ok(pattern) = glob::Pattern::new(glob) else {
+                return false;
+            };
+            if glob.contains('/') {
+                return pattern.matches_path(path);
+            }
+            if let Some(name) = path.file_name() {
+                return pattern.matches(&name.to_string_lossy());
+            }
+        }
+
+        false
+    }
+}
+
+/// A language server to run for files of a particular syntax.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct LanguageServerConfig {
+    /// Syntax name, e.g. "Rust".
+    pub syntax: String,
+
+    /// Command that starts the server, e.g. "rust-analyzer".
+    pub command: String,
+
+    /// Language identifier sent to the server, e.g. "rust". If not
+    /// set, this is derived from the syntax name.
+    #[serde(default)]
+    pub language_id: Option<String>,
+}
+
+impl LanguageServerConfig {
+    pub fn language_id(&self) -> String {
+        self.language_id
+            .clone()
+            .unwrap_or_else(|| self.syntax.to_lowercase().replace(' ', ""))
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
     #[serde(default = "default_font_size")]
     pub font_size: f64,
+
+    /// Commands used to format files on save. The first matching
+    /// entry is used.
+    #[serde(default)]
+    pub formatters: Vec<Formatter>,
+
+    /// Language servers that provide diagnostics, completion, etc.
+    #[serde(default)]
+    pub language_servers: Vec<LanguageServerConfig>,
+
+    /// Key bindings that take priority over the built-in ones. Each
+    /// key is a key sequence such as "<ctrl>x+<ctrl>f", and each value
+    /// is an action name such as `OpenFile`. Actions with arguments
+    /// are written with a tag, e.g. `!FocusPane [Horizontal, Dec]`.
+    #[serde(default)]
+    pub keymap: Mapping,
+
+    /// Use vi-like modal editing instead of only the Emacs-style
+    /// bindings.
+    #[serde(default)]
+    pub modal_editing: bool,
+
+    /// Text the config was parsed from, used to point at the line of
+    /// an invalid key binding.
+    #[serde(skip)]
+    source: String,
+}
+
+/// Get the line number (starting at one) of the binding for `keys`
+/// in the keymap section of `source`.
+fn keymap_line(source: &str, keys: &str) -> Option<usize> {
+    source
+        .lines()
+        .enumerate()
+        .skip_while(|(_, line)| !line.starts_with("keymap:"))
+        .find(|(_, line)| mapping_key(line) == Some(keys))
+        .map(|(index, _)| index + 1)
+}
+
+/// Get the key of a YAML mapping entry written on a single line, with
+/// any quotes removed.
+fn mapping_key(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    for quote in ['"', '\''] {
+        if let Some(rest) = line.strip_prefix(quote) {
+            return rest.split_once(quote).map(|(key, _)| key);
+        }
+    }
+    line.split_once(':').map(|(key, _)| key.trim_end())
+}
+
+/// Get the directory holding the config file and other user data
+/// such as saved macros.
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| anyhow!("config dir unknown"))?
+        .join("emma"))
 }
 
 impl Default for Config {
@@ -24,11 +151,56 @@ impl Default for Config {
 
 impl Config {
     pub fn load() -> Result<Self> {
-        let dir = dirs::config_dir()
-            .ok_or_else(|| anyhow!("config dir unknown"))?
-            .join("emma");
+        Self::load_from_dir(&config_dir()?)
+    }
 
-        Self::load_from_dir(&dir)
+    /// Get the formatter for a file with the given path and syntax
+    /// name, if any.
+    pub fn formatter_for(
+        &self,
+        path: &Path,
+        syntax_name: &str,
+    ) -> Option<&Formatter> {
+        self.formatters
+            .iter()
+            .find(|f| f.matches(path, syntax_name))
+    }
+
+    /// Build a keymap from the user's key bindings. Bindings that
+    /// can't be parsed are left out, and an error describing each of
+    /// them is returned instead.
+    pub fn user_keymap(&self) -> (KeyMap, Vec<String>) {
+        let mut keymap = KeyMap::new("user");
+        let mut errors = Vec::new();
+        for (keys, action) in &self.keymap {
+            let Value::String(keys) = keys else {
+                errors.push(format!(
+                    "key sequence is not a string: {}",
+                    serde_yaml::to_string(keys).unwrap_or_default().trim()
+                ));
+                continue;
+            };
+            let result = serde_yaml::from_value::<Action>(action.clone())
+                .map_err(anyhow::Error::from)
+                .and_then(|action| keymap.parse_and_insert(keys, action));
+            if let Err(err) = result {
+                let location = match keymap_line(&self.source, keys) {
+                    Some(line) => format!("line {line}: "),
+                    None => String::new(),
+                };
+                errors.push(format!(
+                    "{location}invalid binding for {keys:?}: {err}"
+                ));
+            }
+        }
+        (keymap, errors)
+    }
+
+    /// Parse a config from YAML.
+    pub(crate) fn from_yaml(raw: &str) -> Result<Self> {
+        let mut config: Self = serde_yaml::from_str(raw)?;
+        config.source = raw.to_owned();
+        Ok(config)
     }
 
     fn load_from_dir(dir: &Path) -> Result<Self> {
@@ -52,13 +224,17 @@ impl Config {
 
         // Read and parse the config.
         let raw = fs::read_to_string(config_path)?;
-        Ok(serde_yaml::from_str(&raw)?)
+        Self::from_yaml(&raw)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::action::Direction;
+    use crate::key_map::KeyMapLookup;
+    use crate::key_sequence::KeySequence;
+    use crate::pane_tree::Orientation;
     use tempfile::TempDir;
 
     #[test]
@@ -71,6 +247,81 @@ mod tests {
         assert_eq!(config.font_size, 12.0);
     }
 
+    #[test]
+    fn test_formatter_for() -> Result<()> {
+        let config: Config = serde_yaml::from_str(
+            "
+formatters:
+  - syntax: Rust
+    command: rustfmt --emit stdout
+  - glob: '*.json'
+    command: jq .
+  - glob: /tmp/*.txt
+    command: sort
+",
+        )?;
+
+        let command = |path: &str, syntax| {
+            config
+                .formatter_for(Path::new(path), syntax)
+                .map(|f| f.command.as_str())
+        };
+        assert_eq!(command("/a/b.rs", "Rust"), Some("rustfmt --emit stdout"));
+        assert_eq!(command("/a/b.json", "JSON"), Some("jq ."));
+        assert_eq!(command("/tmp/b.txt", "Plain Text"), Some("sort"));
+        assert_eq!(command("/a/b.txt", "Plain Text"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_keymap() -> Result<()> {
+        let config = Config::from_yaml(
+            "font-size: 10
+keymap:
+  <ctrl>x+f: OpenFile
+  <alt>h: !FocusPane [Horizontal, Dec]
+  <ctl>x: OpenFile
+  <ctrl>y: NotAnAction
+",
+        )?;
+
+        let (keymap, errors) = config.user_keymap();
+        let lookup = |keys| keymap.lookup(&KeySequence::parse(keys).unwrap());
+        assert_eq!(lookup("<ctrl>x+f"), KeyMapLookup::Action(Action::OpenFile));
+        assert_eq!(
+            lookup("<alt>h"),
+            KeyMapLookup::Action(Action::FocusPane(
+                Orientation::Horizontal,
+                Direction::Dec
+            ))
+        );
+        assert_eq!(lookup("<ctrl>y"), KeyMapLookup::BadSequence);
+
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors[0].starts_with("line 5: invalid binding for \"<ctl>x\"")
+        );
+        assert!(
+            errors[1].starts_with("line 6: invalid binding for \"<ctrl>y\"")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keymap_line() {
+        let source = "keymap:
+  <ctrl>x+f: OpenFile
+  \"<ctrl>x\": NotAnAction
+  '<ctrl>y' : NotAnAction
+";
+        assert_eq!(keymap_line(source, "<ctrl>x+f"), Some(2));
+        assert_eq!(keymap_line(source, "<ctrl>x"), Some(3));
+        assert_eq!(keymap_line(source, "<ctrl>y"), Some(4));
+        assert_eq!(keymap_line(source, "<ctrl>"), None);
+    }
+
     #[test]
     fn test_load() -> Result<()> {
         let tmp_dir = TempDir::new()?;
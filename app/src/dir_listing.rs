@@ -0,0 +1,200 @@
+//! Contents of a directory, shown in a read-only buffer.
+//!
+//! The first line of the buffer is the directory path. Each line
+//! after that is one entry, showing its mode, size and name.
+//! Directory names end with a slash.
+
+use crate::action::Action;
+use crate::key_map::KeyMap;
+use crate::rope::AbsLine;
+use anyhow::Result;
+use fs_err as fs;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Number of lines before the first entry.
+const HEADER_LINES: usize = 1;
+
+/// Name of the entry for the parent directory.
+pub const PARENT_NAME: &str = "..";
+
+pub struct DirEntry {
+    pub name: OsString,
+    pub is_dir: bool,
+    pub size: u64,
+    pub mode: u32,
+}
+
+impl DirEntry {
+    fn read(path: &Path, name: OsString) -> Result<Self> {
+        // Use the symlink's own mode, but follow it to see if it
+        // points to a directory.
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(Self {
+            name,
+            is_dir: path.is_dir(),
+            size: metadata.len(),
+            mode: metadata.permissions().mode(),
+        })
+    }
+
+    pub fn is_parent(&self) -> bool {
+        self.name == PARENT_NAME
+    }
+}
+
+/// Format `mode` like `ls -l`, e.g. "drwxr-xr-x".
+fn format_mode(mode: u32) -> String {
+    let kind = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o010000 => 'p',
+        0o140000 => 's',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        _ => '-',
+    };
+    let mut s = String::from(kind);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+pub struct DirListing {
+    dir: PathBuf,
+
+    /// Entries sorted by name, with the parent directory first.
+    entries: Vec<DirEntry>,
+}
+
+impl DirListing {
+    pub fn read(dir: &Path) -> Result<Self> {
+        let dir = fs::canonicalize(dir)?;
+
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            entries.push(DirEntry::read(&entry.path(), entry.file_name())?);
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(parent) = dir.parent() {
+            entries.insert(0, DirEntry::read(parent, PARENT_NAME.into())?);
+        }
+
+        Ok(Self { dir, entries })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn entries(&self) -> &[DirEntry] {
+        &self.entries
+    }
+
+    /// Get the entry shown on `line` of the text.
+    pub fn entry_at_line(&self, line: AbsLine) -> Option<&DirEntry> {
+        self.entries.get(line.0.checked_sub(HEADER_LINES)?)
+    }
+
+    /// Get the line of the text showing the entry named `name`.
+    pub fn line_of_entry(&self, name: &OsStr) -> Option<AbsLine> {
+        self.entries
+            .iter()
+            .position(|entry| entry.name == name)
+            .map(|index| AbsLine(index + HEADER_LINES))
+    }
+
+    /// Line of the first entry after the parent directory.
+    pub fn first_entry_line(&self) -> AbsLine {
+        let skip = self.entries.first().is_some_and(DirEntry::is_parent);
+        AbsLine(HEADER_LINES + usize::from(skip))
+    }
+
+    /// Get the text of the listing buffer.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}:\n", self.dir.display());
+        for entry in &self.entries {
+            let slash = if entry.is_dir { "/" } else { "" };
+            // Writing to a string can't fail.
+            let _ = writeln!(
+                text,
+                "{} {:>10} {}{slash}",
+                format_mode(entry.mode),
+                entry.size,
+                entry.name.to_string_lossy(),
+            );
+        }
+        text
+    }
+}
+
+/// Keys for the actions available in a listing buffer. Since the
+/// buffer is read-only, plain letters are used.
+pub fn keymap() -> Result<KeyMap> {
+    KeyMap::from_pairs(
+        "dir_listing",
+        vec![
+            ("<ret>", Action::Confirm),
+            ("<ctrl>m", Action::Confirm),
+            ("g", Action::RefreshDirectory),
+            ("c", Action::CreateDirEntry),
+            ("r", Action::RenameDirEntry),
+            ("d", Action::DeleteDirEntry),
+        ]
+        .into_iter(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_mode() {
+        assert_eq!(format_mode(0o100644), "-rw-r--r--");
+        assert_eq!(format_mode(0o040755), "drwxr-xr-x");
+        assert_eq!(format_mode(0o120777), "lrwxrwxrwx");
+    }
+
+    #[test]
+    fn test_dir_listing() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let dir = fs::canonicalize(tmp_dir.path())?;
+        fs::create_dir(dir.join("sub"))?;
+        fs::write(dir.join("b.txt"), "12345")?;
+        fs::write(dir.join("a.txt"), "")?;
+
+        let listing = DirListing::read(&dir)?;
+        let names: Vec<_> = listing
+            .entries()
+            .iter()
+            .map(|entry| entry.name.to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["..", "a.txt", "b.txt", "sub"]);
+
+        let text = listing.to_text();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], format!("{}:", dir.display()));
+        assert!(lines[3].ends_with("          5 b.txt"));
+        assert!(lines[4].starts_with('d'));
+        assert!(lines[4].ends_with(" sub/"));
+
+        assert_eq!(listing.first_entry_line(), AbsLine(2));
+        assert_eq!(
+            listing.entry_at_line(AbsLine(2)).map(|e| &e.name),
+            Some(&OsString::from("a.txt"))
+        );
+        assert!(listing.entry_at_line(AbsLine(0)).is_none());
+        assert!(listing.entry_at_line(AbsLine(5)).is_none());
+        assert_eq!(listing.line_of_entry(OsStr::new("sub")), Some(AbsLine(4)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Prompt for changing an entry of a directory listing buffer.
+
+use crate::LineHeight;
+use crate::action::{Action, Boundary, Direction, Move};
+use crate::buffer::{Buffer, BufferId};
+use crate::key_map::KeyMap;
+use crate::pane_tree::{Pane, Rect};
+use crate::widget::Widget;
+use anyhow::{Result, bail};
+use fs_err as fs;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Text that must be typed to confirm a deletion.
+const CONFIRM_DELETE: &str = "yes";
+
+pub enum DirOp {
+    /// Create a file, or a directory if the name ends with a slash.
+    Create,
+
+    /// Rename the entry with this name.
+    Rename(OsString),
+
+    /// Delete the entry with this name.
+    Delete(OsString),
+}
+
+pub struct DirPrompt {
+    buffer: Buffer,
+    pane: Pane,
+    rect: Rect,
+
+    /// The listing buffer the prompt was opened from.
+    listing_buffer_id: BufferId,
+
+    dir: PathBuf,
+    op: DirOp,
+}
+
+impl DirPrompt {
+    pub fn new(listing_buffer_id: BufferId, dir: &Path, op: DirOp) -> Self {
+        let mut buffer = Buffer::create_empty();
+        let pane = Pane::create_for_widget(&mut buffer);
+
+        // Start with the current name when renaming.
+        if let DirOp::Rename(name) = &op {
+            buffer.set_text(&name.to_string_lossy());
+            buffer.move_cursor(
+                pane.id(),
+                Move::Boundary(Boundary::BufferEnd),
+                Direction::Inc,
+            );
+        }
+
+        Self {
+            buffer,
+            pane,
+            rect: Rect::default(),
+            listing_buffer_id,
+            dir: dir.to_owned(),
+            op,
+        }
+    }
+
+    pub fn op(&self) -> &DirOp {
+        &self.op
+    }
+
+    pub fn listing_buffer_id(&self) -> &BufferId {
+        &self.listing_buffer_id
+    }
+
+    /// Carry out the operation with the text typed in the prompt.
+    pub fn apply(&self) -> Result<()> {
+        let text = self.buffer.text().to_string();
+        match &self.op {
+            DirOp::Create => {
+                if let Some(name) = text.strip_suffix('/') {
+                    fs::create_dir_all(self.dir.join(name))?;
+                } else if text.is_empty() {
+                    bail!("no name given");
+                } else {
+                    fs::File::create_new(self.dir.join(text))?;
+                }
+            }
+            DirOp::Rename(old_name) => {
+                if text.is_empty() {
+                    bail!("no name given");
+                }
+                let new_path = self.dir.join(&text);
+                if new_path.exists() {
+                    bail!("{} already exists", new_path.display());
+                }
+                fs::rename(self.dir.join(old_name), new_path)?;
+            }
+            DirOp::Delete(name) => {
+                if text != CONFIRM_DELETE {
+                    bail!("not deleted, type \"{CONFIRM_DELETE}\" to confirm");
+                }
+                let path = self.dir.join(name);
+                if fs::symlink_metadata(&path)?.is_dir() {
+                    fs::remove_dir_all(path)?;
+                } else {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Widget for DirPrompt {
+    fn get_keymap(&self) -> Result<KeyMap> {
+        KeyMap::from_pairs(
+            "dir_prompt",
+            vec![("<ret>", Action::Confirm), ("<ctrl>m", Action::Confirm)]
+                .into_iter(),
+        )
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_buffer_mut(&mut self) -> (&Pane, &mut Buffer) {
+        (&self.pane, &mut self.buffer)
+    }
+
+    fn pane_mut_buffer_mut(&mut self) -> (&mut Pane, &mut Buffer) {
+        (&mut self.pane, &mut self.buffer)
+    }
+
+    fn recalc_layout(&mut self, width: f64, line_height: LineHeight) {
+        self.rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height: line_height.0 * 2.0,
+        };
+        self.pane.set_rect(Rect {
+            x: 0.0,
+            y: line_height.0,
+            width,
+            height: line_height.0,
+        });
+    }
+
+    fn rect(&self) -> &Rect {
+        &self.rect
+    }
+}
@@ -0,0 +1,344 @@
+//! Popup for opening any file in the current project.
+//!
+//! The project root is the nearest ancestor directory containing
+//! `.git`. Its files are listed by a background thread that respects
+//! ignore files, and sent back in batches over the message pipe so
+//! that the popup is usable before the walk finishes. Candidates are
+//! filtered by fuzzy matching against their path relative to the
+//! root, with recently opened files ranked higher.
+
+use crate::action::{Action, Direction};
+use crate::buffer::Buffer;
+use crate::candidate_list::{self, CandidateList};
+use crate::key_map::KeyMap;
+use crate::message::{Message, MessageWriter};
+use crate::pane_tree::{Pane, Rect};
+use crate::widget::Widget;
+use crate::{LineHeight, fuzzy, util};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use tracing::error;
+
+/// Maximum number of files shown at once.
+pub const MAX_VISIBLE_FILES: usize = 10;
+
+/// Number of paths sent in each message from the walker thread.
+const BATCH_SIZE: usize = 256;
+
+/// Number of recently opened files that get ranked higher. The most
+/// recent file gets the biggest bonus.
+const MAX_RECENT_BONUS: usize = 32;
+
+/// Iterate over the files under `root`, relative to `root`. Hidden
+/// files and anything excluded by ignore files are skipped.
+fn project_files(root: &Path) -> impl Iterator<Item = PathBuf> {
+    let root = root.to_owned();
+    ignore::WalkBuilder::new(&root)
+        // Respect .gitignore even if `root` isn't a git repo.
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                error!("failed to walk project: {err}");
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(move |entry| {
+            entry.path().strip_prefix(&root).ok().map(Path::to_owned)
+        })
+}
+
+pub struct FileEntry {
+    /// Path relative to the project root.
+    pub path: PathBuf,
+
+    /// Path as shown in the popup and matched against.
+    pub display: String,
+
+    /// Added to the match score, based on how recently the file was
+    /// opened.
+    recent_bonus: i64,
+}
+
+pub struct FileFinder {
+    buffer: Buffer,
+    pane: Pane,
+    rect: Rect,
+
+    root: PathBuf,
+
+    /// Identifies the walk started for this finder. Results from
+    /// other walks are ignored.
+    search_id: String,
+
+    /// Set when the finder is closed, to stop the walk early.
+    cancelled: Arc<AtomicBool>,
+
+    /// Rank of recently opened files under the root, keyed by their
+    /// path relative to the root. Zero is the most recent.
+    recent: HashMap<PathBuf, usize>,
+
+    candidates: CandidateList<FileEntry>,
+}
+
+impl FileFinder {
+    /// Create a finder for the project containing `dir`. `recent` is
+    /// the absolute paths of recently opened files, most recent first.
+    /// No files are listed until `start_walk` is called.
+    pub fn new(dir: &Path, recent: &[PathBuf]) -> Self {
+        let mut buffer = Buffer::create_empty();
+        let pane = Pane::create_for_widget(&mut buffer);
+
+        let root = util::find_project_root(dir);
+        let mut recent_ranks = HashMap::new();
+        for path in recent {
+            if let Ok(rel) = path.strip_prefix(&root) {
+                let rank = recent_ranks.len();
+                recent_ranks.entry(rel.to_owned()).or_insert(rank);
+            }
+        }
+
+        Self {
+            buffer,
+            pane,
+            rect: Rect::default(),
+            root,
+            search_id: util::make_id("search"),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            recent: recent_ranks,
+            candidates: CandidateList::new(Vec::new()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn search_id(&self) -> &str {
+        &self.search_id
+    }
+
+    pub fn candidates(&self) -> &CandidateList<FileEntry> {
+        &self.candidates
+    }
+
+    /// Absolute path of the selected file.
+    pub fn selected_path(&self) -> Option<PathBuf> {
+        self.candidates
+            .selected()
+            .map(|entry| self.root.join(&entry.path))
+    }
+
+    /// List the project's files in a background thread. Each batch
+    /// of files is sent as an `Action::FoundFiles`.
+    pub fn start_walk(&self, message_writer: MessageWriter) {
+        let root = self.root.clone();
+        let search_id = self.search_id.clone();
+        let cancelled = self.cancelled.clone();
+        thread::spawn(move || {
+            let mut files = project_files(&root);
+            while !cancelled.load(Ordering::Relaxed) {
+                let batch: Vec<PathBuf> =
+                    files.by_ref().take(BATCH_SIZE).collect();
+                if batch.is_empty() {
+                    return;
+                }
+                let msg = Message::Action(Action::FoundFiles(
+                    search_id.clone(),
+                    batch,
+                ));
+                if let Err(err) = message_writer.send(msg) {
+                    error!("failed to send found files: {err}");
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Add files found by the walk, given as paths relative to the
+    /// root.
+    pub fn add_files(&mut self, paths: Vec<PathBuf>) {
+        let entries = paths.into_iter().map(|path| {
+            let recent_bonus = self
+                .recent
+                .get(&path)
+                .map(|rank| MAX_RECENT_BONUS.saturating_sub(*rank) as i64)
+                .unwrap_or(0);
+            FileEntry {
+                display: path.display().to_string(),
+                path,
+                recent_bonus,
+            }
+        });
+        let query = self.buffer.text().to_string();
+        // Only the new files are scored, and the selection stays where
+        // it is so that it can be moved before the walk finishes.
+        self.candidates
+            .extend(entries, |entry| Self::score(&query, entry));
+    }
+
+    fn score(query: &str, entry: &FileEntry) -> Option<i64> {
+        fuzzy::score(query, &entry.display)
+            .map(|score| score + entry.recent_bonus)
+    }
+
+    /// Filter the files by the text typed in the finder. The best
+    /// match gets selected.
+    pub fn update_matches(&mut self) {
+        let query = self.buffer.text().to_string();
+        self.candidates
+            .update_matches(|entry| Self::score(&query, entry));
+        self.candidates.select_first();
+    }
+
+    pub fn select(&mut self, dir: Direction) {
+        self.candidates.select(dir);
+    }
+}
+
+impl Drop for FileFinder {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Widget for FileFinder {
+    fn get_keymap(&self) -> Result<KeyMap> {
+        candidate_list::popup_keymap("find_file", Vec::new())
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_buffer_mut(&mut self) -> (&Pane, &mut Buffer) {
+        (&self.pane, &mut self.buffer)
+    }
+
+    fn pane_mut_buffer_mut(&mut self) -> (&mut Pane, &mut Buffer) {
+        (&mut self.pane, &mut self.buffer)
+    }
+
+    fn recalc_layout(&mut self, width: f64, line_height: LineHeight) {
+        // Prompt, input line, then one line per visible file.
+        let num_rows = self.candidates.len().min(MAX_VISIBLE_FILES);
+        self.rect = candidate_list::popup_layout(
+            &mut self.pane,
+            width,
+            line_height,
+            num_rows,
+        );
+    }
+
+    fn rect(&self) -> &Rect {
+        &self.rect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_project_files() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let root = tmp_dir.path();
+        fs::create_dir_all(root.join(".git"))?;
+        fs::create_dir_all(root.join("src/nested"))?;
+        fs::create_dir_all(root.join("target"))?;
+        fs::write(root.join(".gitignore"), "target/\n*.log\n")?;
+        for name in [
+            "Cargo.toml",
+            "src/main.rs",
+            "src/nested/mod.rs",
+            "target/out",
+            "debug.log",
+            ".hidden",
+        ] {
+            fs::write(root.join(name), "")?;
+        }
+
+        // The root is found from a subdirectory.
+        assert_eq!(util::find_project_root(&root.join("src/nested")), root);
+
+        // Ignored and hidden files are skipped.
+        let files: Vec<_> = project_files(root).collect();
+        assert_eq!(
+            files,
+            [
+                Path::new("Cargo.toml"),
+                Path::new("src/main.rs"),
+                Path::new("src/nested/mod.rs")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_finder() {
+        let root = Path::new("/project");
+        let recent = [
+            PathBuf::from("/elsewhere/lib.rs"),
+            PathBuf::from("/project/src/lib.rs"),
+            PathBuf::from("/project/README.md"),
+        ];
+        let mut finder = FileFinder::new(root, &recent);
+        assert_eq!(finder.root(), root);
+
+        let paths = |finder: &FileFinder| -> Vec<String> {
+            finder
+                .candidates()
+                .matches()
+                .map(|entry| entry.display.clone())
+                .collect()
+        };
+
+        // Recent files first, in order. The selection isn't moved by
+        // files found later.
+        finder.add_files(vec!["Cargo.toml".into(), "README.md".into()]);
+        assert_eq!(
+            finder.selected_path(),
+            Some(PathBuf::from("/project/README.md"))
+        );
+        finder.select(Direction::Inc);
+        finder.add_files(vec!["src/lib.rs".into(), "src/main.rs".into()]);
+        assert_eq!(
+            paths(&finder),
+            ["src/lib.rs", "README.md", "Cargo.toml", "src/main.rs"]
+        );
+        assert_eq!(
+            finder.selected_path(),
+            Some(PathBuf::from("/project/Cargo.toml"))
+        );
+
+        // Recent files win between similar matches.
+        finder.buffer_mut().set_text("srcrs");
+        finder.update_matches();
+        assert_eq!(paths(&finder), ["src/lib.rs", "src/main.rs"]);
+
+        finder.buffer_mut().set_text("main");
+        finder.update_matches();
+        assert_eq!(paths(&finder), ["src/main.rs"]);
+
+        finder.buffer_mut().set_text("zzz");
+        finder.update_matches();
+        assert_eq!(finder.selected_path(), None);
+    }
+}
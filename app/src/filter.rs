@@ -0,0 +1,70 @@
+//! Run text through an external command, e.g. `sort` or `rustfmt`.
+
+use crate::command_line::CommandLine;
+use anyhow::{Context, Result, anyhow, bail};
+use std::io::{ErrorKind, Write};
+use std::process::Stdio;
+use std::thread;
+
+/// Run `command_line` with `input` written to its stdin, and return
+/// its stdout.
+///
+/// If the command can't be run or exits with an error, the error
+/// message includes anything the command wrote to stderr.
+pub fn run_filter(command_line: &CommandLine, input: &str) -> Result<String> {
+    let mut child = command_line
+        .to_command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run \"{command_line}\""))?;
+
+    // Write the input from a separate thread. Otherwise a command that
+    // writes a lot of output before it has read all of its input
+    // would deadlock.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_owned();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child.wait_with_output()?;
+
+    match writer.join() {
+        Ok(Ok(())) => {}
+        // The command doesn't have to read all of its input.
+        Ok(Err(err)) if err.kind() == ErrorKind::BrokenPipe => {}
+        Ok(Err(err)) => return Err(err.into()),
+        Err(_) => return Err(anyhow!("stdin writer thread panicked")),
+    }
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "\"{command_line}\" failed ({}): {}",
+            output.status,
+            stderr.trim_end()
+        );
+    }
+
+    String::from_utf8(output.stdout).context("output is not valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_filter() -> Result<()> {
+        let sort = CommandLine::from_string("sort")?;
+        assert_eq!(run_filter(&sort, "b\nc\na\n")?, "a\nb\nc\n");
+
+        let fail = CommandLine::from_string("sh -c 'echo oops >&2; exit 1'")?;
+        let err = run_filter(&fail, "").unwrap_err();
+        assert!(err.to_string().ends_with(": oops"), "{err}");
+
+        let missing = CommandLine::from_string("emma-no-such-command")?;
+        assert!(run_filter(&missing, "").is_err());
+
+        Ok(())
+    }
+}
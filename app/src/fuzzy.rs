@@ -0,0 +1,115 @@
+//! Fuzzy matching, used to filter lists of candidates as the user
+//! types.
+//!
+//! A pattern matches a candidate if all the pattern's chars appear in
+//! the candidate in order. Matching is case-insensitive unless the
+//! pattern contains an uppercase char. Among the possible alignments
+//! of the pattern, the best-scoring one is used: matches at the start
+//! of words and runs of consecutive matches score higher, gaps
+//! between matched chars score lower.
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 8;
+const PENALTY_GAP: i64 = 1;
+
+/// Bonus for matching the char at `index`, based on whether it starts
+/// a word.
+fn boundary_bonus(chars: &[char], index: usize) -> i64 {
+    let Some(prev) = index.checked_sub(1).map(|i| chars[i]) else {
+        return BONUS_BOUNDARY;
+    };
+    let cur = chars[index];
+    if !prev.is_alphanumeric() || (prev.is_lowercase() && cur.is_uppercase()) {
+        BONUS_BOUNDARY
+    } else {
+        0
+    }
+}
+
+/// Score how well `pattern` matches `candidate`, higher is better.
+/// Returns `None` if it doesn't match at all. An empty pattern matches
+/// everything with a score of zero.
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let normalize = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+
+    let pattern: Vec<char> = pattern.chars().map(normalize).collect();
+    let original: Vec<char> = candidate.chars().collect();
+    let chars: Vec<char> = original.iter().copied().map(normalize).collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    // `prev[j]` is the best score for matching the pattern so far
+    // with the previous pattern char matched at `chars[j]`.
+    let unmatched = i64::MIN / 2;
+    let mut prev = vec![unmatched; chars.len()];
+    let mut cur = vec![unmatched; chars.len()];
+    for (i, pc) in pattern.iter().enumerate() {
+        // Best score of `prev[k] - gap penalty` for all `k < j - 1`.
+        let mut best_with_gap = unmatched;
+        for j in 0..chars.len() {
+            if j >= 2 {
+                best_with_gap = best_with_gap.max(prev[j - 2]) - PENALTY_GAP;
+            }
+
+            cur[j] = if chars[j] != *pc {
+                unmatched
+            } else if i == 0 {
+                // Prefer matches near the start.
+                SCORE_MATCH + 2 * boundary_bonus(&original, j)
+                    - PENALTY_GAP * j as i64
+            } else {
+                let consecutive = if j >= 1 {
+                    prev[j - 1] + BONUS_CONSECUTIVE
+                } else {
+                    unmatched
+                };
+                SCORE_MATCH
+                    + boundary_bonus(&original, j)
+                    + consecutive.max(best_with_gap)
+            };
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    let best = prev.into_iter().max()?;
+    // Anything below this threshold is an "unmatched" value plus or
+    // minus some bonuses and penalties.
+    if best < unmatched / 2 {
+        None
+    } else {
+        Some(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("", "abc"), Some(0));
+        assert!(score("abc", "abc").is_some());
+        assert!(score("ac", "abc").is_some());
+        assert!(score("ABC", "abc").is_none());
+        assert!(score("abc", "ABC").is_some());
+        assert!(score("ca", "abc").is_none());
+        assert!(score("abcd", "abc").is_none());
+
+        // Consecutive matches are better.
+        assert!(score("ab", "abxx") > score("ab", "axbx"));
+        // Word starts are better.
+        assert!(score("fb", "foo_bar") > score("fb", "fxxxbxx"));
+        assert!(score("fb", "fooBar") > score("fb", "foobar"));
+        // The best alignment is found, not just the first.
+        assert!(score("bar", "xbxaxrbar") > score("bar", "xbxaxrxxx"));
+    }
+}
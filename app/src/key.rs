@@ -9,14 +9,31 @@ pub enum Key {
     // TODO: some of these could be represented with Char, is there a
     // good reason not to?
     Backspace,
+    Delete,
+    Down,
+    End,
     Escape,
+
+    /// Function key, e.g. `Function(1)` is F1.
+    Function(u8),
     Greater,
+    Home,
+    Insert,
+    Left,
     Less,
+    PageDown,
+    PageUp,
     Plus,
     Return,
+    Right,
     Space,
+    Tab,
+    Up,
 }
 
+/// Highest numbered function key.
+pub const MAX_FUNCTION_KEY: u8 = 35;
+
 impl Key {
     pub fn is_modifier(self) -> bool {
         matches!(self, Self::Modifier(_))
@@ -74,6 +91,7 @@ impl Key {
             Self::Plus => Some('+'),
             Self::Space => Some(' '),
             Self::Return => Some('\n'),
+            Self::Tab => Some('\t'),
             _ => None,
         }
     }
@@ -84,6 +102,22 @@ pub enum Modifier {
     Alt,
     Control,
     Shift,
+    Super,
+    Hyper,
+    Meta,
+}
+
+impl Modifier {
+    /// All modifiers, in the order they are written in a key
+    /// sequence.
+    pub const ALL: [Self; 6] = [
+        Self::Control,
+        Self::Alt,
+        Self::Shift,
+        Self::Super,
+        Self::Hyper,
+        Self::Meta,
+    ];
 }
 
 impl Display for Modifier {
@@ -95,6 +129,9 @@ impl Display for Modifier {
                 Self::Control => "ctrl",
                 Self::Shift => "shift",
                 Self::Alt => "alt",
+                Self::Super => "super",
+                Self::Hyper => "hyper",
+                Self::Meta => "meta",
             }
         )
     }
@@ -105,60 +142,65 @@ pub struct Modifiers {
     pub alt: bool,
     pub control: bool,
     pub shift: bool,
+    pub super_: bool,
+    pub hyper: bool,
+    pub meta: bool,
 }
 
 impl Modifiers {
     pub fn new() -> Self {
-        Self {
-            alt: false,
-            control: false,
-            shift: false,
-        }
+        Self::default()
     }
 
     // TODO: naming
     pub fn is_empty(&self) -> bool {
-        !self.alt && !self.control && !self.shift
+        *self == Self::default()
+    }
+
+    pub fn is_enabled(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::Alt => self.alt,
+            Modifier::Control => self.control,
+            Modifier::Shift => self.shift,
+            Modifier::Super => self.super_,
+            Modifier::Hyper => self.hyper,
+            Modifier::Meta => self.meta,
+        }
     }
 
     pub fn enable_modifier(&mut self, modifier: Modifier) {
+        self.set_modifier(modifier, true);
+    }
+
+    pub fn disable_modifier(&mut self, modifier: Modifier) {
+        self.set_modifier(modifier, false);
+    }
+
+    fn set_modifier(&mut self, modifier: Modifier, enabled: bool) {
         match modifier {
-            Modifier::Alt => self.alt = true,
-            Modifier::Control => self.control = true,
-            Modifier::Shift => self.shift = true,
+            Modifier::Alt => self.alt = enabled,
+            Modifier::Control => self.control = enabled,
+            Modifier::Shift => self.shift = enabled,
+            Modifier::Super => self.super_ = enabled,
+            Modifier::Hyper => self.hyper = enabled,
+            Modifier::Meta => self.meta = enabled,
         }
     }
 }
 
+/// A set of modifiers equals a modifier if that modifier is the only
+/// one enabled.
 impl PartialEq<Modifier> for Modifiers {
     fn eq(&self, modifier: &Modifier) -> bool {
-        match modifier {
-            Modifier::Alt => self.alt && !self.control && !self.shift,
-            Modifier::Control => !self.alt && self.control && !self.shift,
-            Modifier::Shift => !self.alt && !self.control && self.shift,
-        }
+        *self == Self::from(*modifier)
     }
 }
 
 impl From<Modifier> for Modifiers {
     fn from(modifier: Modifier) -> Self {
-        match modifier {
-            Modifier::Alt => Self {
-                alt: true,
-                control: false,
-                shift: false,
-            },
-            Modifier::Control => Self {
-                alt: false,
-                control: true,
-                shift: false,
-            },
-            Modifier::Shift => Self {
-                alt: false,
-                control: false,
-                shift: true,
-            },
-        }
+        let mut modifiers = Self::default();
+        modifiers.enable_modifier(modifier);
+        modifiers
     }
 }
 
@@ -172,6 +214,19 @@ mod tests {
         assert!(!Key::Char('a').is_modifier());
     }
 
+    #[test]
+    fn test_modifiers() {
+        let mut modifiers = Modifiers::new();
+        assert!(modifiers.is_empty());
+        modifiers.enable_modifier(Modifier::Super);
+        assert!(modifiers.is_enabled(Modifier::Super));
+        assert_eq!(modifiers, Modifier::Super);
+        modifiers.enable_modifier(Modifier::Control);
+        assert_ne!(modifiers, Modifier::Super);
+        modifiers.disable_modifier(Modifier::Super);
+        assert_eq!(modifiers, Modifier::Control);
+    }
+
     #[test]
     fn test_to_upper() {
         assert_eq!(Key::Char('a').to_upper(), Key::Char('A'));
@@ -189,6 +244,8 @@ mod tests {
     #[test]
     fn test_to_char() {
         assert_eq!(Key::Char('a').to_char(), Some('a'));
+        assert_eq!(Key::Tab.to_char(), Some('\t'));
         assert!(Key::Escape.to_char().is_none());
+        assert!(Key::Function(1).to_char().is_none());
     }
 }
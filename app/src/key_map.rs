@@ -1,5 +1,5 @@
-use crate::action::{Action, Boundary, Direction, Move};
-use crate::key::Modifier;
+use crate::action::{Action, Boundary, Direction, FilterOutput, Move};
+use crate::key::{Key, Modifier};
 use crate::key_sequence::KeySequence;
 use crate::pane_tree;
 use anyhow::Result;
@@ -80,6 +80,10 @@ impl KeyMap {
                 ),
                 ("<alt>v", Action::Move(Move::Page, Direction::Dec)),
                 ("<ctrl>v", Action::Move(Move::Page, Direction::Inc)),
+                ("<alt>p", Action::Scroll(Direction::Dec)),
+                ("<alt>n", Action::Scroll(Direction::Inc)),
+                ("<ctrl>l", Action::Recenter),
+                ("<ctrl>x+x+t", Action::ToggleWrap),
                 (
                     "<alt><shift><less>",
                     Action::Move(
@@ -98,16 +102,76 @@ impl KeyMap {
                     "<backspace>",
                     Action::Delete(Boundary::Grapheme, Direction::Dec),
                 ),
+                (
+                    "<delete>",
+                    Action::Delete(Boundary::Grapheme, Direction::Inc),
+                ),
+                (
+                    "<left>",
+                    Action::Move(
+                        Move::Boundary(Boundary::Grapheme),
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<right>",
+                    Action::Move(
+                        Move::Boundary(Boundary::Grapheme),
+                        Direction::Inc,
+                    ),
+                ),
+                ("<up>", Action::Move(Move::Line, Direction::Dec)),
+                ("<down>", Action::Move(Move::Line, Direction::Inc)),
+                (
+                    "<home>",
+                    Action::Move(
+                        Move::Boundary(Boundary::LineEnd),
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<end>",
+                    Action::Move(
+                        Move::Boundary(Boundary::LineEnd),
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<ctrl><home>",
+                    Action::Move(
+                        Move::Boundary(Boundary::BufferEnd),
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<ctrl><end>",
+                    Action::Move(
+                        Move::Boundary(Boundary::BufferEnd),
+                        Direction::Inc,
+                    ),
+                ),
+                ("<pageup>", Action::Move(Move::Page, Direction::Dec)),
+                ("<pagedown>", Action::Move(Move::Page, Direction::Inc)),
                 (
                     "<ctrl>d",
                     Action::Delete(Boundary::Grapheme, Direction::Inc),
                 ),
                 ("<ctrl>k", Action::Delete(Boundary::LineEnd, Direction::Inc)),
+                ("<ctrl><space>", Action::SetMark),
+                ("<alt><shift>|", Action::FilterRegion(FilterOutput::Replace)),
+                (
+                    "<ctrl>x+<alt><shift>|",
+                    Action::FilterRegion(FilterOutput::NewBuffer),
+                ),
+                ("<alt>.", Action::GoToDefinition),
+                ("<alt>/", Action::Complete),
+                ("<ctrl>c+h", Action::ShowHover),
                 ("<ctrl>s", Action::InteractiveSearch),
                 ("<ctrl>/", Action::Undo),
                 ("<ctrl><shift>?", Action::Redo),
                 ("<ctrl>x+k", Action::DeleteBuffer),
                 ("<ctrl>x+<ctrl>f", Action::OpenFile),
+                ("<ctrl>c+f", Action::FindFileInProject),
                 ("<ctrl>x+<ctrl>s", Action::SaveFile),
                 ("<ctrl><shift>j", Action::PreviousPane),
                 ("<ctrl><shift>k", Action::NextPane),
@@ -120,13 +184,134 @@ impl KeyMap {
                     Action::SplitPane(pane_tree::Orientation::Horizontal),
                 ),
                 ("<ctrl>x+0", Action::ClosePane),
+                ("<ctrl>x+1", Action::CloseOtherPanes),
+                (
+                    "<ctrl>x+<shift>^",
+                    Action::ResizePane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<ctrl>x+-",
+                    Action::ResizePane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<ctrl>x+<shift>}",
+                    Action::ResizePane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<ctrl>x+<shift>{",
+                    Action::ResizePane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Dec,
+                    ),
+                ),
+                ("<ctrl>x+<shift><plus>", Action::BalancePanes),
+                (
+                    "<shift><left>",
+                    Action::FocusPane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<shift><right>",
+                    Action::FocusPane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<shift><up>",
+                    Action::FocusPane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<shift><down>",
+                    Action::FocusPane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<ctrl><shift><left>",
+                    Action::SwapPane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<ctrl><shift><right>",
+                    Action::SwapPane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<ctrl><shift><up>",
+                    Action::SwapPane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<ctrl><shift><down>",
+                    Action::SwapPane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<alt><shift><left>",
+                    Action::MovePane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<alt><shift><right>",
+                    Action::MovePane(
+                        pane_tree::Orientation::Horizontal,
+                        Direction::Inc,
+                    ),
+                ),
+                (
+                    "<alt><shift><up>",
+                    Action::MovePane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Dec,
+                    ),
+                ),
+                (
+                    "<alt><shift><down>",
+                    Action::MovePane(
+                        pane_tree::Orientation::Vertical,
+                        Direction::Inc,
+                    ),
+                ),
+                ("<ctrl>x+r", Action::RotateSplit),
                 ("<ctrl>c+<ctrl>s", Action::OpenShell),
                 ("<ctrl>x+b", Action::SwitchToBuffer),
+                ("<alt>x", Action::OpenCommandPalette),
                 // TODO: what key to use for this.
                 ("<ctrl>x+<ctrl>p", Action::RunNonInteractiveProcess),
                 ("<ctrl>x+<ctrl>r", Action::RerunProcess),
-                // TODO: make this generic so that any key sequence can be
-                // canceled with ctrl+g.
+                ("<ctrl>u", Action::UniversalArgument),
+                ("<ctrl>x+z", Action::RepeatLastAction),
+                ("<ctrl>x+<shift>(", Action::StartMacro),
+                ("<ctrl>x+<shift>)", Action::EndMacro),
+                ("<ctrl>x+e", Action::ReplayMacro),
+                ("<ctrl>x+<ctrl>k+n", Action::SaveMacro),
+                ("<ctrl>x+<ctrl>k+r", Action::RunSavedMacro),
                 ("<ctrl>g", Action::Cancel),
             ]
             .into_iter(),
@@ -155,9 +340,6 @@ impl KeyMap {
         }
 
         // At this point we know the sequence is not in the map.
-
-        // TODO: special "<ctrl>g" type thing to kill any sequence
-
         KeyMapLookup::BadSequence
     }
 
@@ -172,15 +354,40 @@ impl KeyMap {
         }
         false
     }
+
+    /// Iterate over the bindings that start with `seq`, excluding
+    /// `seq` itself.
+    fn continuations<'a>(
+        &'a self,
+        seq: &'a KeySequence,
+    ) -> impl Iterator<Item = (&'a KeySequence, &'a Action)> {
+        self.map
+            .iter()
+            .filter(move |(k, _)| k.starts_with(seq) && *k != seq)
+    }
+}
+
+#[derive(Debug)]
+pub struct KeyMapStack {
+    maps: Vec<KeyMap>,
+
+    /// Whether a single unbound key inserts its character.
+    insert_unbound: bool,
 }
 
-#[derive(Debug, Default)]
-pub struct KeyMapStack(Vec<KeyMap>);
+impl Default for KeyMapStack {
+    fn default() -> Self {
+        Self {
+            maps: Vec::new(),
+            insert_unbound: true,
+        }
+    }
+}
 
 impl KeyMapStack {
     #[instrument(skip(self))]
     pub fn lookup(&self, seq: &KeySequence) -> KeyMapLookup {
-        for map in self.0.iter().rev() {
+        for map in self.maps.iter().rev() {
             debug!("map: {}", map.name);
 
             let res = map.lookup(seq);
@@ -201,9 +408,10 @@ impl KeyMapStack {
         // modifiers (other than shift) then just pass it along; this
         // handles things like pressing the letter 'a' where we just
         // want the default insertion action to occur.
-        if seq.0.len() == 1 {
+        if seq.0.len() == 1 && self.insert_unbound {
             let atom = &seq.0[0];
-            // TODO: not very robust, and won't work with capslock.
+            // Caps lock turns letters into shifted letters, see
+            // `KeySequenceAtom::from_event`.
             let key = if atom.modifiers.is_empty() {
                 Some(atom.key)
             } else if atom.modifiers == Modifier::Shift {
@@ -212,9 +420,8 @@ impl KeyMapStack {
                 None
             };
 
-            if let Some(key) = key {
-                let c =
-                    key.to_char().expect("failed to convert key to unicode");
+            // Keys such as <f1> have no character to insert.
+            if let Some(c) = key.and_then(Key::to_char) {
                 return KeyMapLookup::Action(Action::Insert(c));
             }
         }
@@ -222,9 +429,58 @@ impl KeyMapStack {
         KeyMapLookup::BadSequence
     }
 
+    /// Get the bindings that could complete `seq`, sorted by key
+    /// sequence. Where maps bind the same sequence, the one highest
+    /// in the stack wins.
+    pub fn continuations(
+        &self,
+        seq: &KeySequence,
+    ) -> Vec<(KeySequence, Action)> {
+        let mut bindings = HashMap::new();
+        for map in &self.maps {
+            for (k, action) in map.continuations(seq) {
+                bindings.insert(k.clone(), action.clone());
+            }
+        }
+        let mut bindings: Vec<_> = bindings.into_iter().collect();
+        bindings.sort_by_cached_key(|(k, _)| k.to_string());
+        bindings
+    }
+
+    /// Find the key sequence that runs `action`, if any. Bindings
+    /// hidden by a map higher in the stack don't count, including
+    /// ones whose prefix is bound to something else. Where several
+    /// sequences run it, the shortest is returned.
+    pub fn keys_for(&self, action: &Action) -> Option<KeySequence> {
+        let reachable = |seq: &KeySequence| {
+            (1..seq.0.len()).all(|len| {
+                let prefix = KeySequence(seq.0[..len].to_vec());
+                matches!(self.lookup(&prefix), KeyMapLookup::Prefix)
+            })
+        };
+        self.continuations(&KeySequence::default())
+            .into_iter()
+            .filter(|(seq, bound)| {
+                bound == action
+                    && matches!(
+                        self.lookup(seq),
+                        KeyMapLookup::Action(found) if found == *action
+                    )
+                    && reachable(seq)
+            })
+            .map(|(seq, _)| seq)
+            .min_by_key(|seq| seq.0.len())
+    }
+
+    /// Make single unbound keys a bad sequence rather than inserting
+    /// their character, e.g. in a mode where plain keys are commands.
+    pub fn disable_insert(&mut self) {
+        self.insert_unbound = false;
+    }
+
     pub fn push(&mut self, map: Result<KeyMap>) {
         match map {
-            Ok(map) => self.0.push(map),
+            Ok(map) => self.maps.push(map),
             Err(err) => {
                 // TODO: display in UI
                 error!("invalid map: {}", err)
@@ -302,5 +558,98 @@ mod tests {
             stack.lookup(&KeySequence::parse("<ctrl>x").unwrap()),
             KeyMapLookup::BadSequence,
         );
+
+        // With insertion disabled, only bound keys do anything.
+        stack.disable_insert();
+        assert_eq!(
+            stack.lookup(&KeySequence::parse("y").unwrap()),
+            KeyMapLookup::BadSequence
+        );
+        assert_eq!(
+            stack.lookup(&KeySequence::parse("x").unwrap()),
+            KeyMapLookup::Action(Action::Insert(x_base))
+        );
+    }
+
+    #[test]
+    fn test_continuations() {
+        let mut stack = KeyMapStack::default();
+        stack.push(KeyMap::from_pairs(
+            "base",
+            vec![
+                ("<ctrl>x+a", Action::Insert('a')),
+                ("<ctrl>x+b", Action::Insert('b')),
+                ("<ctrl>x+<ctrl>c+d", Action::Insert('d')),
+                ("<ctrl>y", Action::Insert('y')),
+            ]
+            .into_iter(),
+        ));
+        stack.push(KeyMap::from_pairs(
+            "overlay",
+            vec![
+                ("<ctrl>x+b", Action::Insert('B')),
+                ("<ctrl>x+c", Action::Insert('c')),
+            ]
+            .into_iter(),
+        ));
+
+        let seq = |s| KeySequence::parse(s).unwrap();
+        assert_eq!(
+            stack.continuations(&seq("<ctrl>x")),
+            [
+                (seq("<ctrl>x+<ctrl>c+d"), Action::Insert('d')),
+                (seq("<ctrl>x+a"), Action::Insert('a')),
+                (seq("<ctrl>x+b"), Action::Insert('B')),
+                (seq("<ctrl>x+c"), Action::Insert('c')),
+            ]
+        );
+        assert_eq!(stack.continuations(&seq("<ctrl>y")), []);
+    }
+
+    #[test]
+    fn test_unbound_key_without_char() {
+        let stack = KeyMapStack::default();
+        let seq = |s| KeySequence::parse(s).unwrap();
+        assert_eq!(
+            stack.lookup(&seq("<tab>")),
+            KeyMapLookup::Action(Action::Insert('\t'))
+        );
+        assert_eq!(stack.lookup(&seq("<f5>")), KeyMapLookup::BadSequence);
+        assert_eq!(stack.lookup(&seq("<insert>")), KeyMapLookup::BadSequence);
+    }
+
+    #[test]
+    fn test_keys_for() {
+        let mut stack = KeyMapStack::default();
+        stack.push(KeyMap::from_pairs(
+            "base",
+            vec![
+                ("<ctrl>x+<ctrl>s", Action::SaveFile),
+                ("<ctrl>s", Action::SaveFile),
+                ("<ctrl>x+u", Action::Undo),
+                ("<ctrl>y", Action::Redo),
+            ]
+            .into_iter(),
+        ));
+        stack.push(KeyMap::from_pairs(
+            "user",
+            vec![
+                ("<ctrl>s", Action::InteractiveSearch),
+                ("<ctrl>x", Action::Exit),
+            ]
+            .into_iter(),
+        ));
+
+        let seq = |s| KeySequence::parse(s).unwrap();
+        // The shortest binding wins, unless it's shadowed.
+        assert_eq!(stack.keys_for(&Action::Redo), Some(seq("<ctrl>y")));
+        assert_eq!(
+            stack.keys_for(&Action::InteractiveSearch),
+            Some(seq("<ctrl>s"))
+        );
+        // <ctrl>x is no longer a prefix.
+        assert_eq!(stack.keys_for(&Action::SaveFile), None);
+        assert_eq!(stack.keys_for(&Action::Undo), None);
+        assert_eq!(stack.keys_for(&Action::Confirm), None);
     }
 }
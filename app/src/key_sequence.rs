@@ -1,17 +1,36 @@
-use crate::key::{Key, Modifier, Modifiers};
+use crate::key::{Key, MAX_FUNCTION_KEY, Modifier, Modifiers};
 use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
 
-fn name_to_key_map() -> HashMap<&'static str, Key> {
+fn name_to_key_map() -> HashMap<String, Key> {
     // This map is the only place that needs to be updated to add a
     // new named key.
     let mut map = HashMap::new();
-    map.insert("backspace", Key::Backspace);
-    map.insert("esc", Key::Escape);
-    map.insert("space", Key::Space);
-    map.insert("ret", Key::Return);
-    map.insert("plus", Key::Plus);
-    map.insert("less", Key::Less);
-    map.insert("greater", Key::Greater);
+    for (name, key) in [
+        ("backspace", Key::Backspace),
+        ("esc", Key::Escape),
+        ("space", Key::Space),
+        ("ret", Key::Return),
+        ("tab", Key::Tab),
+        ("plus", Key::Plus),
+        ("less", Key::Less),
+        ("greater", Key::Greater),
+        ("up", Key::Up),
+        ("down", Key::Down),
+        ("left", Key::Left),
+        ("right", Key::Right),
+        ("home", Key::Home),
+        ("end", Key::End),
+        ("pageup", Key::PageUp),
+        ("pagedown", Key::PageDown),
+        ("insert", Key::Insert),
+        ("delete", Key::Delete),
+    ] {
+        map.insert(name.to_owned(), key);
+    }
+    for n in 1..=MAX_FUNCTION_KEY {
+        map.insert(format!("f{n}"), Key::Function(n));
+    }
     map
 }
 
@@ -21,20 +40,100 @@ pub struct KeySequenceAtom {
     pub key: Key,
 }
 
+/// Unshifted and shifted symbols on a US keyboard, so that a binding
+/// can be written as e.g. "<shift>/" as well as "?".
+const US_SHIFTED_SYMBOLS: [(char, char); 21] = [
+    ('`', '~'),
+    ('1', '!'),
+    ('2', '@'),
+    ('3', '#'),
+    ('4', '$'),
+    ('5', '%'),
+    ('6', '^'),
+    ('7', '&'),
+    ('8', '*'),
+    ('9', '('),
+    ('0', ')'),
+    ('-', '_'),
+    ('=', '+'),
+    ('[', '{'),
+    (']', '}'),
+    ('\\', '|'),
+    (';', ':'),
+    ('\'', '"'),
+    (',', '<'),
+    ('.', '>'),
+    ('/', '?'),
+];
+
 impl KeySequenceAtom {
-    pub fn from_event(key: Key, modifiers: Modifiers) -> Self {
+    /// Create an atom for a key press. Keys are identified by the
+    /// symbol they produce, so shift is implied by a shifted symbol
+    /// such as "?" and is dropped. Letters are the exception: they
+    /// are stored lowercase, with shift enabled for an uppercase
+    /// letter, which also makes caps lock behave like shift.
+    pub fn from_event(key: Key, mut modifiers: Modifiers) -> Self {
+        // Symbols used by the binding syntax have named keys.
+        let key = match key {
+            Key::Char('+') => Key::Plus,
+            Key::Char('<') => Key::Less,
+            Key::Char('>') => Key::Greater,
+            key => key,
+        };
+        let lower = key.to_lower();
+        let has_case = lower != key.to_upper();
+        if has_case {
+            if key != lower {
+                modifiers.enable_modifier(Modifier::Shift);
+            }
+        } else if matches!(
+            key,
+            Key::Char(_) | Key::Plus | Key::Less | Key::Greater
+        ) {
+            modifiers.disable_modifier(Modifier::Shift);
+        }
         Self {
             modifiers,
-            // Convert the key to lowercase as a way to
-            // normalize. This is far from perfect, for example "?"
-            // should probably be the same thing as "<shift>/", but
-            // that's not handled well right now.
-            key: key.to_lower(),
+            key: lower,
+        }
+    }
+
+    /// Create an atom for a key written in a binding. Unlike a key
+    /// press, an unshifted symbol may be written with shift, which
+    /// is read as the symbol shift produces on a US keyboard.
+    fn from_binding(key: Key, modifiers: Modifiers) -> Self {
+        let key = match key {
+            Key::Char(c) if modifiers.shift => US_SHIFTED_SYMBOLS
+                .iter()
+                .find(|(unshifted, _)| *unshifted == c)
+                .map(|(_, shifted)| Key::from_char(*shifted))
+                .unwrap_or(key),
+            _ => key,
+        };
+        Self::from_event(key, modifiers)
+    }
+}
+
+/// Formats the atom in the syntax accepted by `KeySequence::parse`,
+/// e.g. "<ctrl>x".
+impl Display for KeySequenceAtom {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for modifier in Modifier::ALL {
+            if self.modifiers.is_enabled(modifier) {
+                write!(f, "<{modifier}>")?;
+            }
+        }
+        if let Some(name) = key_to_name_map().get(&self.key) {
+            write!(f, "<{name}>")
+        } else if let Some(c) = self.key.to_char() {
+            write!(f, "{c}")
+        } else {
+            write!(f, "{:?}", self.key)
         }
     }
 }
 
-fn key_to_name_map() -> HashMap<Key, &'static str> {
+fn key_to_name_map() -> HashMap<Key, String> {
     let mut map = HashMap::new();
     for (k, v) in name_to_key_map() {
         map.insert(v, k);
@@ -83,9 +182,9 @@ fn parse_key_sequence_as_items(s: &str) -> Result<Vec<ParseItem>, Error> {
     let mut state = State::Initial;
 
     let mut names = HashMap::new();
-    names.insert("alt", ParseItem::Modifier(Modifier::Alt));
-    names.insert("ctrl", ParseItem::Modifier(Modifier::Control));
-    names.insert("shift", ParseItem::Modifier(Modifier::Shift));
+    for modifier in Modifier::ALL {
+        names.insert(modifier.to_string(), ParseItem::Modifier(modifier));
+    }
     for (k, v) in name_to_key_map() {
         names.insert(k, ParseItem::Key(v));
     }
@@ -152,10 +251,7 @@ impl KeySequence {
                     }
                 }
                 ParseItem::Key(k) => {
-                    seq.push(KeySequenceAtom {
-                        modifiers: cur_mods,
-                        key: *k,
-                    });
+                    seq.push(KeySequenceAtom::from_binding(*k, cur_mods));
                     cur_mods = Modifiers::new();
 
                     match state {
@@ -191,6 +287,20 @@ impl KeySequence {
     }
 }
 
+/// Formats the sequence in the syntax accepted by `parse`, e.g.
+/// "<ctrl>x+<ctrl>f".
+impl Display for KeySequence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (index, atom) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, "+")?;
+            }
+            write!(f, "{atom}")?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,12 +346,24 @@ mod tests {
             ])
         );
 
+        assert_eq!(
+            parse_key_sequence_as_items("<f35><pageup>"),
+            Ok(vec![
+                ParseItem::Key(Key::Function(35)),
+                ParseItem::Key(Key::PageUp),
+            ])
+        );
+
         // Error
 
         assert_eq!(
             parse_key_sequence_as_items("<invalid>"),
             Err(Error::InvalidName("invalid".into()))
         );
+        assert_eq!(
+            parse_key_sequence_as_items("<f36>"),
+            Err(Error::InvalidName("f36".into()))
+        );
     }
 
     #[test]
@@ -327,4 +449,62 @@ mod tests {
             Err(Error::UnexpectedKey(Key::Char('a')))
         );
     }
+
+    #[test]
+    fn test_display() {
+        for s in [
+            "a",
+            "<ctrl>x+<ctrl>f",
+            "<ctrl><alt><less>",
+            "<super><hyper><meta>a",
+            "<ctrl><shift>g",
+            "<ctrl>c+f",
+            "<ctrl><home>",
+            "<pagedown>",
+            "<shift><tab>",
+            "<f1>+<f12>",
+            "<delete>",
+        ] {
+            assert_eq!(KeySequence::parse(s).unwrap().to_string(), s);
+        }
+        assert_eq!(
+            KeySequence::parse("<shift><ctrl><left>")
+                .unwrap()
+                .to_string(),
+            "<ctrl><shift><left>"
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let seq = |s| KeySequence::parse(s).unwrap();
+        let event = |key, modifiers: &[Modifier]| {
+            let mut mods = Modifiers::new();
+            for m in modifiers {
+                mods.enable_modifier(*m);
+            }
+            KeySequence(vec![KeySequenceAtom::from_event(key, mods)])
+        };
+        let ctrl_shift = [Modifier::Control, Modifier::Shift];
+
+        // Shifted symbols match however they are written.
+        for s in ["<ctrl>?", "<ctrl><shift>?", "<ctrl><shift>/"] {
+            assert_eq!(seq(s), event(Key::Char('?'), &ctrl_shift));
+            assert_eq!(seq(s).to_string(), "<ctrl>?");
+        }
+        assert_eq!(seq("<shift><plus>"), seq("<plus>"));
+        assert_eq!(seq("<shift>="), event(Key::Plus, &[Modifier::Shift]));
+        assert_eq!(seq("<shift>,"), event(Key::Char('<'), &[]));
+
+        // Letters keep shift, including with caps lock.
+        for s in ["G", "<shift>g", "<shift>G"] {
+            assert_eq!(seq(s), event(Key::Char('G'), &[Modifier::Shift]));
+            assert_eq!(seq(s), event(Key::Char('G'), &[]));
+            assert_eq!(seq(s).to_string(), "<shift>g");
+        }
+        assert_ne!(seq("g"), seq("G"));
+
+        // Named keys keep shift too.
+        assert_ne!(seq("<shift><tab>"), seq("<tab>"));
+    }
 }
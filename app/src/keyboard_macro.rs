@@ -0,0 +1,240 @@
+//! Keyboard macros: actions recorded as they run, so that they can
+//! be replayed. A macro can be saved under a name in a YAML file in
+//! the config directory, and run again later by that name.
+
+use crate::action::Action;
+use crate::config;
+use anyhow::{Result, bail};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn default_count() -> usize {
+    1
+}
+
+/// One recorded action.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct MacroStep {
+    pub action: Action,
+
+    #[serde(default = "default_count")]
+    pub count: usize,
+}
+
+/// Saved macros by name.
+pub type SavedMacros = BTreeMap<String, Vec<MacroStep>>;
+
+/// Path of the file that saved macros are kept in.
+pub fn default_macros_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("macros.yml"))
+}
+
+/// Load saved macros. A missing file means there are none.
+pub fn load_macros(path: &Path) -> Result<SavedMacros> {
+    match fs::read_to_string(path) {
+        Ok(raw) => Ok(serde_yaml::from_str(&raw)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Ok(SavedMacros::new())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Save `steps` under `name`, replacing any macro with that name.
+pub fn save_macro(path: &Path, name: &str, steps: &[MacroStep]) -> Result<()> {
+    let mut macros = load_macros(path)?;
+    macros.insert(name.to_owned(), steps.to_vec());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_yaml::to_string(&macros)?)?;
+    Ok(())
+}
+
+/// How deeply macros can run other macros, so that a macro that runs
+/// itself stops.
+const MAX_REPLAY_DEPTH: usize = 8;
+
+/// Check if `action` is recorded into a macro. Commands for the macro
+/// being recorded aren't, and neither are actions sent by background
+/// work such as a running process. `RepeatLastAction` records the
+/// action it repeats instead, and counts are recorded as part of the
+/// action they apply to.
+fn is_recordable(action: &Action) -> bool {
+    !matches!(
+        action,
+        Action::StartMacro
+            | Action::EndMacro
+            | Action::ReplayMacro
+            | Action::SaveMacro
+            | Action::RepeatLastAction
+            | Action::UniversalArgument
+            | Action::CountDigit(_)
+            | Action::FoundFiles(..)
+            | Action::ShowKeyHints(_)
+            | Action::AppendToBuffer(..)
+            | Action::ProcessFinished(_)
+            | Action::Lsp(_)
+    )
+}
+
+#[derive(Default)]
+pub struct MacroRecorder {
+    /// Steps recorded so far, if recording.
+    recording: Option<Vec<MacroStep>>,
+
+    /// The last macro recorded.
+    last: Vec<MacroStep>,
+
+    /// Number of macros being replayed, counting macros run by
+    /// other macros.
+    replay_depth: usize,
+
+    /// File for saved macros, if not the default.
+    path: Option<PathBuf>,
+}
+
+impl MacroRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        if self.is_recording() {
+            bail!("already recording a macro");
+        }
+        self.recording = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Stop recording. Returns the number of steps recorded.
+    pub fn end(&mut self) -> Result<usize> {
+        let Some(steps) = self.recording.take() else {
+            bail!("not recording a macro");
+        };
+        self.last = steps;
+        Ok(self.last.len())
+    }
+
+    /// Record `action` if recording. Actions run by a replayed macro
+    /// aren't recorded separately.
+    pub fn record(&mut self, action: &Action, count: usize) {
+        if let Some(steps) = &mut self.recording
+            && self.replay_depth == 0
+            && is_recordable(action)
+        {
+            steps.push(MacroStep {
+                action: action.clone(),
+                count,
+            });
+        }
+    }
+
+    pub fn last(&self) -> &[MacroStep] {
+        &self.last
+    }
+
+    pub fn replay_depth(&self) -> usize {
+        self.replay_depth
+    }
+
+    /// Note that a replay is starting. Fails if macros are nested too
+    /// deeply.
+    pub fn begin_replay(&mut self) -> Result<()> {
+        if self.replay_depth >= MAX_REPLAY_DEPTH {
+            bail!("macros nested too deeply");
+        }
+        self.replay_depth += 1;
+        Ok(())
+    }
+
+    pub fn end_replay(&mut self) {
+        self.replay_depth = self.replay_depth.saturating_sub(1);
+    }
+
+    pub fn path(&self) -> Result<PathBuf> {
+        match &self.path {
+            Some(path) => Ok(path.clone()),
+            None => default_macros_path(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Boundary, Direction, Move};
+
+    #[test]
+    fn test_recorder() -> Result<()> {
+        let mut recorder = MacroRecorder::default();
+        assert!(recorder.end().is_err());
+
+        // Nothing is recorded until started.
+        recorder.record(&Action::Insert('a'), 1);
+        recorder.start()?;
+        assert!(recorder.start().is_err());
+        recorder.record(&Action::Insert('b'), 2);
+        recorder.record(&Action::ShowKeyHints(1), 1);
+        recorder.record(&Action::ReplayMacro, 1);
+
+        // Nor are the actions of a replayed macro.
+        recorder.begin_replay()?;
+        recorder.record(&Action::Insert('c'), 1);
+        recorder.end_replay();
+        assert_eq!(recorder.end()?, 1);
+        assert_eq!(
+            recorder.last(),
+            [MacroStep {
+                action: Action::Insert('b'),
+                count: 2,
+            }]
+        );
+
+        for _ in 0..MAX_REPLAY_DEPTH {
+            recorder.begin_replay()?;
+        }
+        assert!(recorder.begin_replay().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_macro() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("emma/macros.yml");
+        assert!(load_macros(&path)?.is_empty());
+
+        let steps = [
+            MacroStep {
+                action: Action::Move(
+                    Move::Boundary(Boundary::LineEnd),
+                    Direction::Inc,
+                ),
+                count: 1,
+            },
+            MacroStep {
+                action: Action::Insert(';'),
+                count: 3,
+            },
+        ];
+        save_macro(&path, "semi", &steps)?;
+        save_macro(&path, "other", &steps[..1])?;
+        let macros = load_macros(&path)?;
+        assert_eq!(macros["semi"], steps);
+        assert_eq!(macros["other"], steps[..1]);
+
+        // The count can be left out when editing the file by hand.
+        fs::write(&path, "x:\n- action: !Insert x\n")?;
+        assert_eq!(load_macros(&path)?["x"][0].count, 1);
+
+        Ok(())
+    }
+}
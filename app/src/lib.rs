@@ -1,10 +1,13 @@
-#![expect(clippy::new_without_default)]
-
 mod command_line;
 mod command_line_widget;
+mod dir_prompt;
+mod filter;
+mod fuzzy;
 mod key_map;
 mod key_sequence;
-mod path_chooser;
+mod keyboard_macro;
+mod macro_prompt;
+mod prefix_arg;
 mod process;
 mod search_widget;
 mod shell;
@@ -12,17 +15,33 @@ mod util;
 
 pub mod action;
 pub mod buffer;
+pub mod buffer_switcher;
+pub mod candidate_list;
+pub mod command_palette;
+pub mod completion;
 pub mod config;
+pub mod dir_listing;
+pub mod file_finder;
 pub mod grapheme;
 pub mod key;
+pub mod lsp;
 pub mod message;
+pub mod modal;
+pub mod mouse;
 pub mod overlay;
 pub mod pane_tree;
+pub mod path_chooser;
 pub mod rope;
 pub mod state;
 pub mod theme;
 pub mod widget;
+pub mod wrap;
 
 // TODO: location
 #[derive(Clone, Copy, Debug)]
 pub struct LineHeight(pub f64);
+
+/// Width of one column of text. Text is drawn in a monospace font,
+/// so this is the width of every character other than tabs.
+#[derive(Clone, Copy, Debug)]
+pub struct CharWidth(pub f64);
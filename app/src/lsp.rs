@@ -0,0 +1,803 @@
+//! Language Server Protocol client.
+//!
+//! Each server runs as a child process that speaks JSON-RPC over
+//! stdin/stdout. Messages are written to the server from the main
+//! thread. A background thread per server reads messages from the
+//! server and turns them into `Action::Lsp` events, which are sent
+//! back to the main thread through the message pipe.
+
+mod jsonrpc;
+pub mod protocol;
+
+use crate::action::Action;
+use crate::buffer::{AbsChar, Buffer, BufferId, TextChange};
+use crate::command_line::CommandLine;
+use crate::config::LanguageServerConfig;
+use crate::message::{Message, MessageWriter};
+use crate::pane_tree::PaneId;
+use crate::util;
+use anyhow::{Context, Result, anyhow};
+use protocol::{CompletionItem, Diagnostic, Location, Range};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{error, info};
+
+/// An event from a language server.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum LspEvent {
+    /// The server is ready to receive documents and requests.
+    Initialized {
+        server: String,
+        incremental_sync: bool,
+    },
+
+    /// New diagnostics for a file, replacing any previous ones.
+    Diagnostics {
+        path: PathBuf,
+        diagnostics: Vec<Diagnostic>,
+    },
+
+    /// Response to a go-to-definition request.
+    Definition(Option<Location>),
+
+    /// Response to a hover request.
+    Hover(Option<String>),
+
+    /// Response to a completion request made at `position` in a pane.
+    Completion {
+        buffer_id: BufferId,
+        pane_id: PaneId,
+        position: AbsChar,
+        items: Vec<CompletionItem>,
+    },
+
+    /// The server replied to the initialize request with an error.
+    InitializeFailed { server: String, message: String },
+
+    /// A request failed.
+    Error(String),
+}
+
+/// A request that is waiting for a response. This determines how the
+/// response is converted to an `LspEvent`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum PendingRequest {
+    Initialize,
+    Definition,
+    Hover,
+    Completion {
+        buffer_id: BufferId,
+        pane_id: PaneId,
+        position: AbsChar,
+    },
+}
+
+type PendingRequests = Arc<Mutex<HashMap<u64, PendingRequest>>>;
+
+/// A document that has been opened in a server.
+struct Document {
+    uri: String,
+    version: i32,
+}
+
+pub(crate) struct LanguageServer {
+    child: Child,
+    stdin: Arc<Mutex<ChildStdin>>,
+    pending: PendingRequests,
+    next_id: u64,
+    is_initialized: bool,
+    incremental_sync: bool,
+    language_id: String,
+    documents: HashMap<BufferId, Document>,
+}
+
+impl LanguageServer {
+    /// Start the server and send the initialize request. `name` is
+    /// used to identify the server in events.
+    fn start(
+        name: &str,
+        config: &LanguageServerConfig,
+        root: &Path,
+        message_writer: &MessageWriter,
+    ) -> Result<Self> {
+        let mut command_line = CommandLine::from_string(&config.command)?;
+        command_line.set_current_dir(Some(root.to_owned()));
+
+        let mut child = command_line
+            .to_command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| {
+                format!("failed to start language server: {}", config.command)
+            })?;
+        let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let stdout = child.stdout.take().unwrap();
+
+        let mut server = Self {
+            child,
+            stdin: stdin.clone(),
+            pending: PendingRequests::default(),
+            next_id: 0,
+            is_initialized: false,
+            incremental_sync: false,
+            language_id: config.language_id(),
+            documents: HashMap::new(),
+        };
+
+        let pending = server.pending.clone();
+        let name = name.to_owned();
+        let message_writer = message_writer.try_clone()?;
+        thread::spawn(move || {
+            read_messages(stdout, stdin, pending, name, message_writer);
+        });
+
+        let root_uri = protocol::path_to_uri(root);
+        server.send_request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{"uri": root_uri, "name": "root"}],
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": {},
+                        "publishDiagnostics": {},
+                        "definition": {},
+                        "hover": {"contentFormat": ["plaintext"]},
+                        "completion": {},
+                    },
+                },
+            }),
+            PendingRequest::Initialize,
+        )?;
+
+        Ok(server)
+    }
+
+    fn write(&self, msg: &Value) -> Result<()> {
+        let mut stdin = self.stdin.lock().unwrap();
+        jsonrpc::write_message(&mut *stdin, msg)
+    }
+
+    fn send_request(
+        &mut self,
+        method: &str,
+        params: Value,
+        request: PendingRequest,
+    ) -> Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.pending.lock().unwrap().insert(id, request);
+        self.write(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    /// Send a request that uses the current state of a document. Fails
+    /// if the server isn't ready yet.
+    pub(crate) fn request(
+        &mut self,
+        method: &str,
+        params: Value,
+        request: PendingRequest,
+    ) -> Result<()> {
+        if !self.is_initialized {
+            return Err(anyhow!("language server is still starting"));
+        }
+        self.send_request(method, params, request)
+    }
+
+    fn notify(&self, method: &str, params: Value) -> Result<()> {
+        self.write(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    fn set_initialized(&mut self, incremental_sync: bool) -> Result<()> {
+        self.is_initialized = true;
+        self.incremental_sync = incremental_sync;
+        self.notify("initialized", json!({}))
+    }
+
+    fn open_document(&mut self, buf: &mut Buffer) -> Result<()> {
+        let path = buf.path().context("buffer has no path")?;
+        let uri = protocol::path_to_uri(path);
+
+        // The server gets the full text, so any changes made before
+        // now are irrelevant.
+        buf.take_changes();
+
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": self.language_id,
+                    "version": 0,
+                    "text": buf.text().to_string(),
+                },
+            }),
+        )?;
+        self.documents
+            .insert(buf.id().clone(), Document { uri, version: 0 });
+        Ok(())
+    }
+
+    fn change_document(
+        &mut self,
+        buf: &Buffer,
+        changes: &[TextChange],
+    ) -> Result<()> {
+        let Some(doc) = self.documents.get_mut(buf.id()) else {
+            return Ok(());
+        };
+        doc.version += 1;
+
+        let content_changes: Vec<Value> =
+            if !self.incremental_sync || changes.contains(&TextChange::Reset) {
+                vec![json!({"text": buf.text().to_string()})]
+            } else {
+                changes
+                    .iter()
+                    .filter_map(|change| match change {
+                        TextChange::Edit { range, text } => {
+                            Some(json!({"range": range, "text": text}))
+                        }
+                        TextChange::Reset => None,
+                    })
+                    .collect()
+            };
+
+        let params = json!({
+            "textDocument": {"uri": doc.uri, "version": doc.version},
+            "contentChanges": content_changes,
+        });
+        self.notify("textDocument/didChange", params)
+    }
+
+    fn close_document(&mut self, buf_id: &BufferId) -> Result<()> {
+        if let Some(doc) = self.documents.remove(buf_id) {
+            self.notify(
+                "textDocument/didClose",
+                json!({"textDocument": {"uri": doc.uri}}),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LanguageServer {
+    fn drop(&mut self) {
+        // Not waiting for a response to a shutdown request, just
+        // tell the server to exit and make sure it does.
+        let _ = self.notify("exit", Value::Null);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// All the running language servers, and which buffers they handle.
+#[derive(Default)]
+pub(crate) struct LanguageServers {
+    /// Servers keyed by the command used to start them.
+    servers: HashMap<String, LanguageServer>,
+
+    /// Buffers that have already been checked for a language
+    /// server, along with the server's key (if any).
+    buffers: HashMap<BufferId, Option<String>>,
+}
+
+impl LanguageServers {
+    pub(crate) fn server_for_buffer_mut(
+        &mut self,
+        buf_id: &BufferId,
+    ) -> Option<&mut LanguageServer> {
+        let key = self.buffers.get(buf_id)?.as_ref()?;
+        self.servers.get_mut(key)
+    }
+
+    pub(crate) fn set_initialized(
+        &mut self,
+        server: &str,
+        incremental_sync: bool,
+    ) -> Result<()> {
+        self.servers
+            .get_mut(server)
+            .ok_or_else(|| anyhow!("unknown language server: {server}"))?
+            .set_initialized(incremental_sync)
+    }
+
+    /// Shut down a server that can't be used. The buffers it handled
+    /// are left without a server rather than starting it again.
+    pub(crate) fn remove_server(&mut self, server: &str) {
+        self.servers.remove(server);
+        for key in self.buffers.values_mut() {
+            if key.as_deref() == Some(server) {
+                *key = None;
+            }
+        }
+    }
+
+    /// Bring the servers up to date with the buffers. Servers are
+    /// started (according to `configs`) for any new buffers that need
+    /// them, newly-opened and deleted buffers are sent to their
+    /// server, and pending changes are sent for existing buffers.
+    pub(crate) fn sync(
+        &mut self,
+        buffers: &mut HashMap<BufferId, Buffer>,
+        configs: &[LanguageServerConfig],
+        message_writer: &MessageWriter,
+    ) {
+        if configs.is_empty() && self.servers.is_empty() {
+            return;
+        }
+
+        for (buf_id, buf) in buffers.iter_mut() {
+            // A buffer without a path can't be handled yet, but may get
+            // a path later, so it's not added to `self.buffers`.
+            if buf.path().is_none() {
+                continue;
+            }
+
+            if !self.buffers.contains_key(buf_id) {
+                let key =
+                    self.start_server_for_buffer(buf, configs, message_writer);
+                if key.is_some() {
+                    buf.track_changes();
+                }
+                self.buffers.insert(buf_id.clone(), key);
+            }
+
+            let Some(Some(key)) = self.buffers.get(buf_id) else {
+                continue;
+            };
+            let Some(server) = self.servers.get_mut(key) else {
+                continue;
+            };
+            if !server.is_initialized {
+                continue;
+            }
+
+            let result = if server.documents.contains_key(buf_id) {
+                let changes = buf.take_changes();
+                if changes.is_empty() {
+                    Ok(())
+                } else {
+                    server.change_document(buf, &changes)
+                }
+            } else {
+                server.open_document(buf)
+            };
+            if let Err(err) = result {
+                error!("failed to sync {buf_id} with language server: {err}");
+            }
+        }
+
+        // Close documents for deleted buffers.
+        self.buffers.retain(|buf_id, key| {
+            if buffers.contains_key(buf_id) {
+                return true;
+            }
+            if let Some(key) = key
+                && let Some(server) = self.servers.get_mut(key)
+                && let Err(err) = server.close_document(buf_id)
+            {
+                error!("failed to close {buf_id} in language server: {err}");
+            }
+            false
+        });
+    }
+
+    /// Get the key of the server for `buf`, starting the server if
+    /// it's not already running. Returns `None` if no server is
+    /// configured for the buffer or it couldn't be started.
+    fn start_server_for_buffer(
+        &mut self,
+        buf: &Buffer,
+        configs: &[LanguageServerConfig],
+        message_writer: &MessageWriter,
+    ) -> Option<String> {
+        let dir = buf.path()?.parent()?;
+        let syntax_name = buf.syntax_name();
+        let config = configs.iter().find(|c| c.syntax == syntax_name)?;
+
+        let key = config.command.clone();
+        if !self.servers.contains_key(&key) {
+            info!("starting language server: {key}");
+            match LanguageServer::start(
+                &key,
+                config,
+                &util::find_project_root(dir),
+                message_writer,
+            ) {
+                Ok(server) => {
+                    self.servers.insert(key.clone(), server);
+                }
+                Err(err) => {
+                    error!("{err:#}");
+                    return None;
+                }
+            }
+        }
+        Some(key)
+    }
+}
+
+/// Read messages from a server until it exits, forwarding events to
+/// the main thread.
+fn read_messages(
+    stdout: ChildStdout,
+    stdin: Arc<Mutex<ChildStdin>>,
+    pending: PendingRequests,
+    server: String,
+    message_writer: MessageWriter,
+) {
+    let mut reader = BufReader::new(stdout);
+    loop {
+        let msg = match jsonrpc::read_message(&mut reader) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => {
+                info!("language server exited: {server}");
+                return;
+            }
+            Err(err) => {
+                error!("failed to read from language server {server}: {err}");
+                return;
+            }
+        };
+
+        let method = msg.get("method").and_then(Value::as_str);
+        let event = match (method, msg.get("id")) {
+            // Request from the server.
+            (Some(method), Some(id)) => {
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": server_request_result(method, &msg["params"]),
+                });
+                let mut stdin = stdin.lock().unwrap();
+                if let Err(err) = jsonrpc::write_message(&mut *stdin, &response)
+                {
+                    error!("failed to respond to language server: {err}");
+                }
+                None
+            }
+            // Notification from the server.
+            (Some(method), None) => notification_event(method, &msg["params"]),
+            // Response to a request.
+            (None, Some(id)) => {
+                let request = id
+                    .as_u64()
+                    .and_then(|id| pending.lock().unwrap().remove(&id));
+                request
+                    .and_then(|request| response_event(request, &server, &msg))
+            }
+            (None, None) => None,
+        };
+
+        if let Some(event) = event
+            && message_writer
+                .send(Message::Action(Action::Lsp(event)))
+                .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Result to send for a request from the server. None of these
+/// requests are really supported, so the result is just a reasonable
+/// empty value.
+fn server_request_result(method: &str, params: &Value) -> Value {
+    if method == "workspace/configuration" {
+        let num_items = params["items"].as_array().map_or(0, Vec::len);
+        Value::Array(vec![Value::Null; num_items])
+    } else {
+        Value::Null
+    }
+}
+
+fn notification_event(method: &str, params: &Value) -> Option<LspEvent> {
+    match method {
+        "textDocument/publishDiagnostics" => {
+            let uri = params["uri"].as_str()?;
+            let path = match protocol::uri_to_path(uri) {
+                Ok(path) => path,
+                Err(err) => {
+                    error!("{err}");
+                    return None;
+                }
+            };
+            let diagnostics =
+                serde_json::from_value(params["diagnostics"].clone())
+                    .unwrap_or_else(|err| {
+                        error!("invalid diagnostics: {err}");
+                        Vec::new()
+                    });
+            Some(LspEvent::Diagnostics { path, diagnostics })
+        }
+        "window/logMessage" | "window/showMessage" => {
+            info!("language server: {}", params["message"]);
+            None
+        }
+        _ => None,
+    }
+}
+
+fn response_event(
+    request: PendingRequest,
+    server: &str,
+    msg: &Value,
+) -> Option<LspEvent> {
+    if let Some(error) = msg.get("error") {
+        let message = error["message"].as_str().unwrap_or("unknown error");
+        let message = format!("language server error: {message}");
+        return Some(if request == PendingRequest::Initialize {
+            LspEvent::InitializeFailed {
+                server: server.to_owned(),
+                message,
+            }
+        } else {
+            LspEvent::Error(message)
+        });
+    }
+
+    let result = &msg["result"];
+    let event = match request {
+        PendingRequest::Initialize => {
+            // The sync kind is either a number or in an object. 2
+            // means incremental.
+            let sync = &result["capabilities"]["textDocumentSync"];
+            let kind = sync.as_u64().or_else(|| sync["change"].as_u64());
+            LspEvent::Initialized {
+                server: server.to_owned(),
+                incremental_sync: kind == Some(2),
+            }
+        }
+        PendingRequest::Definition => {
+            LspEvent::Definition(parse_definition(result))
+        }
+        PendingRequest::Hover => {
+            let text = hover_text(&result["contents"]);
+            LspEvent::Hover(if text.is_empty() { None } else { Some(text) })
+        }
+        PendingRequest::Completion {
+            buffer_id,
+            pane_id,
+            position,
+        } => LspEvent::Completion {
+            buffer_id,
+            pane_id,
+            position,
+            items: parse_completion_items(result),
+        },
+    };
+    Some(event)
+}
+
+/// Get the first location from a definition response, which may be
+/// a location, a list of locations, or a list of location links.
+fn parse_definition(result: &Value) -> Option<Location> {
+    let value = match result {
+        Value::Array(items) => items.first()?,
+        value => value,
+    };
+
+    if let Some(uri) = value["targetUri"].as_str() {
+        let range: Range =
+            serde_json::from_value(value["targetSelectionRange"].clone())
+                .ok()?;
+        Some(Location {
+            uri: uri.to_owned(),
+            range,
+        })
+    } else {
+        serde_json::from_value(value.clone()).ok()
+    }
+}
+
+/// Flatten hover contents, which may be a string, a "marked string"
+/// object, markup content, or a list of those, to plain text.
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(hover_text)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Object(obj) => obj
+            .get("value")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned(),
+        _ => String::new(),
+    }
+}
+
+/// Get the items from a completion response, which may be a list of
+/// items or a completion list object.
+fn parse_completion_items(result: &Value) -> Vec<CompletionItem> {
+    let items = match result {
+        Value::Array(items) => items,
+        value => match value["items"].as_array() {
+            Some(items) => items,
+            None => return Vec::new(),
+        },
+    };
+
+    items
+        .iter()
+        .filter_map(|item| {
+            let label = item["label"].as_str()?;
+            let insert_text = item["textEdit"]["newText"]
+                .as_str()
+                .or_else(|| item["insertText"].as_str())
+                .unwrap_or(label);
+            Some(CompletionItem {
+                label: label.to_owned(),
+                insert_text: insert_text.to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::create_message_pipe;
+    use protocol::Position;
+
+    #[test]
+    fn test_response_event() {
+        let response =
+            |request, msg: Value| response_event(request, "server", &msg);
+
+        assert_eq!(
+            response(
+                PendingRequest::Initialize,
+                json!({"result": {"capabilities": {
+                    "textDocumentSync": {"change": 2},
+                }}}),
+            ),
+            Some(LspEvent::Initialized {
+                server: "server".to_owned(),
+                incremental_sync: true,
+            })
+        );
+
+        let range = json!({
+            "start": {"line": 1, "character": 2},
+            "end": {"line": 1, "character": 4},
+        });
+        let location = Some(Location {
+            uri: "file:///a".to_owned(),
+            range: Range {
+                start: Position {
+                    line: 1,
+                    character: 2,
+                },
+                end: Position {
+                    line: 1,
+                    character: 4,
+                },
+            },
+        });
+        assert_eq!(
+            response(
+                PendingRequest::Definition,
+                json!({"result": [{"uri": "file:///a", "range": range}]}),
+            ),
+            Some(LspEvent::Definition(location.clone()))
+        );
+        assert_eq!(
+            response(
+                PendingRequest::Definition,
+                json!({"result": [{
+                    "targetUri": "file:///a",
+                    "targetRange": range,
+                    "targetSelectionRange": range,
+                }]}),
+            ),
+            Some(LspEvent::Definition(location))
+        );
+        assert_eq!(
+            response(PendingRequest::Definition, json!({"result": null})),
+            Some(LspEvent::Definition(None))
+        );
+
+        assert_eq!(
+            response(
+                PendingRequest::Hover,
+                json!({"result": {"contents": [
+                    "a",
+                    {"language": "rust", "value": "b"},
+                ]}}),
+            ),
+            Some(LspEvent::Hover(Some("a\nb".to_owned())))
+        );
+
+        let buffer_id = BufferId::from_string("b".to_owned());
+        let pane_id = PaneId::new();
+        assert_eq!(
+            response(
+                PendingRequest::Completion {
+                    buffer_id: buffer_id.clone(),
+                    pane_id: pane_id.clone(),
+                    position: AbsChar(3),
+                },
+                json!({"result": {"items": [
+                    {"label": "a"},
+                    {"label": "b", "insertText": "b()"},
+                ]}}),
+            ),
+            Some(LspEvent::Completion {
+                buffer_id,
+                pane_id,
+                position: AbsChar(3),
+                items: vec![
+                    CompletionItem {
+                        label: "a".to_owned(),
+                        insert_text: "a".to_owned(),
+                    },
+                    CompletionItem {
+                        label: "b".to_owned(),
+                        insert_text: "b()".to_owned(),
+                    },
+                ],
+            })
+        );
+
+        assert_eq!(
+            response(
+                PendingRequest::Hover,
+                json!({"error": {"code": 1, "message": "oops"}}),
+            ),
+            Some(LspEvent::Error("language server error: oops".to_owned()))
+        );
+        assert_eq!(
+            response(
+                PendingRequest::Initialize,
+                json!({"error": {"code": 1, "message": "oops"}}),
+            ),
+            Some(LspEvent::InitializeFailed {
+                server: "server".to_owned(),
+                message: "language server error: oops".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_sync_buffer_without_path() -> Result<()> {
+        let (_reader, writer) = create_message_pipe()?;
+        let configs = [LanguageServerConfig {
+            syntax: "Plain Text".to_owned(),
+            command: "server".to_owned(),
+            language_id: None,
+        }];
+        let buf = Buffer::create_empty();
+        let mut buffers = HashMap::from([(buf.id().clone(), buf)]);
+
+        // The buffer isn't cached, so it's checked again once it has a
+        // path.
+        let mut servers = LanguageServers::default();
+        servers.sync(&mut buffers, &configs, &writer);
+        assert!(servers.buffers.is_empty());
+        assert!(servers.servers.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+//! JSON-RPC message framing as used by the Language Server Protocol:
+//! each message is a JSON body preceded by a `Content-Length` header
+//! and a blank line.
+
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+
+pub fn write_message(writer: &mut impl Write, msg: &Value) -> Result<()> {
+    let body = serde_json::to_vec(msg)?;
+    write!(writer, "Content-Length: {}\r\n\r\n", body.len())?;
+    writer.write_all(&body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one message. Returns `None` if the stream ended cleanly
+/// before the start of a message.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut content_length = None;
+    let mut is_first_line = true;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            if is_first_line {
+                return Ok(None);
+            }
+            bail!("unexpected end of stream in message header");
+        }
+        is_first_line = false;
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .context("invalid Content-Length")?,
+            );
+        }
+    }
+
+    let len = content_length.context("missing Content-Length header")?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let mut stream = Vec::new();
+        write_message(&mut stream, &json!({"id": 1, "method": "a"}))?;
+        write_message(&mut stream, &json!({"id": 2, "result": "ü"}))?;
+        assert!(stream.starts_with(b"Content-Length: 21\r\n\r\n{"));
+
+        let mut reader = stream.as_slice();
+        assert_eq!(
+            read_message(&mut reader)?,
+            Some(json!({"id": 1, "method": "a"}))
+        );
+        assert_eq!(
+            read_message(&mut reader)?,
+            Some(json!({"id": 2, "result": "ü"}))
+        );
+        assert_eq!(read_message(&mut reader)?, None);
+
+        // Other headers are ignored.
+        let mut reader =
+            "Content-Type: x\r\nContent-Length: 2\r\n\r\n{}".as_bytes();
+        assert_eq!(read_message(&mut reader)?, Some(json!({})));
+
+        assert!(read_message(&mut "Content-Length: 2\r\n".as_bytes()).is_err());
+        assert!(read_message(&mut "X: 1\r\n\r\n{}".as_bytes()).is_err());
+
+        Ok(())
+    }
+}
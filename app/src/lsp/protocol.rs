@@ -0,0 +1,179 @@
+//! The subset of the Language Server Protocol types used by the
+//! client, along with conversions to and from buffer positions.
+//!
+//! LSP positions are a zero-indexed line plus an offset within the
+//! line measured in UTF-16 code units, whereas buffer positions are
+//! char indices into the rope.
+
+use crate::rope::{AbsChar, AbsLine, Rope};
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::ops;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+impl Position {
+    pub fn from_abs_char(text: &Rope, pos: AbsChar) -> Self {
+        let line = text.char_to_line(pos);
+        let line_start = AbsChar(text.line_to_char(line));
+        let prefix = text.slice(line_start..pos).to_string();
+
+        Self {
+            line: line.0 as u32,
+            character: prefix.encode_utf16().count() as u32,
+        }
+    }
+
+    /// Convert to a char index in `text`. Positions past the end of a
+    /// line are clamped to the end of the line, and positions past
+    /// the last line are clamped to the end of the text.
+    pub fn to_abs_char(self, text: &Rope) -> AbsChar {
+        let line = AbsLine(self.line as usize);
+        if line > text.max_line_index() {
+            return AbsChar(text.len_chars());
+        }
+
+        let line_start = text.line_to_char(line);
+        let mut units = 0;
+        let mut offset = 0;
+        for c in text.line(line).to_string().chars() {
+            if units >= self.character as usize || c == '\n' {
+                break;
+            }
+            units += c.len_utf16();
+            offset += 1;
+        }
+        AbsChar(line_start + offset)
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn from_abs_range(text: &Rope, range: ops::Range<AbsChar>) -> Self {
+        Self {
+            start: Position::from_abs_char(text, range.start),
+            end: Position::from_abs_char(text, range.end),
+        }
+    }
+
+    pub fn to_abs_range(self, text: &Rope) -> ops::Range<AbsChar> {
+        self.start.to_abs_char(text)..self.end.to_abs_char(text)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Diagnostic {
+    pub range: Range,
+    /// 1 is an error, 2 a warning, 3 information, and 4 a hint. If
+    /// not set, the client decides.
+    #[serde(default)]
+    pub severity: Option<u8>,
+    pub message: String,
+}
+
+/// A completion candidate. Unlike the protocol's completion item, the
+/// text to insert is always filled in.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct CompletionItem {
+    pub label: String,
+    pub insert_text: String,
+}
+
+/// Convert an absolute path to a "file://" URI.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            write!(uri, "%{b:02X}").unwrap();
+        }
+    }
+    uri
+}
+
+/// Convert a "file://" URI to a path.
+pub fn uri_to_path(uri: &str) -> Result<PathBuf> {
+    let encoded = uri
+        .strip_prefix("file://")
+        .ok_or_else(|| anyhow!("unsupported URI: {uri}"))?;
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next().unwrap_or(0), iter.next().unwrap_or(0)];
+            let Some(decoded) = std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            else {
+                bail!("invalid escape in URI: {uri}");
+            };
+            bytes.push(decoded);
+        } else {
+            bytes.push(b);
+        }
+    }
+    Ok(PathBuf::from(OsStr::from_bytes(&bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        // "𝄞" is two UTF-16 code units.
+        let text = Rope::from_str("ab\n𝄞cd\n");
+
+        let pos = |line, character| Position { line, character };
+        assert_eq!(Position::from_abs_char(&text, AbsChar(1)), pos(0, 1));
+        assert_eq!(Position::from_abs_char(&text, AbsChar(3)), pos(1, 0));
+        assert_eq!(Position::from_abs_char(&text, AbsChar(4)), pos(1, 2));
+        assert_eq!(Position::from_abs_char(&text, AbsChar(7)), pos(2, 0));
+
+        assert_eq!(pos(0, 1).to_abs_char(&text), AbsChar(1));
+        assert_eq!(pos(1, 2).to_abs_char(&text), AbsChar(4));
+        assert_eq!(pos(1, 3).to_abs_char(&text), AbsChar(5));
+        // Clamped to the end of the line.
+        assert_eq!(pos(0, 10).to_abs_char(&text), AbsChar(2));
+        // Clamped to the end of the text.
+        assert_eq!(pos(5, 0).to_abs_char(&text), AbsChar(7));
+    }
+
+    #[test]
+    fn test_uri() -> Result<()> {
+        let path = Path::new("/tmp/a b/c%d.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/a%20b/c%25d.rs");
+        assert_eq!(uri_to_path(&uri)?, path);
+
+        assert!(uri_to_path("http://example.com").is_err());
+        assert!(uri_to_path("file:///a%zz").is_err());
+
+        Ok(())
+    }
+}
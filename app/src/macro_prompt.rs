@@ -0,0 +1,109 @@
+//! Prompt for the name of a keyboard macro.
+
+use crate::LineHeight;
+use crate::action::Action;
+use crate::buffer::Buffer;
+use crate::key_map::KeyMap;
+use crate::pane_tree::{Pane, Rect};
+use crate::widget::Widget;
+use anyhow::{Result, bail};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MacroOp {
+    /// Save the last recorded macro under the name.
+    Save,
+
+    /// Run the saved macro with the name.
+    Run,
+}
+
+pub struct MacroPrompt {
+    buffer: Buffer,
+    pane: Pane,
+    rect: Rect,
+    op: MacroOp,
+
+    /// How many times to run the macro.
+    count: usize,
+}
+
+impl MacroPrompt {
+    pub fn new(op: MacroOp, count: usize) -> Self {
+        let mut buffer = Buffer::create_empty();
+        let pane = Pane::create_for_widget(&mut buffer);
+        Self {
+            buffer,
+            pane,
+            rect: Rect::default(),
+            op,
+            count,
+        }
+    }
+
+    pub fn op(&self) -> MacroOp {
+        self.op
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The name typed in the prompt.
+    pub fn name(&self) -> Result<String> {
+        let name = self.buffer.text().to_string();
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("no name given");
+        }
+        Ok(name.to_owned())
+    }
+}
+
+impl Widget for MacroPrompt {
+    fn get_keymap(&self) -> Result<KeyMap> {
+        KeyMap::from_pairs(
+            "macro_prompt",
+            vec![("<ret>", Action::Confirm), ("<ctrl>m", Action::Confirm)]
+                .into_iter(),
+        )
+    }
+
+    fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffer
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_buffer_mut(&mut self) -> (&Pane, &mut Buffer) {
+        (&self.pane, &mut self.buffer)
+    }
+
+    fn pane_mut_buffer_mut(&mut self) -> (&mut Pane, &mut Buffer) {
+        (&mut self.pane, &mut self.buffer)
+    }
+
+    fn recalc_layout(&mut self, width: f64, line_height: LineHeight) {
+        self.rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height: line_height.0 * 2.0,
+        };
+        self.pane.set_rect(Rect {
+            x: 0.0,
+            y: line_height.0,
+            width,
+            height: line_height.0,
+        });
+    }
+
+    fn rect(&self) -> &Rect {
+        &self.rect
+    }
+}
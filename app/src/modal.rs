@@ -0,0 +1,185 @@
+//! Optional vi-like modal editing.
+//!
+//! In normal mode, plain keys move the cursor and operate on text
+//! instead of inserting it. An operator such as `d` waits for a
+//! motion, then applies to the text between the cursor and where the
+//! motion would have moved it. Typing an operator twice applies it to
+//! whole lines. Counts typed before the operator and the motion are
+//! multiplied, so "2d3j" covers six lines. Insert mode
+//! uses the regular bindings, and visual mode selects text from where
+//! it was entered so that an operator can apply to it.
+
+use crate::action::{Action, Boundary, Direction, Move};
+use crate::key_map::KeyMap;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Normal => "NORMAL",
+                Self::Insert => "INSERT",
+                Self::Visual => "VISUAL",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Operator {
+    /// Delete the text.
+    Delete,
+
+    /// Delete the text, then switch to insert mode.
+    Change,
+}
+
+pub struct ModalState {
+    mode: Mode,
+
+    /// Operator waiting for a motion, with the count typed before
+    /// it.
+    operator: Option<(Operator, usize)>,
+}
+
+impl Default for ModalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModalState {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            operator: None,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Switch to `mode`, dropping any pending operator.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.operator = None;
+    }
+
+    /// Make `operator` wait for a motion. If it was already waiting,
+    /// it should apply to whole lines, and the number of lines is
+    /// returned.
+    pub fn push_operator(
+        &mut self,
+        operator: Operator,
+        count: usize,
+    ) -> Option<usize> {
+        match self.operator.take() {
+            Some((pending, pending_count)) if pending == operator => {
+                Some(pending_count * count)
+            }
+            _ => {
+                self.operator = Some((operator, count));
+                None
+            }
+        }
+    }
+
+    pub fn take_operator(&mut self) -> Option<(Operator, usize)> {
+        self.operator.take()
+    }
+}
+
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+/// Keys for `mode`, layered on top of the base keymap. Insert mode
+/// only adds a way back to normal mode.
+pub fn keymap(mode: Mode) -> Result<KeyMap> {
+    let mut pairs = vec![("<esc>", Action::SetMode(Mode::Normal))];
+    if mode == Mode::Insert {
+        return KeyMap::from_pairs("insert", pairs.into_iter());
+    }
+
+    pairs.extend([
+        ("i", Action::SetMode(Mode::Insert)),
+        ("v", Action::SetMode(Mode::Visual)),
+        (
+            "h",
+            Action::Move(Move::Boundary(Boundary::Grapheme), Direction::Dec),
+        ),
+        (
+            "l",
+            Action::Move(Move::Boundary(Boundary::Grapheme), Direction::Inc),
+        ),
+        ("j", Action::Move(Move::Line, Direction::Inc)),
+        ("k", Action::Move(Move::Line, Direction::Dec)),
+        (
+            "<shift>$",
+            Action::Move(Move::Boundary(Boundary::LineEnd), Direction::Inc),
+        ),
+        (
+            "g+g",
+            Action::Move(Move::Boundary(Boundary::BufferEnd), Direction::Dec),
+        ),
+        (
+            "<shift>g",
+            Action::Move(Move::Boundary(Boundary::BufferEnd), Direction::Inc),
+        ),
+        ("<ctrl>f", Action::Move(Move::Page, Direction::Inc)),
+        ("<ctrl>b", Action::Move(Move::Page, Direction::Dec)),
+        ("<ctrl>e", Action::Scroll(Direction::Inc)),
+        ("<ctrl>y", Action::Scroll(Direction::Dec)),
+        ("z+z", Action::Recenter),
+        ("x", Action::Delete(Boundary::Grapheme, Direction::Inc)),
+        ("d", Action::Operator(Operator::Delete)),
+        ("c", Action::Operator(Operator::Change)),
+        ("u", Action::Undo),
+        ("<ctrl>r", Action::Redo),
+    ]);
+    pairs.extend(
+        DIGITS
+            .into_iter()
+            .zip(0..)
+            .map(|(keys, digit)| (keys, Action::CountDigit(digit))),
+    );
+    let name = if mode == Mode::Visual {
+        "visual"
+    } else {
+        "normal"
+    };
+    KeyMap::from_pairs(name, pairs.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modal_state() {
+        let mut modal = ModalState::new();
+        assert_eq!(modal.mode(), Mode::Normal);
+
+        assert_eq!(modal.push_operator(Operator::Delete, 2), None);
+        assert_eq!(modal.push_operator(Operator::Delete, 3), Some(6));
+        assert_eq!(modal.take_operator(), None);
+        assert_eq!(modal.push_operator(Operator::Delete, 1), None);
+        assert_eq!(modal.push_operator(Operator::Change, 1), None);
+        assert_eq!(modal.take_operator(), Some((Operator::Change, 1)));
+
+        // Switching modes drops a pending operator.
+        modal.push_operator(Operator::Delete, 1);
+        modal.set_mode(Mode::Insert);
+        assert_eq!(modal.take_operator(), None);
+    }
+}
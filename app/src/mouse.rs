@@ -0,0 +1,29 @@
+//! Mouse input, translated from the shell's toolkit events so that
+//! `AppState` doesn't depend on the toolkit.
+
+/// A position in the window, in pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseEvent {
+    /// The primary button was pressed. `clicks` counts quick
+    /// successive presses, e.g. two for a double click.
+    Press { point: Point, clicks: u32 },
+
+    /// The pointer moved while the primary button was held.
+    Drag(Point),
+
+    /// The wheel was turned over `point` by `steps` notches. Negative
+    /// steps scroll up.
+    Scroll { point: Point, steps: i32 },
+}
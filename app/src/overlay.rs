@@ -1,7 +1,14 @@
 use crate::LineHeight;
+use crate::action::FilterOutput;
 use crate::buffer::Buffer;
+use crate::buffer_switcher::BufferSwitcher;
 use crate::command_line_widget::CommandLineWidget;
+use crate::command_palette::CommandPalette;
+use crate::completion::CompletionWidget;
+use crate::dir_prompt::{DirOp, DirPrompt};
+use crate::file_finder::FileFinder;
 use crate::key_map::KeyMap;
+use crate::macro_prompt::{MacroOp, MacroPrompt};
 use crate::pane_tree::{Pane, Rect};
 use crate::path_chooser::PathChooser;
 use crate::search_widget::SearchWidget;
@@ -11,7 +18,14 @@ use anyhow::Result;
 pub enum Overlay {
     OpenFile(PathChooser),
     RunProcess(CommandLineWidget),
+    Filter(CommandLineWidget, FilterOutput),
     Search(SearchWidget),
+    Complete(CompletionWidget),
+    SwitchBuffer(BufferSwitcher),
+    FindFile(FileFinder),
+    DirEntry(DirPrompt),
+    Macro(MacroPrompt),
+    CommandPalette(CommandPalette),
 }
 
 impl Overlay {
@@ -19,7 +33,24 @@ impl Overlay {
         match self {
             Self::OpenFile(_) => "Open file:",
             Self::RunProcess(_) => "Run process:",
+            Self::Filter(_, FilterOutput::Replace) => "Filter region:",
+            Self::Filter(_, FilterOutput::NewBuffer) => {
+                "Filter region to new buffer:"
+            }
             Self::Search(_) => "Search:",
+            Self::Complete(_) => "Complete:",
+            Self::SwitchBuffer(_) => "Switch to buffer:",
+            Self::FindFile(_) => "Find file in project:",
+            Self::DirEntry(prompt) => match prompt.op() {
+                DirOp::Create => "Create (end with / for a directory):",
+                DirOp::Rename(_) => "Rename to:",
+                DirOp::Delete(_) => "Delete? Type yes to confirm:",
+            },
+            Self::Macro(prompt) => match prompt.op() {
+                MacroOp::Save => "Save macro as:",
+                MacroOp::Run => "Run macro:",
+            },
+            Self::CommandPalette(_) => "Run command:",
         }
     }
 
@@ -27,7 +58,14 @@ impl Overlay {
         match self {
             Self::OpenFile(w) => w,
             Self::RunProcess(w) => w,
+            Self::Filter(w, _) => w,
             Self::Search(w) => w,
+            Self::Complete(w) => w,
+            Self::SwitchBuffer(w) => w,
+            Self::FindFile(w) => w,
+            Self::DirEntry(w) => w,
+            Self::Macro(w) => w,
+            Self::CommandPalette(w) => w,
         }
     }
 
@@ -35,7 +73,14 @@ impl Overlay {
         match self {
             Self::OpenFile(w) => w,
             Self::RunProcess(w) => w,
+            Self::Filter(w, _) => w,
             Self::Search(w) => w,
+            Self::Complete(w) => w,
+            Self::SwitchBuffer(w) => w,
+            Self::FindFile(w) => w,
+            Self::DirEntry(w) => w,
+            Self::Macro(w) => w,
+            Self::CommandPalette(w) => w,
         }
     }
 }
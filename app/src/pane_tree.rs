@@ -2,12 +2,18 @@
 #![allow(clippy::use_self)]
 
 use crate::action::{Boundary, Direction, Move};
-use crate::buffer::{AbsChar, Buffer, BufferId, BufferMap, RelLine};
-use crate::rope::AbsLine;
+use crate::buffer::{
+    AbsChar, Buffer, BufferId, BufferMap, LinePosition, RelLine,
+    line_len_without_newline,
+};
+use crate::grapheme::prev_grapheme_boundary;
+use crate::rope::{AbsLine, RelChar};
+use crate::wrap::{self, Truncated};
 use crate::{LineHeight, util};
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::ops::Range;
 
 #[derive(
     Debug, Default, Clone, Eq, Hash, PartialEq, Deserialize, Serialize,
@@ -32,6 +38,15 @@ pub enum Orientation {
     Vertical,
 }
 
+impl Orientation {
+    fn rotated(self) -> Self {
+        match self {
+            Self::Horizontal => Self::Vertical,
+            Self::Vertical => Self::Horizontal,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Rect {
     pub x: f64,
@@ -44,6 +59,50 @@ impl Rect {
     pub fn bottom(&self) -> f64 {
         self.y + self.height
     }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x
+            && x < self.x + self.width
+            && y >= self.y
+            && y < self.bottom()
+    }
+
+    /// Size of the rect along the axis that `orientation` lays
+    /// children out on.
+    fn extent(&self, orientation: Orientation) -> f64 {
+        match orientation {
+            Orientation::Horizontal => self.width,
+            Orientation::Vertical => self.height,
+        }
+    }
+
+    /// Check if a pane with this rect is at least the minimum pane
+    /// size, allowing for rounding errors in the layout.
+    fn fits_min_size(&self) -> bool {
+        let epsilon = 1e-6;
+        self.width >= MIN_PANE_WIDTH - epsilon
+            && self.height >= MIN_PANE_HEIGHT - epsilon
+    }
+}
+
+/// Number of lines that stay in view when moving by a page.
+const PAGE_CONTEXT_LINES: usize = 2;
+
+/// Minimum pane width in pixels.
+const MIN_PANE_WIDTH: f64 = 120.0;
+
+/// Minimum pane height in pixels.
+const MIN_PANE_HEIGHT: f64 = 60.0;
+
+/// Space in pixels between the top of a pane and its first line of
+/// text.
+pub const PANE_TEXT_MARGIN: f64 = 2.0;
+
+fn min_pane_extent(orientation: Orientation) -> f64 {
+    match orientation {
+        Orientation::Horizontal => MIN_PANE_WIDTH,
+        Orientation::Vertical => MIN_PANE_HEIGHT,
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -54,11 +113,43 @@ pub struct Pane {
     rect: Rect,
 
     top_line: AbsLine,
+
+    /// With wrapping on, the row of `top_line` shown at the top of
+    /// the pane.
+    #[serde(default)]
+    top_row: usize,
+
+    /// Whether long lines are wrapped onto more rows rather than cut
+    /// off at the edge of the pane.
+    #[serde(default)]
+    wrap: bool,
+
+    /// With wrapping off, the number of columns scrolled off the left
+    /// edge of the pane.
+    #[serde(default)]
+    left_column: usize,
+
+    /// Number of columns of text that fit in the pane's width. Set on
+    /// layout.
+    #[serde(skip)]
+    columns: usize,
+
     is_active: bool,
     show_info_bar: bool,
     is_cursor_visible: bool,
 }
 
+/// A row of text drawn in a pane: a whole line, or part of a wrapped
+/// line.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct VisualRow {
+    pub line: AbsLine,
+
+    /// Index of the row within the line. Always zero if wrapping is
+    /// off.
+    pub row: usize,
+}
+
 impl Pane {
     // Create a one-off pane for use in a widget (e.g. open_file).
     pub fn create_for_widget(buffer: &mut Buffer) -> Self {
@@ -67,6 +158,10 @@ impl Pane {
             buffer_id: buffer.id().clone(),
             rect: Rect::default(),
             top_line: AbsLine::zero(),
+            top_row: 0,
+            wrap: false,
+            left_column: 0,
+            columns: 0,
             is_active: true,
             show_info_bar: false,
             is_cursor_visible: true,
@@ -106,6 +201,29 @@ impl Pane {
 
     pub fn set_top_line(&mut self, top_line: AbsLine) {
         self.top_line = top_line;
+        self.top_row = 0;
+    }
+
+    pub fn top_row(&self) -> usize {
+        self.top_row
+    }
+
+    pub fn is_wrapped(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+        self.top_row = 0;
+        self.left_column = 0;
+    }
+
+    pub fn left_column(&self) -> usize {
+        self.left_column
+    }
+
+    pub fn set_columns(&mut self, columns: usize) {
+        self.columns = columns;
     }
 
     pub fn is_active(&self) -> bool {
@@ -131,19 +249,243 @@ impl Pane {
             old_buf.remove_cursor(self);
         }
 
+        // Pick up where the buffer was last viewed.
         let new_buf = buffers.get_mut(new_buf_id).unwrap();
-        new_buf.set_cursor(self.id(), AbsChar::default());
-
-        // TODO: think about what should happen to the cursor when a
-        // buffer is viewed by only one pane, then that pane switches
-        // away from the buffer so no panes are showing it, then a
-        // pane shows the buffer again. It aught to put the cursor at
-        // the same place, but currently we don't have anything like
-        // that...
+        let view = new_buf.last_view();
+        new_buf.set_cursor(self.id(), view.cursor);
+        self.set_top_line(view.top_line);
+        self.left_column = 0;
 
         self.buffer_id = new_buf_id.clone();
     }
 
+    /// Number of text lines that fit in the pane, not counting the
+    /// info bar. Always at least one.
+    pub fn visible_lines(&self, line_height: LineHeight) -> usize {
+        let lines = (self.rect.height / line_height.0).floor() as usize;
+        lines.saturating_sub(usize::from(self.show_info_bar)).max(1)
+    }
+
+    /// Get the char range of each row that `line` is drawn on, not
+    /// counting the newline. Without wrapping, that's the whole line.
+    pub fn rows(&self, buf: &Buffer, line: AbsLine) -> Vec<Range<usize>> {
+        let len = line_len_without_newline(buf.text(), line);
+        if self.wrap {
+            wrap::wrap_line(&buf.text().line(line), len, self.columns)
+        } else {
+            std::iter::once(0..len).collect()
+        }
+    }
+
+    /// Get the part of `line` that's drawn when wrapping is off.
+    /// Panes that haven't been laid out, such as an overlay's, show
+    /// the whole line.
+    pub fn truncate_line(&self, buf: &Buffer, line: AbsLine) -> Truncated {
+        let len = line_len_without_newline(buf.text(), line);
+        if self.columns == 0 {
+            return Truncated {
+                range: 0..len,
+                indent: 0,
+                cut_left: false,
+                cut_right: false,
+            };
+        }
+        wrap::truncate_line(
+            &buf.text().line(line),
+            len,
+            self.left_column,
+            self.columns,
+        )
+    }
+
+    /// Get the row at the top of the pane. This is clamped to the
+    /// buffer, in case the text got shorter since the pane scrolled.
+    fn top(&self, buf: &Buffer) -> VisualRow {
+        let line = self.top_line.min(buf.text().max_line_index());
+        VisualRow {
+            line,
+            row: self.top_row.min(self.rows(buf, line).len() - 1),
+        }
+    }
+
+    fn set_top(&mut self, top: VisualRow) {
+        self.top_line = top.line;
+        self.top_row = top.row;
+    }
+
+    /// Get the row `count` rows after or before `row`, stopping at the
+    /// start or end of the buffer.
+    fn step_rows(
+        &self,
+        buf: &Buffer,
+        mut row: VisualRow,
+        count: usize,
+        dir: Direction,
+    ) -> VisualRow {
+        let max_line = buf.text().max_line_index();
+        for _ in 0..count {
+            match dir {
+                Direction::Dec if row.row > 0 => row.row -= 1,
+                Direction::Dec if row.line > AbsLine::zero() => {
+                    row.line = row.line.saturating_sub(RelLine::new(1));
+                    row.row = self.rows(buf, row.line).len() - 1;
+                }
+                Direction::Inc
+                    if row.row + 1 < self.rows(buf, row.line).len() =>
+                {
+                    row.row += 1
+                }
+                Direction::Inc if row.line < max_line => {
+                    row.line += 1;
+                    row.row = 0;
+                }
+                _ => break,
+            }
+        }
+        row
+    }
+
+    /// Get the row that `pos` is drawn on, and the column within it.
+    /// A position at the end of a wrapped row is at the start of the
+    /// next row.
+    fn row_at(&self, buf: &Buffer, pos: AbsChar) -> (VisualRow, usize) {
+        let line_pos = LinePosition::from_abs_char(pos, buf);
+        let offset = line_pos.offset.0;
+        let rows = self.rows(buf, line_pos.line);
+        let row = rows
+            .iter()
+            .position(|row| offset < row.end)
+            .unwrap_or(rows.len() - 1);
+        let column = wrap::column_at_offset(
+            &buf.text().line(line_pos.line),
+            rows[row].clone(),
+            offset,
+        );
+        let row = VisualRow {
+            line: line_pos.line,
+            row,
+        };
+        (row, column)
+    }
+
+    /// Get the position drawn at `column` in `row`, or the end of the
+    /// row if it's shorter. The end of a wrapped row is drawn on the
+    /// next row, so the last grapheme is used instead.
+    fn position_in_row(
+        &self,
+        buf: &Buffer,
+        row: VisualRow,
+        column: usize,
+    ) -> AbsChar {
+        let line = buf.text().line(row.line);
+        let rows = self.rows(buf, row.line);
+        let index = row.row.min(rows.len() - 1);
+        let range = rows[index].clone();
+        let mut offset = wrap::offset_at_column(&line, range.clone(), column);
+        if offset == range.end && index + 1 < rows.len() {
+            offset = prev_grapheme_boundary(&line, offset).0.max(range.start);
+        }
+        LinePosition {
+            line: row.line,
+            offset: RelChar(offset),
+        }
+        .to_abs_char(buf)
+    }
+
+    /// Get the position drawn at `column` on the `index`th row in view.
+    pub fn position_at(
+        &self,
+        buf: &Buffer,
+        index: usize,
+        column: usize,
+    ) -> LinePosition {
+        let column = column + self.left_column;
+        let row = self.step_rows(buf, self.top(buf), index, Direction::Inc);
+        LinePosition::from_abs_char(self.position_in_row(buf, row, column), buf)
+    }
+
+    /// Get the index of `row` among the rows in view, if it's in view.
+    fn index_in_view(
+        &self,
+        buf: &Buffer,
+        row: VisualRow,
+        line_height: LineHeight,
+    ) -> Option<usize> {
+        let mut cur = self.top(buf);
+        if row < cur {
+            return None;
+        }
+        for index in 0..self.visible_lines(line_height) {
+            if cur == row {
+                return Some(index);
+            }
+            let next = self.step_rows(buf, cur, 1, Direction::Inc);
+            if next == cur {
+                break;
+            }
+            cur = next;
+        }
+        None
+    }
+
+    /// Move the cursor by `count` rows, keeping its column.
+    fn move_cursor_rows(&self, buf: &mut Buffer, count: usize, dir: Direction) {
+        if !self.wrap {
+            buf.move_cursor_lines(self.id(), count, dir);
+            return;
+        }
+
+        let (row, column) = self.row_at(buf, buf.cursor(self.id()));
+        let row = self.step_rows(buf, row, count, dir);
+        let pos = self.position_in_row(buf, row, column);
+        buf.set_cursor(self.id(), pos);
+    }
+
+    /// Move the cursor in `buf` by `count` steps. Lines are the rows
+    /// drawn in the pane, so with wrapping a long line is several.
+    /// A page is the pane's height, less a couple of lines kept in
+    /// view for context.
+    pub fn move_cursor(
+        &self,
+        buf: &mut Buffer,
+        step: Move,
+        dir: Direction,
+        count: usize,
+        line_height: LineHeight,
+    ) {
+        match step {
+            Move::Boundary(_) => {
+                for _ in 0..count {
+                    buf.move_cursor(self.id(), step, dir);
+                }
+            }
+            Move::Line => self.move_cursor_rows(buf, count, dir),
+            Move::Page => {
+                let page = self
+                    .visible_lines(line_height)
+                    .saturating_sub(PAGE_CONTEXT_LINES)
+                    .max(1);
+                self.move_cursor_rows(buf, page * count, dir);
+            }
+        }
+    }
+
+    /// Range of lines that are at least partly in view.
+    fn visible_range(
+        &self,
+        buf: &Buffer,
+        line_height: LineHeight,
+    ) -> Range<usize> {
+        let top = self.top(buf);
+        let bottom = self.step_rows(
+            buf,
+            top,
+            self.visible_lines(line_height) - 1,
+            Direction::Inc,
+        );
+        top.line.0..bottom.line.0 + 1
+    }
+
     // If the cursor is not visible in the pane, scroll it so that the
     // cursor is vertically in the middle of the pane.
     pub fn maybe_rescroll(
@@ -152,19 +494,145 @@ impl Pane {
         pos: AbsChar,
         line_height: LineHeight,
     ) {
-        let line_height = line_height.0;
-        let line_index = buf.text().char_to_line(pos);
+        let (row, _) = self.row_at(buf, pos);
+        if self.index_in_view(buf, row, line_height).is_none() {
+            self.scroll_to_row(buf, row, RecenterPosition::Middle, line_height);
+        }
+        self.follow_column(buf, pos);
+    }
+
+    /// With wrapping off, if `pos` is scrolled off either side of the
+    /// pane, scroll sideways so that it's in the middle, or all the
+    /// way to the left if that brings it into view. The columns taken
+    /// by the indicators for cut off text don't count as in view.
+    pub fn follow_column(&mut self, buf: &Buffer, pos: AbsChar) {
+        // Until the first layout it's not known what fits.
+        if self.wrap || self.columns == 0 {
+            return;
+        }
 
-        let top =
-            (line_index.0 as f64 - self.top_line.0 as f64 + 1.0) * line_height;
-        let bottom = top + line_height;
-        if top < self.rect.y || bottom > self.rect.bottom() {
-            // Scroll current line to middle of the screen.
-            let half_height = self.rect.height / 2.0;
-            let half_height_in_lines =
-                (half_height / line_height).round() as usize;
-            self.top_line =
-                line_index.saturating_sub(RelLine::new(half_height_in_lines));
+        let (_, column) = self.row_at(buf, pos);
+        let first = self.left_column + usize::from(self.left_column > 0);
+        let end = self.left_column + self.columns.saturating_sub(1);
+        if column < first || column >= end {
+            self.left_column = if column < self.columns.saturating_sub(1) {
+                0
+            } else {
+                column - self.columns / 2
+            };
+        }
+    }
+
+    /// Scroll so that `row` is at `position` in the pane.
+    fn scroll_to_row(
+        &mut self,
+        buf: &Buffer,
+        row: VisualRow,
+        position: RecenterPosition,
+        line_height: LineHeight,
+    ) {
+        let visible_lines = self.visible_lines(line_height);
+        let above = match position {
+            RecenterPosition::Middle => visible_lines / 2,
+            RecenterPosition::Top => 0,
+            RecenterPosition::Bottom => visible_lines - 1,
+        };
+        self.set_top(self.step_rows(buf, row, above, Direction::Dec));
+    }
+
+    /// Scroll so that the cursor's row is at `position` in the pane.
+    pub fn recenter(
+        &mut self,
+        buf: &Buffer,
+        position: RecenterPosition,
+        line_height: LineHeight,
+    ) {
+        let (row, _) = self.row_at(buf, buf.cursor(self.id()));
+        self.scroll_to_row(buf, row, position, line_height);
+    }
+
+    /// Scroll by `rows` without moving the cursor, unless the cursor
+    /// would go out of view. In that case it moves to the nearest row
+    /// in view. Scrolling stops at the start and end of the buffer.
+    pub fn scroll(
+        &mut self,
+        buf: &mut Buffer,
+        rows: usize,
+        dir: Direction,
+        line_height: LineHeight,
+    ) {
+        let top = self.step_rows(buf, self.top(buf), rows, dir);
+        self.set_top(top);
+
+        let (row, column) = self.row_at(buf, buf.cursor(self.id()));
+        if self.index_in_view(buf, row, line_height).is_some() {
+            return;
+        }
+        let row = if row < top {
+            top
+        } else {
+            let last = self.visible_lines(line_height) - 1;
+            self.step_rows(buf, top, last, Direction::Inc)
+        };
+        let pos = self.position_in_row(buf, row, column);
+        buf.set_cursor(self.id(), pos);
+        self.follow_column(buf, pos);
+    }
+
+    /// Describe how far through `buf` the pane is scrolled: "All" if
+    /// the whole buffer is in view, "Top" or "Bot" at either end, and
+    /// otherwise the percentage of the buffer above the view.
+    pub fn scroll_position(
+        &self,
+        buf: &Buffer,
+        line_height: LineHeight,
+    ) -> String {
+        let len_lines = buf.text().len_lines();
+        let visible = self.visible_range(buf, line_height);
+        match (visible.start == 0, visible.end >= len_lines) {
+            (true, true) => "All".to_owned(),
+            (true, false) => "Top".to_owned(),
+            (false, true) => "Bot".to_owned(),
+            (false, false) => {
+                format!("{}%", visible.start * 100 / len_lines)
+            }
+        }
+    }
+
+    /// Get the part of `buf` in view, as fractions of its length, for
+    /// drawing a scrollbar. Returns `None` if the whole buffer is in
+    /// view.
+    pub fn visible_fraction(
+        &self,
+        buf: &Buffer,
+        line_height: LineHeight,
+    ) -> Option<Range<f64>> {
+        let len_lines = buf.text().len_lines();
+        let visible = self.visible_range(buf, line_height);
+        if visible.start == 0 && visible.end >= len_lines {
+            return None;
+        }
+        let fraction =
+            |line: usize| line.min(len_lines) as f64 / len_lines as f64;
+        Some(fraction(visible.start)..fraction(visible.end))
+    }
+}
+
+/// Where `Pane::recenter` puts the cursor's line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecenterPosition {
+    Middle,
+    Top,
+    Bottom,
+}
+
+impl RecenterPosition {
+    /// The position to use when recentering again.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Middle => Self::Top,
+            Self::Top => Self::Bottom,
+            Self::Bottom => Self::Middle,
         }
     }
 }
@@ -174,13 +642,108 @@ enum SplitResult {
     Single(Node),
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 struct Internal {
     orientation: Orientation,
     children: Vec<Node>,
+
+    /// Relative size of each child. Trees persisted before weights
+    /// were added don't have them, so `PaneTree::cleanup_after_load`
+    /// fills them in.
+    #[serde(default)]
+    weights: Vec<f64>,
 }
 
-#[derive(Deserialize, Serialize)]
+impl Internal {
+    /// Create a node where all children have the same size.
+    fn new(orientation: Orientation, children: Vec<Node>) -> Self {
+        Self {
+            orientation,
+            weights: vec![1.0; children.len()],
+            children,
+        }
+    }
+
+    /// Get the area covered by the node, as of the last layout.
+    fn rect(&self) -> Rect {
+        let (Some(first), Some(last)) =
+            (self.children.first(), self.children.last())
+        else {
+            return Rect::default();
+        };
+        let first = first.rect();
+        let last = last.rect();
+        Rect {
+            x: first.x,
+            y: first.y,
+            width: last.x + last.width - first.x,
+            height: last.bottom() - first.y,
+        }
+    }
+
+    /// Grow (positive `delta`) or shrink (negative `delta`) the child
+    /// at `index` by `delta` pixels, taking space from or giving space
+    /// to its siblings. No child is made smaller than its minimum
+    /// size.
+    fn resize_child(&mut self, index: usize, delta: f64) {
+        let orientation = self.orientation;
+        let mut sizes: Vec<f64> = self
+            .children
+            .iter()
+            .map(|c| c.rect().extent(orientation))
+            .collect();
+        let mins: Vec<f64> = self
+            .children
+            .iter()
+            .map(|c| c.min_extent(orientation))
+            .collect();
+        if sizes.iter().sum::<f64>() <= 0.0 {
+            // Layout hasn't been calculated yet.
+            return;
+        }
+
+        if delta > 0.0 {
+            let slack = |j: usize| (sizes[j] - mins[j]).max(0.0);
+            let total_slack: f64 =
+                (0..sizes.len()).filter(|j| *j != index).map(slack).sum();
+            let delta = delta.min(total_slack);
+            if delta <= 0.0 {
+                return;
+            }
+            let taken: Vec<f64> = (0..sizes.len())
+                .map(|j| {
+                    if j == index {
+                        0.0
+                    } else {
+                        delta * slack(j) / total_slack
+                    }
+                })
+                .collect();
+            for (size, taken) in sizes.iter_mut().zip(taken) {
+                *size -= taken;
+            }
+            sizes[index] += delta;
+        } else {
+            let delta = (-delta).min(sizes[index] - mins[index]);
+            if delta <= 0.0 {
+                return;
+            }
+            sizes[index] -= delta;
+            let others: f64 = sizes.iter().sum::<f64>() - sizes[index];
+            for (j, size) in sizes.iter_mut().enumerate() {
+                if j != index {
+                    *size += delta * *size / others;
+                }
+            }
+        }
+
+        let total: f64 = sizes.iter().sum();
+        let len = sizes.len() as f64;
+        self.weights = sizes.into_iter().map(|s| s * len / total).collect();
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 enum Node {
     Internal(Internal),
     Leaf(Pane),
@@ -262,59 +825,303 @@ impl Node {
         }
     }
 
+    fn contains(&self, id: &PaneId) -> bool {
+        self.panes().iter().any(|pane| pane.id == *id)
+    }
+
+    /// Get the area covered by the node, as of the last layout.
+    fn rect(&self) -> Rect {
+        match self {
+            Node::Leaf(leaf) => leaf.rect.clone(),
+            Node::Internal(internal) => internal.rect(),
+        }
+    }
+
+    /// Get the smallest size the node can have along the axis that
+    /// `orientation` lays children out on. Children share their
+    /// parent's space in proportion to their weights, so this depends
+    /// on the current weights.
+    fn min_extent(&self, orientation: Orientation) -> f64 {
+        match self {
+            Node::Leaf(_) => min_pane_extent(orientation),
+            Node::Internal(internal) => {
+                let total: f64 = internal.weights.iter().sum();
+                internal
+                    .children
+                    .iter()
+                    .zip(&internal.weights)
+                    .map(|(child, weight)| {
+                        let min = child.min_extent(orientation);
+                        if internal.orientation == orientation {
+                            min * total / weight
+                        } else {
+                            min
+                        }
+                    })
+                    .fold(0.0, f64::max)
+            }
+        }
+    }
+
+    /// Get the number of panes side by side along the axis that
+    /// `orientation` lays children out on.
+    fn num_panes_along(&self, orientation: Orientation) -> usize {
+        match self {
+            Node::Leaf(_) => 1,
+            Node::Internal(internal) => {
+                let counts = internal
+                    .children
+                    .iter()
+                    .map(|c| c.num_panes_along(orientation));
+                if internal.orientation == orientation {
+                    counts.sum()
+                } else {
+                    counts.max().unwrap_or(1)
+                }
+            }
+        }
+    }
+
+    /// Set the weights so that panes side by side have the same size.
+    fn balance(&mut self) {
+        if let Node::Internal(internal) = self {
+            let orientation = internal.orientation;
+            internal.weights = internal
+                .children
+                .iter()
+                .map(|c| c.num_panes_along(orientation) as f64)
+                .collect();
+            for child in &mut internal.children {
+                child.balance();
+            }
+        }
+    }
+
+    /// Reset the weights of any node where they don't make sense,
+    /// e.g. a tree persisted before weights were added.
+    fn fix_weights(&mut self) {
+        if let Node::Internal(internal) = self {
+            if internal.weights.len() != internal.children.len()
+                || internal.weights.iter().any(|w| !w.is_finite() || *w <= 0.0)
+            {
+                internal.weights = vec![1.0; internal.children.len()];
+            }
+            for child in &mut internal.children {
+                child.fix_weights();
+            }
+        }
+    }
+
+    /// Resize the pane with `id` by `delta` pixels along the axis that
+    /// `orientation` lays children out on. This resizes the pane's
+    /// closest ancestor with that orientation. Returns false if there
+    /// is no such ancestor.
+    fn resize(
+        &mut self,
+        id: &PaneId,
+        orientation: Orientation,
+        delta: f64,
+    ) -> bool {
+        let Node::Internal(internal) = self else {
+            return false;
+        };
+        let Some(index) = internal.children.iter().position(|c| c.contains(id))
+        else {
+            return false;
+        };
+        if internal.children[index].resize(id, orientation, delta) {
+            return true;
+        }
+        if internal.orientation != orientation {
+            return false;
+        }
+        internal.resize_child(index, delta);
+        true
+    }
+
+    /// Get the parent of the pane with `id`.
+    fn parent_mut(&mut self, id: &PaneId) -> Option<&mut Internal> {
+        let Node::Internal(internal) = self else {
+            return None;
+        };
+        if internal
+            .children
+            .iter()
+            .any(|c| c.leaf().map(|p| &p.id) == Some(id))
+        {
+            return Some(internal);
+        }
+        internal.children.iter_mut().find_map(|c| c.parent_mut(id))
+    }
+
+    /// Merge children that have the same orientation as their parent
+    /// into the parent, keeping the size of each pane.
+    fn normalize(self) -> Self {
+        let Node::Internal(internal) = self else {
+            return self;
+        };
+
+        let orientation = internal.orientation;
+        let mut children = Vec::new();
+        let mut weights = Vec::new();
+        for (child, weight) in
+            internal.children.into_iter().zip(internal.weights)
+        {
+            match child.normalize() {
+                Node::Internal(inner) if inner.orientation == orientation => {
+                    let inner_total: f64 = inner.weights.iter().sum();
+                    for (inner_child, inner_weight) in
+                        inner.children.into_iter().zip(inner.weights)
+                    {
+                        children.push(inner_child);
+                        weights.push(inner_weight * weight / inner_total);
+                    }
+                }
+                child => {
+                    children.push(child);
+                    weights.push(weight);
+                }
+            }
+        }
+        Node::Internal(Internal {
+            orientation,
+            children,
+            weights,
+        })
+    }
+
     fn recalc_layout(&mut self, rect: Rect) {
         match self {
             Node::Leaf(leaf) => {
                 leaf.rect = rect;
             }
-            Node::Internal(internal) => match internal.orientation {
-                Orientation::Horizontal => {
-                    let mut x = rect.x;
-                    let width = rect.width / internal.children.len() as f64;
-                    for child in &mut internal.children {
-                        child.recalc_layout(Rect {
-                            x,
+            Node::Internal(internal) => {
+                let total: f64 = internal.weights.iter().sum();
+                let mut offset = 0.0;
+                for (child, weight) in
+                    internal.children.iter_mut().zip(&internal.weights)
+                {
+                    let fraction = weight / total;
+                    let child_rect = match internal.orientation {
+                        Orientation::Horizontal => Rect {
+                            x: rect.x + offset * rect.width,
                             y: rect.y,
-                            width,
+                            width: fraction * rect.width,
                             height: rect.height,
-                        });
-                        x += width;
-                    }
-                }
-                Orientation::Vertical => {
-                    let mut y = rect.y;
-                    let height = rect.height / internal.children.len() as f64;
-                    for child in &mut internal.children {
-                        child.recalc_layout(Rect {
+                        },
+                        Orientation::Vertical => Rect {
                             x: rect.x,
-                            y,
+                            y: rect.y + offset * rect.height,
                             width: rect.width,
-                            height,
-                        });
-                        y += height;
+                            height: fraction * rect.height,
+                        },
+                    };
+                    child.recalc_layout(child_rect);
+                    offset += fraction;
+                }
+            }
+        }
+    }
+
+    /// Get the pane that should become active when the pane with
+    /// `id` is closed: the last pane of the preceding sibling, or the
+    /// first pane of the following sibling.
+    fn neighbor(&self, id: &PaneId) -> Option<&Pane> {
+        let Node::Internal(internal) = self else {
+            return None;
+        };
+        let index = internal
+            .children
+            .iter()
+            .position(|child| child.leaf().map(|pane| &pane.id) == Some(id));
+        match index {
+            Some(0) => internal.children.get(1)?.panes().first().copied(),
+            Some(index) => internal.children[index - 1].panes().last().copied(),
+            None => internal.children.iter().find_map(|c| c.neighbor(id)),
+        }
+    }
+
+    /// Remove the pane with `id`. Returns `None` if this node is that
+    /// pane. An internal node left with a single child is replaced by
+    /// that child, and a child with the same orientation as its parent
+    /// is merged into the parent.
+    fn remove(self, id: &PaneId) -> Option<Self> {
+        let internal = match self {
+            Node::Leaf(pane) if pane.id == *id => return None,
+            Node::Leaf(_) => return Some(self),
+            Node::Internal(internal) => internal,
+        };
+
+        // The removed pane's space is shared by its siblings in
+        // proportion to their weights.
+        let orientation = internal.orientation;
+        let mut children = Vec::new();
+        let mut weights = Vec::new();
+        for (child, weight) in
+            internal.children.into_iter().zip(internal.weights)
+        {
+            match child.remove(id) {
+                Some(Node::Internal(inner))
+                    if inner.orientation == orientation =>
+                {
+                    let inner_total: f64 = inner.weights.iter().sum();
+                    for (inner_child, inner_weight) in
+                        inner.children.into_iter().zip(inner.weights)
+                    {
+                        children.push(inner_child);
+                        weights.push(inner_weight * weight / inner_total);
                     }
                 }
-            },
+                Some(child) => {
+                    children.push(child);
+                    weights.push(weight);
+                }
+                None => {}
+            }
+        }
+
+        match children.len() {
+            0 => None,
+            1 => children.pop(),
+            _ => Some(Node::Internal(Internal {
+                orientation,
+                children,
+                weights,
+            })),
         }
     }
 
+    /// Split the pane with `active_pane_id` in two, putting
+    /// `new_pane` after it (`Direction::Inc`) or before it
+    /// (`Direction::Dec`).
     fn split(
         self,
         orientation: Orientation,
         active_pane_id: &PaneId,
         new_pane: Pane,
+        new_pane_side: Direction,
     ) -> SplitResult {
         if self.leaf().map(|pane| &pane.id) == Some(active_pane_id) {
-            return SplitResult::Split([self, Self::Leaf(new_pane)]);
+            return SplitResult::Split(match new_pane_side {
+                Direction::Inc => [self, Self::Leaf(new_pane)],
+                Direction::Dec => [Self::Leaf(new_pane), self],
+            });
         }
 
         if let Node::Internal(mut internal) = self {
             let mut new_children: Vec<Self> = Vec::new();
+            let mut new_weights = Vec::new();
             let mut new_orientation = internal.orientation;
             let num_children = internal.children.len();
-            for child in internal.children {
-                match child.split(orientation, active_pane_id, new_pane.clone())
-                {
+            for (child, weight) in
+                internal.children.into_iter().zip(internal.weights)
+            {
+                match child.split(
+                    orientation,
+                    active_pane_id,
+                    new_pane.clone(),
+                    new_pane_side,
+                ) {
                     SplitResult::Split([child1, child2]) => {
                         if num_children == 1 {
                             // Node has only one child, so just align
@@ -325,22 +1132,30 @@ impl Node {
 
                         if orientation == new_orientation {
                             // Orientation matches, so just add the
-                            // new child in the appropriate place.
+                            // new child in the appropriate place. The
+                            // two halves share the original space.
                             new_children.push(child1);
                             new_children.push(child2);
+                            new_weights.push(weight / 2.0);
+                            new_weights.push(weight / 2.0);
                         } else {
                             // Orientation doesn't match so a new
                             // internal node is needed.
-                            new_children.push(Self::Internal(Internal {
+                            new_children.push(Self::Internal(Internal::new(
                                 orientation,
-                                children: vec![child1, child2],
-                            }));
+                                vec![child1, child2],
+                            )));
+                            new_weights.push(weight);
                         }
                     }
-                    SplitResult::Single(child) => new_children.push(child),
+                    SplitResult::Single(child) => {
+                        new_children.push(child);
+                        new_weights.push(weight);
+                    }
                 }
             }
             internal.children = new_children;
+            internal.weights = new_weights;
             internal.orientation = new_orientation;
             SplitResult::Single(Self::Internal(internal))
         } else {
@@ -361,6 +1176,10 @@ impl PaneTree {
             buffer_id: initial_buffer.id().clone(),
             rect: Rect::default(),
             top_line: AbsLine::zero(),
+            top_row: 0,
+            wrap: false,
+            left_column: 0,
+            columns: 0,
             is_active: true,
             show_info_bar: true,
             is_cursor_visible: true,
@@ -395,6 +1214,8 @@ impl PaneTree {
             // No panes active, arbitrarily pick one to make active.
             self.root.panes_mut()[0].is_active = true;
         }
+
+        self.root.fix_weights();
     }
 
     pub fn recalc_layout(&mut self, width: f64, height: f64) {
@@ -436,18 +1257,26 @@ impl PaneTree {
         // though.
         std::mem::replace(
             &mut self.root,
-            Node::Internal(Internal {
-                orientation: Orientation::Horizontal,
-                children: Vec::new(),
-            }),
+            Node::Internal(Internal::new(Orientation::Horizontal, Vec::new())),
         )
     }
 
-    pub fn split(&mut self, orientation: Orientation, buf: &mut Buffer) {
+    /// Split the active pane in two. Fails if the resulting panes
+    /// would be smaller than the minimum pane size. Before the first
+    /// layout the pane's size is unknown, so it isn't checked.
+    pub fn split(
+        &mut self,
+        orientation: Orientation,
+        buf: &mut Buffer,
+    ) -> Result<()> {
         let active_id;
         let new_pane;
         {
             let active = self.active();
+            let size = active.rect.extent(orientation);
+            if size > 0.0 && size / 2.0 < min_pane_extent(orientation) {
+                bail!("pane is too small to split");
+            }
             active_id = active.id.clone();
             new_pane = Pane {
                 id: PaneId::new(),
@@ -458,15 +1287,230 @@ impl PaneTree {
             buf.set_cursor(new_pane.id(), buf.cursor(active.id()));
         }
 
+        self.insert_pane(orientation, &active_id, new_pane, Direction::Inc);
+        Ok(())
+    }
+
+    /// Insert `pane` next to the pane with `target_id`, splitting the
+    /// target's space.
+    fn insert_pane(
+        &mut self,
+        orientation: Orientation,
+        target_id: &PaneId,
+        pane: Pane,
+        side: Direction,
+    ) {
         // TODO: make just have this method take self instead?
         let root = self.take_root();
-        self.root = match root.split(orientation, &active_id, new_pane) {
+        self.root = match root.split(orientation, target_id, pane, side) {
             SplitResult::Single(single) => single,
-            SplitResult::Split([child1, child2]) => Node::Internal(Internal {
-                orientation,
-                children: vec![child1, child2],
-            }),
+            SplitResult::Split([child1, child2]) => {
+                Node::Internal(Internal::new(orientation, vec![child1, child2]))
+            }
+        };
+    }
+
+    /// Find the pane next to the active pane in the given direction,
+    /// e.g. `Orientation::Horizontal` and `Direction::Dec` for the
+    /// pane to the left. If several panes border the active pane on
+    /// that side, the one sharing the longest edge wins.
+    fn find_neighbor(
+        &self,
+        orientation: Orientation,
+        direction: Direction,
+    ) -> Option<&Pane> {
+        // Allow for rounding errors in the layout.
+        let epsilon = 0.5;
+
+        let active = self.active().rect();
+        // Distance from the active pane's edge to `rect`, and how much
+        // they overlap on the other axis.
+        let measure = |rect: &Rect| -> (f64, f64) {
+            let (gap, overlap) = match orientation {
+                Orientation::Horizontal => (
+                    match direction {
+                        Direction::Dec => active.x - (rect.x + rect.width),
+                        Direction::Inc => rect.x - (active.x + active.width),
+                    },
+                    rect.bottom().min(active.bottom()) - rect.y.max(active.y),
+                ),
+                Orientation::Vertical => (
+                    match direction {
+                        Direction::Dec => active.y - rect.bottom(),
+                        Direction::Inc => rect.y - active.bottom(),
+                    },
+                    (rect.x + rect.width).min(active.x + active.width)
+                        - rect.x.max(active.x),
+                ),
+            };
+            (gap, overlap)
+        };
+
+        self.panes()
+            .into_iter()
+            .filter(|pane| !pane.is_active())
+            .filter_map(|pane| {
+                let (gap, overlap) = measure(pane.rect());
+                (gap > -epsilon && overlap > epsilon)
+                    .then_some((pane, gap, overlap))
+            })
+            .min_by(|a, b| {
+                a.1.total_cmp(&b.1).then_with(|| b.2.total_cmp(&a.2))
+            })
+            .map(|(pane, _, _)| pane)
+    }
+
+    fn neighbor_id(
+        &self,
+        orientation: Orientation,
+        direction: Direction,
+    ) -> Result<PaneId> {
+        self.find_neighbor(orientation, direction)
+            .map(|pane| pane.id.clone())
+            .ok_or_else(|| anyhow!("no pane in that direction"))
+    }
+
+    /// Make the pane next to the active pane in the given direction
+    /// active.
+    pub fn focus(
+        &mut self,
+        orientation: Orientation,
+        direction: Direction,
+    ) -> Result<()> {
+        let neighbor_id = self.neighbor_id(orientation, direction)?;
+        self.set_active(&neighbor_id);
+        Ok(())
+    }
+
+    /// Swap the active pane with the pane next to it in the given
+    /// direction. The panes keep their buffers, cursors and scroll
+    /// positions, so from the user's point of view the buffers trade
+    /// places. The active pane stays active.
+    pub fn swap(
+        &mut self,
+        orientation: Orientation,
+        direction: Direction,
+    ) -> Result<()> {
+        let neighbor_id = self.neighbor_id(orientation, direction)?;
+
+        let mut panes = self.panes_mut();
+        let active = panes.iter().position(|p| p.is_active()).unwrap();
+        let neighbor = panes.iter().position(|p| p.id == neighbor_id).unwrap();
+        let (first, second) = (active.min(neighbor), active.max(neighbor));
+        let (left, right) = panes.split_at_mut(second);
+        let (a, b) = (&mut *left[first], &mut *right[0]);
+        std::mem::swap(a, b);
+        std::mem::swap(&mut a.rect, &mut b.rect);
+        Ok(())
+    }
+
+    /// Move the active pane across the pane next to it in the given
+    /// direction, splitting that pane's space.
+    pub fn move_active(
+        &mut self,
+        orientation: Orientation,
+        direction: Direction,
+    ) -> Result<()> {
+        let neighbor_id = self.neighbor_id(orientation, direction)?;
+        let neighbor = self.find_neighbor(orientation, direction).unwrap();
+        if neighbor.rect.extent(orientation) / 2.0
+            < min_pane_extent(orientation)
+        {
+            bail!("pane is too small to split");
+        }
+
+        let active = self.active().clone();
+        let root = self.take_root();
+        self.root = root
+            .remove(&active.id)
+            .expect("pane tree has more than one pane");
+        self.insert_pane(orientation, &neighbor_id, active, direction);
+        Ok(())
+    }
+
+    /// Switch the split containing the active pane between horizontal
+    /// and vertical. Fails if the panes in the split would end up
+    /// smaller than the minimum pane size.
+    pub fn rotate_split(&mut self) -> Result<()> {
+        let active_id = self.active().id.clone();
+        let mut root = self.root.clone();
+        let Some(parent) = root.parent_mut(&active_id) else {
+            bail!("no split to rotate");
+        };
+        parent.orientation = parent.orientation.rotated();
+
+        // The rotated split may now have the same orientation as its
+        // parent or children.
+        let mut root = root.normalize();
+
+        // Before the first layout the size is unknown, so it isn't
+        // checked.
+        let rect = self.root.rect();
+        if rect.width > 0.0 {
+            root.recalc_layout(rect);
+            if !root.panes().iter().all(|pane| pane.rect.fits_min_size()) {
+                bail!("not enough room to rotate split");
+            }
+        }
+
+        self.root = root;
+        Ok(())
+    }
+
+    /// Grow (positive `delta`) or shrink (negative `delta`) the active
+    /// pane by `delta` pixels along the axis that `orientation` lays
+    /// panes out on. The new size takes effect at the next layout.
+    pub fn resize_active(
+        &mut self,
+        orientation: Orientation,
+        delta: f64,
+    ) -> Result<()> {
+        let active_id = self.active().id.clone();
+        if !self.root.resize(&active_id, orientation, delta) {
+            bail!("no split to resize in that direction");
         }
+        Ok(())
+    }
+
+    /// Make all panes that are side by side the same size.
+    pub fn balance(&mut self) {
+        self.root.balance();
+    }
+
+    /// Close the active pane and make its neighbor active. The pane's
+    /// cursor is removed from its buffer. The last pane can't be
+    /// closed.
+    pub fn close_active(&mut self, buffers: &mut BufferMap) -> Result<()> {
+        let active = self.active();
+        let active_id = active.id.clone();
+        let Some(neighbor) = self.root.neighbor(&active_id) else {
+            bail!("can't close the only pane");
+        };
+        let neighbor_id = neighbor.id.clone();
+        if let Some(buf) = buffers.get_mut(&active.buffer_id) {
+            buf.remove_cursor(active);
+        }
+
+        let root = self.take_root();
+        self.root = root
+            .remove(&active_id)
+            .expect("pane tree has more than one pane");
+        self.set_active(&neighbor_id);
+        Ok(())
+    }
+
+    /// Close all panes other than the active one.
+    pub fn close_other_panes(&mut self, buffers: &mut BufferMap) {
+        for pane in self.panes() {
+            if !pane.is_active()
+                && let Some(buf) = buffers.get_mut(&pane.buffer_id)
+            {
+                buf.remove_cursor(pane);
+            }
+        }
+
+        let active = self.active().clone();
+        self.root = Node::Leaf(active);
     }
 
     pub fn make_previous_pane_active(&mut self) {
@@ -499,9 +1543,552 @@ impl PaneTree {
         self.set_active(&pane_id);
     }
 
-    fn set_active(&mut self, id: &PaneId) {
+    /// Make the pane with `id` active.
+    pub fn set_active(&mut self, id: &PaneId) {
         for pane in self.panes_mut() {
             pane.is_active = &pane.id == id;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::{HashMap, HashSet};
+
+    const WIDTH: f64 = 1600.0;
+    const HEIGHT: f64 = 1000.0;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Split(Orientation),
+        Close,
+        CloseOthers,
+        Next,
+        Previous,
+        Resize(Orientation, f64),
+        Balance,
+        Focus(Orientation, Direction),
+        Swap(Orientation, Direction),
+        Move(Orientation, Direction),
+        Rotate,
+    }
+
+    fn orientation() -> impl Strategy<Value = Orientation> {
+        prop_oneof![Just(Orientation::Horizontal), Just(Orientation::Vertical)]
+    }
+
+    fn direction() -> impl Strategy<Value = Direction> {
+        prop_oneof![Just(Direction::Dec), Just(Direction::Inc)]
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            3 => orientation().prop_map(Op::Split),
+            2 => Just(Op::Close),
+            1 => Just(Op::CloseOthers),
+            1 => Just(Op::Next),
+            1 => Just(Op::Previous),
+            2 => (orientation(), -500.0..500.0).prop_map(|(o, d)| Op::Resize(o, d)),
+            1 => Just(Op::Balance),
+            2 => (orientation(), direction()).prop_map(|(o, d)| Op::Focus(o, d)),
+            1 => (orientation(), direction()).prop_map(|(o, d)| Op::Swap(o, d)),
+            1 => (orientation(), direction()).prop_map(|(o, d)| Op::Move(o, d)),
+            1 => Just(Op::Rotate),
+        ]
+    }
+
+    fn check_node(node: &Node, parent_orientation: Option<Orientation>) {
+        if let Node::Internal(internal) = node {
+            assert!(internal.children.len() >= 2);
+            assert_eq!(internal.children.len(), internal.weights.len());
+            assert!(internal.weights.iter().all(|w| *w > 0.0));
+            assert_ne!(Some(internal.orientation), parent_orientation);
+            for child in &internal.children {
+                check_node(child, Some(internal.orientation));
+            }
+        }
+    }
+
+    fn check_tree(tree: &mut PaneTree, buffers: &BufferMap) {
+        check_node(&tree.root, None);
+
+        let panes = tree.panes();
+        assert_eq!(panes.iter().filter(|p| p.is_active()).count(), 1);
+
+        // Every pane has a cursor, and closed panes don't.
+        let pane_ids: HashSet<&PaneId> = panes.iter().map(|p| p.id()).collect();
+        assert_eq!(pane_ids.len(), panes.len());
+        let cursor_ids: HashSet<&PaneId> =
+            buffers.values().flat_map(|b| b.cursors().keys()).collect();
+        assert_eq!(pane_ids, cursor_ids);
+
+        // The panes cover the whole area.
+        tree.recalc_layout(WIDTH, HEIGHT);
+        let area: f64 = tree
+            .panes()
+            .iter()
+            .map(|p| p.rect().width * p.rect().height)
+            .sum();
+        assert!((area - WIDTH * HEIGHT).abs() < 1e-3);
+    }
+
+    /// Check if all panes are at least the minimum size.
+    fn panes_fit(tree: &PaneTree) -> bool {
+        tree.panes().iter().all(|p| {
+            p.rect().width >= MIN_PANE_WIDTH - 1e-6
+                && p.rect().height >= MIN_PANE_HEIGHT - 1e-6
+        })
+    }
+
+    fn create_tree() -> (PaneTree, BufferMap, BufferId) {
+        let mut buf = Buffer::create_empty();
+        let mut tree = PaneTree::new(&mut buf);
+        tree.recalc_layout(WIDTH, HEIGHT);
+        let buf_id = buf.id().clone();
+        (tree, HashMap::from([(buf_id.clone(), buf)]), buf_id)
+    }
+
+    fn active_index(tree: &PaneTree) -> usize {
+        tree.panes().iter().position(|p| p.is_active()).unwrap()
+    }
+
+    #[test]
+    fn test_close_focus() {
+        let (mut tree, mut buffers, buf_id) = create_tree();
+        assert!(tree.close_active(&mut buffers).is_err());
+
+        // Three panes side by side, close the middle one.
+        let buf = buffers.get_mut(&buf_id).unwrap();
+        tree.split(Orientation::Horizontal, buf).unwrap();
+        tree.split(Orientation::Horizontal, buf).unwrap();
+        tree.make_next_pane_active();
+        let first_id = tree.panes()[0].id().clone();
+        tree.close_active(&mut buffers).unwrap();
+        assert_eq!(tree.active().id(), &first_id);
+
+        // Closing the first pane focuses the pane after it.
+        let second_id = tree.panes()[1].id().clone();
+        tree.close_active(&mut buffers).unwrap();
+        assert_eq!(tree.active().id(), &second_id);
+        assert!(matches!(tree.root, Node::Leaf(_)));
+
+        // Close other panes.
+        let buf = buffers.get_mut(&buf_id).unwrap();
+        tree.split(Orientation::Vertical, buf).unwrap();
+        tree.split(Orientation::Horizontal, buf).unwrap();
+        tree.make_next_pane_active();
+        let active_id = tree.active().id().clone();
+        tree.close_other_panes(&mut buffers);
+        assert_eq!(tree.panes().len(), 1);
+        assert_eq!(tree.active().id(), &active_id);
+        check_tree(&mut tree, &buffers);
+    }
+
+    #[test]
+    fn test_resize() {
+        let (mut tree, mut buffers, buf_id) = create_tree();
+        let buf = buffers.get_mut(&buf_id).unwrap();
+        let widths = |tree: &PaneTree| -> Vec<f64> {
+            tree.panes().iter().map(|p| p.rect().width).collect()
+        };
+
+        // Splitting shares the active pane's space.
+        tree.split(Orientation::Horizontal, buf).unwrap();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        tree.split(Orientation::Horizontal, buf).unwrap();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        assert_eq!(widths(&tree), [400.0, 400.0, 800.0]);
+
+        // No vertical split to resize.
+        assert!(tree.resize_active(Orientation::Vertical, 10.0).is_err());
+
+        // Space is taken from the other panes in proportion to how
+        // far they are above the minimum size.
+        tree.resize_active(Orientation::Horizontal, 100.0).unwrap();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        let expected = [
+            500.0,
+            400.0 - 100.0 * 280.0 / 960.0,
+            800.0 - 100.0 * 680.0 / 960.0,
+        ];
+        for (width, expected) in widths(&tree).iter().zip(expected) {
+            assert!((width - expected).abs() < 1e-6);
+        }
+
+        // Panes don't shrink below the minimum.
+        tree.resize_active(Orientation::Horizontal, -1000.0)
+            .unwrap();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        assert_eq!(widths(&tree)[0], MIN_PANE_WIDTH);
+
+        tree.balance();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        let third = WIDTH / 3.0;
+        assert!(widths(&tree).iter().all(|w| (w - third).abs() < 1e-6));
+
+        // Weights survive a round trip through JSON.
+        tree.resize_active(Orientation::Horizontal, 100.0).unwrap();
+        let json = serde_json::to_string(&tree).unwrap();
+        let mut loaded = PaneTree::load_from_json(&json).unwrap();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        loaded.recalc_layout(WIDTH, HEIGHT);
+        assert_eq!(widths(&loaded), widths(&tree));
+    }
+
+    #[test]
+    fn test_switch_buffer_restores_position() {
+        let (mut tree, mut buffers, buf_id) = create_tree();
+        let mut other = Buffer::create_empty();
+        other.set_text("a\nb\nc\n");
+        let other_id = other.id().clone();
+        buffers.insert(other_id.clone(), other);
+
+        let pane = tree.active_mut();
+        pane.switch_buffer(&mut buffers, &other_id);
+        let pane_id = pane.id().clone();
+        buffers
+            .get_mut(&other_id)
+            .unwrap()
+            .set_cursor(&pane_id, AbsChar(4));
+        pane.set_top_line(AbsLine(2));
+
+        pane.switch_buffer(&mut buffers, &buf_id);
+        assert_eq!(buffers[&buf_id].cursor(&pane_id), AbsChar(0));
+        assert_eq!(pane.top_line(), AbsLine(0));
+
+        // The position is clamped if the text got shorter.
+        buffers.get_mut(&other_id).unwrap().set_text("a\nb");
+        pane.switch_buffer(&mut buffers, &other_id);
+        assert_eq!(buffers[&other_id].cursor(&pane_id), AbsChar(3));
+        assert_eq!(pane.top_line(), AbsLine(1));
+
+        // Switching to the same buffer keeps the position.
+        buffers
+            .get_mut(&other_id)
+            .unwrap()
+            .set_cursor(&pane_id, AbsChar(2));
+        pane.switch_buffer(&mut buffers, &other_id);
+        assert_eq!(buffers[&other_id].cursor(&pane_id), AbsChar(2));
+    }
+
+    #[test]
+    fn test_wrapped_rows() {
+        let (mut tree, mut buffers, buf_id) = create_tree();
+        let buf = buffers.get_mut(&buf_id).unwrap();
+        buf.set_text("aaaa bbbb cccc\nab cdefg\n");
+        let line_height = LineHeight(10.0);
+        let pane = tree.active_mut();
+        let pane_id = pane.id().clone();
+        // Two rows of text above the info bar, five columns wide.
+        pane.set_rect(Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 60.0,
+            height: 30.0,
+        });
+        pane.set_columns(5);
+        pane.set_wrap(true);
+        assert_eq!(pane.rows(buf, AbsLine(0)), [0..5, 5..10, 10..14]);
+
+        let down = |pane: &mut Pane, buf: &mut Buffer, dir| {
+            pane.move_cursor(buf, Move::Line, dir, 1, line_height);
+            pane.maybe_rescroll(buf, buf.cursor(pane.id()), line_height);
+            (buf.cursor(pane.id()).0, pane.top_line().0, pane.top_row())
+        };
+
+        // Moving goes by row, keeping the column, and scrolls by row.
+        // Past the end of a shorter row, the cursor stays on that row.
+        buf.set_cursor(&pane_id, AbsChar(3));
+        assert_eq!(down(pane, buf, Direction::Inc), (8, 0, 0));
+        assert_eq!(down(pane, buf, Direction::Inc), (13, 0, 1));
+        assert_eq!(down(pane, buf, Direction::Inc), (17, 0, 2));
+        assert_eq!(down(pane, buf, Direction::Inc), (20, 1, 0));
+        buf.set_cursor(&pane_id, AbsChar(22));
+        assert_eq!(down(pane, buf, Direction::Dec), (17, 1, 0));
+
+        // The mouse finds positions by row in view.
+        assert_eq!(pane.position_at(buf, 1, 2).offset.0, 5);
+
+        // Scrolling by row pushes the cursor along.
+        pane.scroll(buf, 2, Direction::Dec, line_height);
+        assert_eq!(
+            (buf.cursor(&pane_id).0, pane.top_line().0, pane.top_row()),
+            (12, 0, 1)
+        );
+
+        // Without wrapping, a line is one row.
+        pane.set_wrap(false);
+        let rows = pane.rows(buf, AbsLine(0));
+        assert_eq!((rows.len(), rows[0].end), (1, 14));
+        assert_eq!(down(pane, buf, Direction::Inc), (23, 0, 0));
+    }
+
+    #[test]
+    fn test_horizontal_scroll() {
+        let (mut tree, mut buffers, buf_id) = create_tree();
+        let buf = buffers.get_mut(&buf_id).unwrap();
+        buf.set_text("0123456789abcdefghij\nshort\n");
+        let line_height = LineHeight(10.0);
+        let pane = tree.active_mut();
+        pane.set_columns(8);
+
+        let step = |pane: &mut Pane, buf: &mut Buffer, step, count| {
+            pane.move_cursor(buf, step, Direction::Inc, count, line_height);
+            pane.maybe_rescroll(buf, buf.cursor(pane.id()), line_height);
+            (buf.cursor(pane.id()).0, pane.left_column())
+        };
+        let grapheme = Move::Boundary(Boundary::Grapheme);
+
+        // The last column is kept for the indicator, so moving onto it
+        // scrolls the cursor to the middle.
+        assert_eq!(step(pane, buf, grapheme, 6), (6, 0));
+        assert_eq!(step(pane, buf, grapheme, 1), (7, 3));
+        let line_end = Move::Boundary(Boundary::LineEnd);
+        assert_eq!(step(pane, buf, line_end, 1), (20, 16));
+        let truncated = pane.truncate_line(buf, AbsLine(0));
+        assert_eq!(truncated.range, 17..20);
+        assert_eq!((truncated.cut_left, truncated.cut_right), (true, false));
+
+        // The mouse finds positions in the scrolled text.
+        assert_eq!(pane.position_at(buf, 0, 2).offset.0, 18);
+
+        // The offset is kept with the tree, and reset by wrapping.
+        let json = serde_json::to_string(&tree).unwrap();
+        let mut loaded = PaneTree::load_from_json(&json).unwrap();
+        assert_eq!(loaded.active().left_column(), 16);
+        loaded.active_mut().set_wrap(true);
+        assert_eq!(loaded.active().left_column(), 0);
+
+        // Moving to a line that fits scrolls all the way back to the
+        // left, so that its start isn't hidden by the indicator.
+        let pane = tree.active_mut();
+        assert_eq!(step(pane, buf, Move::Line, 1), (26, 0));
+    }
+
+    /// Create a tree with pane A on the left, and panes B and C on the
+    /// right with B above C. B is active.
+    fn create_three_pane_tree() -> (PaneTree, BufferMap, [PaneId; 3]) {
+        let (mut tree, mut buffers, buf_id) = create_tree();
+        let buf = buffers.get_mut(&buf_id).unwrap();
+        tree.split(Orientation::Horizontal, buf).unwrap();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        tree.make_next_pane_active();
+        tree.split(Orientation::Vertical, buf).unwrap();
+        tree.recalc_layout(WIDTH, HEIGHT);
+        let ids = tree
+            .panes()
+            .iter()
+            .map(|p| p.id().clone())
+            .collect::<Vec<_>>();
+        (tree, buffers, ids.try_into().unwrap())
+    }
+
+    #[test]
+    fn test_focus() {
+        let (mut tree, _buffers, [a, b, c]) = create_three_pane_tree();
+        assert_eq!(tree.active().id(), &b);
+
+        tree.focus(Orientation::Vertical, Direction::Inc).unwrap();
+        assert_eq!(tree.active().id(), &c);
+        assert!(tree.focus(Orientation::Vertical, Direction::Inc).is_err());
+        assert!(tree.focus(Orientation::Horizontal, Direction::Inc).is_err());
+        tree.focus(Orientation::Horizontal, Direction::Dec).unwrap();
+        assert_eq!(tree.active().id(), &a);
+        assert!(tree.focus(Orientation::Vertical, Direction::Dec).is_err());
+
+        // B and C border A equally, the first one wins.
+        tree.focus(Orientation::Horizontal, Direction::Inc).unwrap();
+        assert_eq!(tree.active().id(), &b);
+    }
+
+    #[test]
+    fn test_swap_and_move() {
+        let (mut tree, mut buffers, [a, b, c]) = create_three_pane_tree();
+        let rects = |tree: &PaneTree| -> Vec<Rect> {
+            tree.panes().iter().map(|p| p.rect().clone()).collect()
+        };
+        let before = rects(&tree);
+
+        // Swapping exchanges the panes but not the layout.
+        tree.swap(Orientation::Horizontal, Direction::Dec).unwrap();
+        let ids: Vec<_> = tree.panes().iter().map(|p| p.id().clone()).collect();
+        assert_eq!(ids, [b.clone(), a.clone(), c.clone()]);
+        assert_eq!(tree.active().id(), &b);
+        assert_eq!(rects(&tree), before);
+        assert!(tree.swap(Orientation::Horizontal, Direction::Dec).is_err());
+
+        // Move B below C.
+        tree.swap(Orientation::Horizontal, Direction::Inc).unwrap();
+        assert_eq!(rects(&tree), before);
+        tree.move_active(Orientation::Vertical, Direction::Inc)
+            .unwrap();
+        check_tree(&mut tree, &buffers);
+        let ids: Vec<_> = tree.panes().iter().map(|p| p.id().clone()).collect();
+        assert_eq!(ids, [a.clone(), c.clone(), b.clone()]);
+        assert_eq!(tree.active().id(), &b);
+        assert_eq!(tree.active().rect().width, WIDTH / 2.0);
+
+        // Move A to the right of C, leaving B alone at the bottom.
+        tree.focus(Orientation::Horizontal, Direction::Dec).unwrap();
+        tree.move_active(Orientation::Horizontal, Direction::Inc)
+            .unwrap();
+        check_tree(&mut tree, &buffers);
+        let ids: Vec<_> = tree.panes().iter().map(|p| p.id().clone()).collect();
+        assert_eq!(ids, [c, a.clone(), b]);
+        assert_eq!(tree.active().id(), &a);
+        assert_eq!(tree.active().rect().width, WIDTH / 2.0);
+        assert_eq!(tree.active().rect().height, HEIGHT / 2.0);
+
+        tree.close_other_panes(&mut buffers);
+        assert!(
+            tree.move_active(Orientation::Horizontal, Direction::Inc)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rotate() {
+        let (mut tree, mut buffers, _) = create_three_pane_tree();
+
+        // B and C become side by side, which merges them into the
+        // root split while keeping their sizes.
+        tree.rotate_split().unwrap();
+        check_tree(&mut tree, &buffers);
+        let widths: Vec<f64> =
+            tree.panes().iter().map(|p| p.rect().width).collect();
+        assert_eq!(widths, [800.0, 400.0, 400.0]);
+
+        tree.rotate_split().unwrap();
+        check_tree(&mut tree, &buffers);
+        assert!(tree.panes().iter().all(|p| p.rect().width == WIDTH));
+
+        tree.close_other_panes(&mut buffers);
+        assert!(tree.rotate_split().is_err());
+    }
+
+    /// Test loading a tree persisted before weights were added.
+    #[test]
+    fn test_load_without_weights() {
+        let (mut tree, mut buffers, buf_id) = create_tree();
+        let buf = buffers.get_mut(&buf_id).unwrap();
+        tree.split(Orientation::Vertical, buf).unwrap();
+        let mut json: serde_json::Value = serde_json::to_value(&tree).unwrap();
+        json["root"]["Internal"]
+            .as_object_mut()
+            .unwrap()
+            .remove("weights");
+
+        let mut loaded = PaneTree::load_from_json(&json.to_string()).unwrap();
+        loaded.recalc_layout(WIDTH, HEIGHT);
+        check_node(&loaded.root, None);
+        assert!(
+            loaded
+                .panes()
+                .iter()
+                .all(|p| p.rect().height == HEIGHT / 2.0)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_tree_invariants(ops in prop::collection::vec(op(), 0..50)) {
+            let (mut tree, mut buffers, buf_id) = create_tree();
+            let mut balanced = false;
+
+            for op in ops {
+                let num_panes = tree.panes().len();
+                match op {
+                    Op::Split(orientation) => {
+                        let buf = buffers.get_mut(&buf_id).unwrap();
+                        let min = min_pane_extent(orientation);
+                        let too_small =
+                            tree.active().rect().extent(orientation) / 2.0 < min;
+                        let result = tree.split(orientation, buf);
+                        prop_assert_eq!(result.is_err(), too_small);
+                        let expected =
+                            if too_small { num_panes } else { num_panes + 1 };
+                        prop_assert_eq!(tree.panes().len(), expected);
+                    }
+                    Op::Close => {
+                        let index = active_index(&tree);
+                        let result = tree.close_active(&mut buffers);
+                        prop_assert_eq!(result.is_ok(), num_panes > 1);
+                        prop_assert_eq!(
+                            tree.panes().len(),
+                            num_panes.max(2) - 1
+                        );
+                        // Focus moves to an adjacent pane.
+                        if num_panes > 1 {
+                            prop_assert!(active_index(&tree) <= index);
+                            prop_assert!(active_index(&tree) + 1 >= index);
+                        }
+                    }
+                    Op::CloseOthers => {
+                        tree.close_other_panes(&mut buffers);
+                        prop_assert_eq!(tree.panes().len(), 1);
+                    }
+                    Op::Next => tree.make_next_pane_active(),
+                    Op::Previous => tree.make_previous_pane_active(),
+                    Op::Resize(orientation, delta) => {
+                        let before = tree.active().rect().extent(orientation);
+                        if tree.resize_active(orientation, delta).is_ok() {
+                            tree.recalc_layout(WIDTH, HEIGHT);
+                            let after = tree.active().rect().extent(orientation);
+                            // The pane changes size in the requested
+                            // direction, by no more than requested.
+                            let change = after - before;
+                            prop_assert!(change * delta >= -1e-6);
+                            prop_assert!(change.abs() <= delta.abs() + 1e-6);
+                        }
+                    }
+                    Op::Balance => {
+                        tree.balance();
+                        balanced = true;
+                    }
+                    Op::Focus(orientation, direction) => {
+                        let before = tree.active().rect().clone();
+                        if tree.focus(orientation, direction).is_ok() {
+                            // Focus moved in the requested direction.
+                            let after = tree.active().rect();
+                            let moved = match orientation {
+                                Orientation::Horizontal => after.x - before.x,
+                                Orientation::Vertical => after.y - before.y,
+                            };
+                            match direction {
+                                Direction::Inc => prop_assert!(moved > 0.0),
+                                Direction::Dec => prop_assert!(moved < 0.0),
+                            }
+                        }
+                    }
+                    Op::Swap(orientation, direction) => {
+                        let active_id = tree.active().id().clone();
+                        if tree.swap(orientation, direction).is_ok() {
+                            prop_assert_eq!(tree.active().id(), &active_id);
+                        }
+                    }
+                    Op::Move(orientation, direction) => {
+                        let active_id = tree.active().id().clone();
+                        if tree.move_active(orientation, direction).is_ok() {
+                            prop_assert_eq!(tree.active().id(), &active_id);
+                            prop_assert_eq!(tree.panes().len(), num_panes);
+                        }
+                    }
+                    Op::Rotate => {
+                        let result = tree.rotate_split();
+                        if num_panes == 1 {
+                            prop_assert!(result.is_err());
+                        }
+                    }
+                }
+                check_tree(&mut tree, &buffers);
+                // Balancing doesn't consider the minimum size, but
+                // nothing else shrinks panes below it.
+                if !balanced {
+                    prop_assert!(panes_fit(&tree));
+                }
+            }
+        }
+    }
+}
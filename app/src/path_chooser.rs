@@ -1,27 +1,129 @@
+//! Prompt for a file path, with completion of the names in the
+//! directory being typed.
+//!
+//! A leading `~` and `$VAR` or `${VAR}` are expanded. Pressing tab
+//! completes the longest prefix shared by all the suggestions, then
+//! cycles through them.
+
 use crate::LineHeight;
 use crate::action::{Action, Boundary, Direction, Move};
 use crate::buffer::Buffer;
+use crate::candidate_list::{self, CandidateList};
 use crate::key_map::KeyMap;
 use crate::pane_tree::{Pane, Rect};
 use crate::widget::Widget;
 use anyhow::Result;
+use fs_err as fs;
 use std::path::{Path, PathBuf};
 
+/// Maximum number of suggestions shown at once.
+pub const MAX_VISIBLE_SUGGESTIONS: usize = 10;
+
+/// Maximum number of directory entries to suggest.
+const MAX_SUGGESTIONS: usize = 1000;
+
+/// Split the input into the directory part, including the trailing
+/// slash, and the partial file name after it.
+fn split_input(text: &str) -> (&str, &str) {
+    match text.rfind('/') {
+        Some(index) => text.split_at(index + 1),
+        None => ("", text),
+    }
+}
+
+/// Expand a leading `~` to the home directory, and `$VAR` or `${VAR}`
+/// to the value of the environment variable. Anything that can't be
+/// expanded is left as is.
+fn expand_path(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+
+    if let Some(after) = rest.strip_prefix('~')
+        && (after.is_empty() || after.starts_with('/'))
+        && let Some(home) = dirs::home_dir()
+    {
+        out.push_str(&home.to_string_lossy());
+        rest = after;
+    }
+
+    while let Some(index) = rest.find('$') {
+        out.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => {
+                out.push_str(&value);
+                rest = &after[len..];
+            }
+            _ => {
+                out.push('$');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Get the longest prefix shared by all of `names`.
+fn common_prefix(names: &[String]) -> &str {
+    let Some((first, others)) = names.split_first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for name in others {
+        len = first
+            .char_indices()
+            .zip(name.chars())
+            .take_while(|((_, a), b)| a == b)
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// State of cycling through the suggestions with repeated tabs.
+struct Cycle {
+    /// Directory part of the input the suggestions are for.
+    dir: String,
+
+    /// Input text set for the selected suggestion. Cycling stops if
+    /// the input gets changed to something else.
+    text: String,
+}
+
 pub struct PathChooser {
     buffer: Buffer,
     pane: Pane,
     rect: Rect,
-    // TODO: this type will probably eventually become more interesting.
-    suggestions: Vec<String>,
+
+    /// Entries in the directory being typed whose names start with
+    /// the partial file name. Directory names end with a slash.
+    suggestions: CandidateList<String>,
+
+    cycle: Option<Cycle>,
 }
 
 impl PathChooser {
     pub fn new(default_path: &Path) -> Result<Self> {
         let mut buffer = Buffer::create_empty();
-        // TODO: what about non-utf8 paths?
-        let mut default_path = default_path.to_str().unwrap().to_owned();
+        let mut default_path = default_path.to_string_lossy().into_owned();
         // That pesky default path doesn't end in a slash.
-        default_path += "/";
+        if !default_path.ends_with('/') {
+            default_path += "/";
+        }
         buffer.set_text(&default_path);
 
         let pane = Pane::create_for_widget(&mut buffer);
@@ -29,54 +131,127 @@ impl PathChooser {
             buffer,
             pane,
             rect: Rect::default(),
-            suggestions: Vec::new(),
+            suggestions: CandidateList::new(Vec::new()),
+            cycle: None,
         };
         s.update_suggestions()?;
         Ok(s)
     }
 
+    /// Get the path that has been typed, with `~` and variables
+    /// expanded.
     pub fn path(&self) -> PathBuf {
-        PathBuf::from(self.buffer.text().to_string())
+        PathBuf::from(expand_path(&self.buffer.text().to_string()))
     }
 
-    pub fn suggestions(&self) -> String {
-        self.suggestions.join(" | ")
+    pub fn suggestions(&self) -> &CandidateList<String> {
+        &self.suggestions
     }
 
-    pub fn autocomplete(&mut self) -> Result<()> {
-        // TODO: for now only autocomplete with one option.
-        if self.suggestions.len() != 1 {
-            return Ok(());
+    fn set_text(&mut self, text: &str) {
+        self.buffer.set_text(text);
+        self.buffer.move_cursor(
+            self.pane.id(),
+            Move::Boundary(Boundary::BufferEnd),
+            Direction::Inc,
+        );
+    }
+
+    /// Complete the longest prefix shared by all the suggestions. If
+    /// that doesn't add anything, fill in the next suggestion instead.
+    pub fn autocomplete(&mut self) {
+        let text = self.buffer.text().to_string();
+        if self.cycle.as_ref().is_some_and(|cycle| cycle.text == text) {
+            self.select(Direction::Inc);
+            return;
         }
 
-        if let Some(dir) = self.path().parent() {
-            let child = &self.suggestions[0];
-            self.buffer.set_text(dir.join(child).to_str().unwrap());
-            self.buffer.move_cursor(
-                self.pane.id(),
-                Move::Boundary(Boundary::LineEnd),
-                Direction::Inc,
-            );
-            self.update_suggestions()
+        let (dir, name) = split_input(&text);
+        let common = common_prefix(self.suggestions.candidates());
+        if common.len() > name.len() {
+            let text = format!("{dir}{common}");
+            self.set_text(&text);
+        } else if self.suggestions.len() > 1 {
+            self.cycle = Some(Cycle {
+                dir: dir.to_owned(),
+                text: String::new(),
+            });
+            self.fill_in_selected();
+        }
+    }
+
+    /// Select the next or previous suggestion and fill it in.
+    pub fn select(&mut self, dir: Direction) {
+        if self.cycle.is_none() {
+            let text = self.buffer.text().to_string();
+            self.cycle = Some(Cycle {
+                dir: split_input(&text).0.to_owned(),
+                text: String::new(),
+            });
         } else {
-            Ok(())
+            self.suggestions.select(dir);
         }
+        self.fill_in_selected();
     }
 
+    fn fill_in_selected(&mut self) {
+        let (Some(cycle), Some(name)) =
+            (&mut self.cycle, self.suggestions.selected())
+        else {
+            return;
+        };
+        cycle.text = format!("{}{name}", cycle.dir);
+        let text = cycle.text.clone();
+        self.set_text(&text);
+    }
+
+    /// List the entries matching the input. Hidden files are only
+    /// included if the partial file name starts with a dot. Does
+    /// nothing while cycling through the suggestions.
     pub fn update_suggestions(&mut self) -> Result<()> {
-        // TODO: this is a very simple completion that is
-        // minimally helpful.
-        let mut path = self.path().to_str().unwrap().to_owned();
-        path.push('*');
-        // Arbitrarily grab a few options.
-        let completions: Vec<_> = glob::glob(&path)?
-            .take(100)
-            .map(|p| {
-                p.unwrap().file_name().unwrap().to_str().unwrap().to_owned()
-            })
-            .collect();
-
-        self.suggestions = completions;
+        let text = self.buffer.text().to_string();
+        if self.cycle.as_ref().is_some_and(|cycle| cycle.text == text) {
+            return Ok(());
+        }
+        self.cycle = None;
+
+        // The directory is read directly rather than globbed, so
+        // there's no need to worry about glob metacharacters in the
+        // path.
+        let (dir, prefix) = split_input(&text);
+        let dir = if dir.is_empty() {
+            ".".to_owned()
+        } else {
+            expand_path(dir)
+        };
+        let Ok(entries) = fs::read_dir(&dir) else {
+            // Nothing to suggest in a directory that doesn't exist.
+            self.suggestions = CandidateList::new(Vec::new());
+            return Ok(());
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            // Names that aren't UTF-8 can't be typed in, so there's
+            // no point suggesting them.
+            let Ok(mut name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if !name.starts_with(prefix)
+                || (name.starts_with('.') && !prefix.starts_with('.'))
+            {
+                continue;
+            }
+            // Follow symlinks to see if the entry is a directory.
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            names.push(name);
+        }
+        names.sort();
+        names.truncate(MAX_SUGGESTIONS);
+        self.suggestions = CandidateList::new(names);
 
         Ok(())
     }
@@ -84,14 +259,12 @@ impl PathChooser {
 
 impl Widget for PathChooser {
     fn get_keymap(&self) -> Result<KeyMap> {
-        KeyMap::from_pairs(
+        candidate_list::popup_keymap(
             "open_file",
             vec![
                 ("<ctrl>i", Action::Autocomplete),
-                ("<ret>", Action::Confirm),
-                ("<ctrl>m", Action::Confirm),
-            ]
-            .into_iter(),
+                ("<tab>", Action::Autocomplete),
+            ],
         )
     }
 
@@ -116,18 +289,14 @@ impl Widget for PathChooser {
     }
 
     fn recalc_layout(&mut self, width: f64, line_height: LineHeight) {
-        self.rect = Rect {
-            x: 0.0,
-            y: 0.0,
-            width,
-            height: line_height.0 * 3.0,
-        };
-        self.pane.set_rect(Rect {
-            x: 0.0,
-            y: line_height.0,
+        // Prompt, input line, then one line per visible suggestion.
+        let num_rows = self.suggestions.len().min(MAX_VISIBLE_SUGGESTIONS);
+        self.rect = candidate_list::popup_layout(
+            &mut self.pane,
             width,
-            height: line_height.0,
-        });
+            line_height,
+            num_rows,
+        );
     }
 
     fn rect(&self) -> &Rect {
@@ -139,12 +308,19 @@ impl Widget for PathChooser {
 mod tests {
     use super::*;
     use anyhow::Result;
-    use std::fs;
 
     fn path_to_str(p: &Path) -> String {
         p.to_str().unwrap().to_owned()
     }
 
+    fn suggestions(open_file: &PathChooser) -> Vec<&str> {
+        open_file
+            .suggestions()
+            .matches()
+            .map(String::as_str)
+            .collect()
+    }
+
     #[test]
     fn test_open_file() -> Result<()> {
         // Create test files.
@@ -155,19 +331,99 @@ mod tests {
         let tmp_path2 = tmp_dir.join("testfile2");
         fs::write(&tmp_path2, "test data 2\n")?;
 
-        let mut open_file = PathChooser::new(&tmp_dir)?;
+        let mut open_file = PathChooser::new(tmp_dir)?;
 
         // Check the default path.
-        assert_eq!(path_to_str(&open_file.path()), path_to_str(&tmp_dir) + "/");
+        assert_eq!(path_to_str(&open_file.path()), path_to_str(tmp_dir) + "/");
 
         // Check the initial suggestions.
-        assert_eq!(open_file.suggestions(), "testfile1 | testfile2");
+        assert_eq!(suggestions(&open_file), ["testfile1", "testfile2"]);
 
         // Modify the path and check suggestions again.
         open_file.buffer_mut().set_text(&path_to_str(&tmp_path1));
         open_file.update_suggestions()?;
-        assert_eq!(open_file.suggestions(), "testfile1");
+        assert_eq!(suggestions(&open_file), ["testfile1"]);
 
         Ok(())
     }
+
+    #[test]
+    fn test_autocomplete() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        // Glob metacharacters in the path have no special meaning.
+        let dir = tmp_dir.path().join("[a]*");
+        fs::create_dir_all(dir.join("subdir"))?;
+        for name in ["sub1", "sub2", ".hidden", "other"] {
+            fs::write(dir.join(name), "")?;
+        }
+        let dir = path_to_str(&dir) + "/";
+
+        let mut open_file = PathChooser::new(Path::new(&dir))?;
+        let text =
+            |open_file: &PathChooser| open_file.buffer().text().to_string();
+        let tab = |open_file: &mut PathChooser| -> Result<()> {
+            open_file.autocomplete();
+            open_file.update_suggestions()
+        };
+
+        // Directories get a slash, hidden files are left out.
+        assert_eq!(
+            suggestions(&open_file),
+            ["other", "sub1", "sub2", "subdir/"]
+        );
+        open_file.buffer_mut().set_text(&format!("{dir}."));
+        open_file.update_suggestions()?;
+        assert_eq!(suggestions(&open_file), [".hidden"]);
+
+        // Complete the common prefix.
+        open_file.buffer_mut().set_text(&format!("{dir}s"));
+        open_file.update_suggestions()?;
+        tab(&mut open_file)?;
+        assert_eq!(text(&open_file), format!("{dir}sub"));
+        assert_eq!(suggestions(&open_file).len(), 3);
+
+        // Then cycle through the suggestions, wrapping around.
+        for expected in ["sub1", "sub2", "subdir/", "sub1"] {
+            tab(&mut open_file)?;
+            assert_eq!(text(&open_file), format!("{dir}{expected}"));
+        }
+        open_file.select(Direction::Dec);
+        assert_eq!(text(&open_file), format!("{dir}subdir/"));
+
+        // Editing stops cycling. A single suggestion is completed.
+        open_file.buffer_mut().set_text(&format!("{dir}o"));
+        open_file.update_suggestions()?;
+        tab(&mut open_file)?;
+        assert_eq!(text(&open_file), format!("{dir}other"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let home = path_to_str(&dirs::home_dir().unwrap());
+        assert_eq!(expand_path("~"), home);
+        assert_eq!(expand_path("~/a"), format!("{home}/a"));
+        assert_eq!(expand_path("/a/~"), "/a/~");
+        assert_eq!(expand_path("~user"), "~user");
+
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(expand_path("$PATH/a"), format!("{path}/a"));
+        assert_eq!(expand_path("/${PATH}a"), format!("/{path}a"));
+        assert_eq!(expand_path("$EMMA_UNSET_VAR/a"), "$EMMA_UNSET_VAR/a");
+        assert_eq!(expand_path("${PATH"), "${PATH");
+        assert_eq!(expand_path("a$"), "a$");
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let names = |names: &[&str]| -> Vec<String> {
+            names.iter().map(|s| s.to_string()).collect()
+        };
+        assert_eq!(common_prefix(&[]), "");
+        assert_eq!(common_prefix(&names(&["abc"])), "abc");
+        assert_eq!(common_prefix(&names(&["abc", "abd", "ab"])), "ab");
+        assert_eq!(common_prefix(&names(&["aé1", "aé2"])), "aé");
+        assert_eq!(common_prefix(&names(&["abc", "xyz"])), "");
+    }
 }
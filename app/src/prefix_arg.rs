@@ -0,0 +1,130 @@
+//! Numeric argument typed before an action, e.g. "<ctrl>u 1 2
+//! <ctrl>n" to move down twelve lines.
+//!
+//! `<ctrl>u` on its own means four, and each further `<ctrl>u`
+//! multiplies that by four. Digits typed after it replace that with
+//! the number they spell. With modal editing, digits in normal mode
+//! build a count the same way without needing `<ctrl>u` first.
+
+use std::fmt::{self, Display, Formatter};
+
+/// Largest count that can be typed, to keep a stray key press from
+/// repeating an action forever.
+const MAX_COUNT: usize = 9999;
+
+/// What a single `<ctrl>u` multiplies the count by.
+const UNIVERSAL_FACTOR: usize = 4;
+
+#[derive(Debug, Default)]
+pub struct PrefixArg {
+    count: Option<usize>,
+
+    /// Whether `<ctrl>u` was typed, so that plain digits add to the
+    /// count instead of being inserted.
+    universal: bool,
+
+    /// Whether the count was typed as digits.
+    has_digits: bool,
+}
+
+impl PrefixArg {
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none()
+    }
+
+    /// Handle `<ctrl>u`. After digits it ends the count, so that the
+    /// next digit is passed to the action instead.
+    pub fn push_universal(&mut self) {
+        if self.has_digits {
+            self.universal = false;
+            return;
+        }
+        let count = self.count.unwrap_or(1).saturating_mul(UNIVERSAL_FACTOR);
+        self.count = Some(count.min(MAX_COUNT));
+        self.universal = true;
+    }
+
+    /// Whether plain digits are part of the count rather than text.
+    pub fn accepts_digits(&self) -> bool {
+        self.universal
+    }
+
+    /// Add a digit to the count. Returns false for a zero with no
+    /// count before it, which isn't a count.
+    pub fn push_digit(&mut self, digit: u8) -> bool {
+        if digit == 0 && self.count.is_none() {
+            return false;
+        }
+        let prev = if self.has_digits {
+            self.count.unwrap_or(0)
+        } else {
+            0
+        };
+        let count = prev.saturating_mul(10) + usize::from(digit);
+        self.count = Some(count.min(MAX_COUNT));
+        self.has_digits = true;
+        true
+    }
+
+    /// Take the count, which is one if none was typed, and reset.
+    pub fn take(&mut self) -> usize {
+        let count = self.count.unwrap_or(1);
+        *self = Self::default();
+        count
+    }
+}
+
+/// Formats the count as it's being typed, for the echo area.
+impl Display for PrefixArg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.universal {
+            write!(f, "<ctrl>u ")?;
+        }
+        match self.count {
+            Some(count) => write!(f, "{count}-"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_arg() {
+        let mut arg = PrefixArg::default();
+        assert!(arg.is_empty());
+        assert!(!arg.accepts_digits());
+        assert_eq!(arg.take(), 1);
+
+        // Each <ctrl>u multiplies by four.
+        arg.push_universal();
+        assert!(arg.accepts_digits());
+        assert_eq!(arg.to_string(), "<ctrl>u 4-");
+        arg.push_universal();
+        assert_eq!(arg.take(), 16);
+        assert!(arg.is_empty());
+
+        // Digits replace the default of four.
+        arg.push_universal();
+        assert!(arg.push_digit(1));
+        assert!(arg.push_digit(2));
+        assert_eq!(arg.to_string(), "<ctrl>u 12-");
+        arg.push_universal();
+        assert!(!arg.accepts_digits());
+        assert_eq!(arg.take(), 12);
+
+        // A leading zero isn't a count.
+        assert!(!arg.push_digit(0));
+        assert!(arg.push_digit(3));
+        assert!(arg.push_digit(0));
+        assert_eq!(arg.to_string(), "30-");
+        assert_eq!(arg.take(), 30);
+
+        for _ in 0..6 {
+            arg.push_digit(9);
+        }
+        assert_eq!(arg.take(), MAX_COUNT);
+    }
+}
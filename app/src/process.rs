@@ -22,6 +22,10 @@ impl NonInteractiveProcess {
         }
     }
 
+    pub fn command_line(&self) -> &CommandLine {
+        &self.command_line
+    }
+
     pub fn run(
         &mut self,
         command_line: CommandLine,
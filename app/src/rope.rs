@@ -17,6 +17,7 @@ use std::ops::{Add, AddAssign, Bound, RangeBounds};
     Default,
     Eq,
     PartialEq,
+    Hash,
     Ord,
     PartialOrd,
     Deserialize,
@@ -238,6 +239,11 @@ impl<'a> RopeSlice<'a> {
         self.0.len_chars()
     }
 
+    // TODO: stricter type
+    pub fn char(&self, char_idx: usize) -> char {
+        self.0.char(char_idx)
+    }
+
     // TODO: stricter type
     pub fn slice<R>(&self, char_range: R) -> Self
     where
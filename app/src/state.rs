@@ -1,31 +1,94 @@
+mod buffer_switcher;
+mod command_palette;
+mod completion;
+mod dir_listing;
 mod event;
+mod file_finder;
+mod keyboard_macro;
+mod lsp;
+mod modal;
+mod mouse;
 mod persistence;
+mod scroll;
+#[cfg(test)]
+mod test_util;
 
-use crate::LineHeight;
 use crate::buffer::{Buffer, BufferId};
+use crate::completion::CompletionSource;
+use crate::config::Config;
+use crate::keyboard_macro::MacroRecorder;
+use crate::lsp::LanguageServers;
+use crate::modal::Mode;
 use crate::overlay::Overlay;
-use crate::pane_tree::PaneTree;
+use crate::pane_tree::{PaneTree, Rect};
 use crate::rope::AbsLine;
 use crate::theme::Theme;
 use crate::widget::Widget;
+use crate::{CharWidth, LineHeight};
 use anyhow::Result;
 use persistence::PersistedBuffer;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use tracing::{error, info};
 
 pub struct AppState {
     key_handler: event::KeyHandler,
 
+    /// Keyboard macro being recorded, and the last one recorded.
+    macros: MacroRecorder,
+
+    config: Config,
+
     buffers: HashMap<BufferId, Buffer>,
     pane_tree: PaneTree,
 
     line_height: LineHeight,
+    char_width: CharWidth,
+
+    /// Selection being made by dragging the mouse, if any.
+    mouse_drag: Option<mouse::MouseDrag>,
 
-    is_persistence_enabled: bool,
+    /// The view left by the last recenter, if any.
+    last_recenter: Option<scroll::LastRecenter>,
+
+    /// Path of the sqlite database used to persist state. If `None`,
+    /// persistence is disabled.
+    db_path: Option<PathBuf>,
 
     overlay: Option<Overlay>,
+
+    /// Buffers ordered by when they were last shown in the active
+    /// pane, most recent first. May contain deleted buffers.
+    recent_buffers: Vec<BufferId>,
+
+    language_servers: LanguageServers,
+
+    /// Sources of candidates for the completion popup.
+    completion_sources: Vec<Box<dyn CompletionSource>>,
+
+    /// Message shown in the echo area at the bottom of the window,
+    /// e.g. an error.
+    message: Option<String>,
+
+    /// Bindings that could complete the pending key sequence, shown
+    /// above the echo area.
+    key_hints: Vec<KeyHint>,
+
+    echo_area: Rect,
 }
 
+/// A binding that could complete the pending key sequence.
+pub struct KeyHint {
+    /// The rest of the sequence, after the keys already typed.
+    pub keys: String,
+
+    pub action: String,
+}
+
+/// Maximum number of lines the echo area grows to when showing a
+/// multi-line message.
+const MAX_ECHO_AREA_LINES: usize = 10;
+
 impl AppState {
     pub fn buffers(&self) -> &HashMap<BufferId, Buffer> {
         &self.buffers
@@ -39,6 +102,45 @@ impl AppState {
         self.overlay.as_ref()
     }
 
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Current mode, if modal editing is enabled.
+    pub fn mode(&self) -> Option<Mode> {
+        self.key_handler.modal().map(|modal| modal.mode())
+    }
+
+    pub fn is_recording_macro(&self) -> bool {
+        self.macros.is_recording()
+    }
+
+    pub fn key_hints(&self) -> &[KeyHint] {
+        &self.key_hints
+    }
+
+    pub fn echo_area(&self) -> &Rect {
+        &self.echo_area
+    }
+
+    /// Use `config`, including its key bindings. Invalid bindings are
+    /// reported in the echo area.
+    pub fn set_config(&mut self, config: Config) {
+        let (keymap, errors) = config.user_keymap();
+        self.key_handler.set_user_keymap(keymap);
+        self.key_handler.set_modal_editing(config.modal_editing);
+        if !errors.is_empty() {
+            for err in &errors {
+                error!("invalid key binding: {err}");
+            }
+            self.message = Some(format!(
+                "invalid key bindings in config:\n{}",
+                errors.join("\n")
+            ));
+        }
+        self.config = config;
+    }
+
     pub fn line_height(&self) -> LineHeight {
         self.line_height
     }
@@ -47,12 +149,52 @@ impl AppState {
         self.line_height = line_height;
     }
 
+    pub fn char_width(&self) -> CharWidth {
+        self.char_width
+    }
+
+    pub fn set_char_width(&mut self, char_width: CharWidth) {
+        self.char_width = char_width;
+    }
+
     pub fn enable_persistence(&mut self) {
-        self.is_persistence_enabled = true;
+        match persistence::default_db_path() {
+            Ok(db_path) => self.db_path = Some(db_path),
+            Err(err) => error!("failed to enable persistence: {err}"),
+        }
+    }
+
+    /// Enable persistence with a non-default database path.
+    #[cfg(test)]
+    pub(crate) fn enable_persistence_at(&mut self, db_path: PathBuf) {
+        self.db_path = Some(db_path);
     }
 
     pub fn recalc_layout(&mut self, width: f64, height: f64) {
-        self.pane_tree.recalc_layout(width, height);
+        // The echo area is always at least one line, and grows to fit
+        // the current message.
+        let echo_area_lines = self
+            .message
+            .as_ref()
+            .map(|msg| msg.lines().count())
+            .unwrap_or(1)
+            .clamp(1, MAX_ECHO_AREA_LINES);
+        let echo_area_height = echo_area_lines as f64 * self.line_height.0;
+        self.echo_area = Rect {
+            x: 0.0,
+            y: height - echo_area_height,
+            width,
+            height: echo_area_height,
+        };
+
+        self.pane_tree
+            .recalc_layout(width, height - echo_area_height);
+        for pane in self.pane_tree.panes_mut() {
+            // Leave a column free for the cursor at the end of a full
+            // row.
+            let columns = (pane.rect().width / self.char_width.0) as usize;
+            pane.set_columns(columns.saturating_sub(1).max(1));
+        }
 
         // TODO: generalize this somehow.
         if let Some(overlay) = &mut self.overlay {
@@ -72,6 +214,7 @@ impl AppState {
 
         // Always create an empty scratch buffer.
         let mut scratch_buffer = Buffer::create_empty();
+        scratch_buffer.set_name("*scratch*");
 
         let mut buffers = HashMap::new();
         let mut cursors = HashMap::new();
@@ -122,8 +265,13 @@ impl AppState {
             }
         }
 
+        let recent_buffers = vec![pane_tree.active().buffer_id().clone()];
+
         Self {
             key_handler: event::KeyHandler::new().unwrap(),
+            macros: MacroRecorder::default(),
+
+            config: Config::default(),
 
             buffers,
             pane_tree,
@@ -131,9 +279,18 @@ impl AppState {
             // Outside of tests this is overwritten with a
             // dynamically-calculated value later.
             line_height: LineHeight(20.0),
+            char_width: CharWidth(10.0),
+            mouse_drag: None,
+            last_recenter: None,
 
-            is_persistence_enabled: false,
+            db_path: None,
             overlay: None,
+            recent_buffers,
+            language_servers: LanguageServers::default(),
+            completion_sources: crate::completion::default_sources(),
+            message: None,
+            key_hints: Vec::new(),
+            echo_area: Rect::default(),
         }
     }
 }
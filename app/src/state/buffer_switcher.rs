@@ -0,0 +1,145 @@
+use super::AppState;
+use crate::buffer::BufferId;
+use crate::buffer_switcher::BufferSwitcher;
+use crate::overlay::Overlay;
+use anyhow::Result;
+
+impl AppState {
+    /// Record that the active pane's buffer is in use, moving it to
+    /// the front of the recent buffers. This runs after every action.
+    /// Buffers previewed by the switcher don't count.
+    pub(super) fn record_buffer_use(&mut self) {
+        if matches!(self.overlay, Some(Overlay::SwitchBuffer(_))) {
+            return;
+        }
+
+        let buf_id = self.pane_tree.active().buffer_id();
+        if self.recent_buffers.first() == Some(buf_id) {
+            return;
+        }
+        let buf_id = buf_id.clone();
+        let buffers = &self.buffers;
+        self.recent_buffers
+            .retain(|id| *id != buf_id && buffers.contains_key(id));
+        self.recent_buffers.insert(0, buf_id);
+    }
+
+    pub(super) fn open_buffer_switcher(&mut self) -> Result<()> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+
+        let switcher = BufferSwitcher::new(
+            self.pane_tree.active(),
+            &self.buffers,
+            &self.recent_buffers,
+        );
+        self.overlay = Some(Overlay::SwitchBuffer(switcher));
+        self.preview_selected_buffer()
+    }
+
+    /// Show the buffer selected in the switcher in the active pane. If
+    /// nothing matches, show the buffer the pane had originally.
+    pub(super) fn preview_selected_buffer(&mut self) -> Result<()> {
+        let Some(Overlay::SwitchBuffer(switcher)) = &self.overlay else {
+            return Ok(());
+        };
+        let buf_id = switcher
+            .selected_buffer_id()
+            .unwrap_or(switcher.original_buffer_id())
+            .clone();
+        self.show_in_active_pane(&buf_id);
+        Ok(())
+    }
+
+    /// Close the switcher, keeping the selected buffer in the active
+    /// pane.
+    pub(super) fn confirm_buffer_switch(&mut self) -> Result<()> {
+        self.preview_selected_buffer()?;
+        self.overlay = None;
+        Ok(())
+    }
+
+    /// Close the switcher and go back to the buffer the active pane
+    /// had originally.
+    pub(super) fn cancel_buffer_switch(&mut self) {
+        if let Some(Overlay::SwitchBuffer(switcher)) = self.overlay.take() {
+            self.show_in_active_pane(switcher.original_buffer_id());
+        }
+    }
+
+    /// Switch the active pane to `buf_id`. Buffers remember where they
+    /// were last viewed, so going back to the original buffer restores
+    /// its cursor and scroll position.
+    fn show_in_active_pane(&mut self, buf_id: &BufferId) {
+        let pane = self.pane_tree.active_mut();
+        if pane.buffer_id() != buf_id {
+            pane.switch_buffer(&mut self.buffers, buf_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Action, Direction, Move};
+    use crate::buffer::AbsChar;
+    use crate::state::test_util::create_test_state;
+    use std::fs;
+
+    #[test]
+    fn test_switch_buffer() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        let active_name = |state: &AppState| {
+            let pane = state.pane_tree.active();
+            state.buffers[pane.buffer_id()].display_name()
+        };
+        let active_cursor = |state: &AppState| {
+            let pane = state.pane_tree.active();
+            state.buffers[pane.buffer_id()].cursor(pane.id())
+        };
+
+        let tmp_dir = tempfile::tempdir()?;
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let path = tmp_dir.path().join(name);
+            fs::write(&path, "line1\nline2\n")?;
+            state.open_file_at_path(&path)?;
+            state.handle_action(
+                Action::Move(Move::Line, Direction::Inc),
+                &writer,
+            )?;
+        }
+
+        // The most recently used other buffer is previewed.
+        state.handle_action(Action::SwitchToBuffer, &writer)?;
+        assert_eq!(active_name(&state), "b.txt");
+        // Buffers open where they were last viewed.
+        assert_eq!(active_cursor(&state), AbsChar(6));
+        state
+            .handle_action(Action::SelectCandidate(Direction::Inc), &writer)?;
+        assert_eq!(active_name(&state), "a.txt");
+
+        // Cancelling goes back to the original buffer and position.
+        state.handle_action(Action::Cancel, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(active_name(&state), "c.txt");
+        assert_eq!(active_cursor(&state), AbsChar(6));
+
+        // Filter and switch.
+        state.handle_action(Action::SwitchToBuffer, &writer)?;
+        for c in "scr".chars() {
+            state.handle_action(Action::Insert(c), &writer)?;
+        }
+        assert_eq!(active_name(&state), "*scratch*");
+        state.handle_action(Action::Confirm, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(active_name(&state), "*scratch*");
+
+        // The previous buffer is now c.txt.
+        state.handle_action(Action::SwitchToBuffer, &writer)?;
+        state.handle_action(Action::Confirm, &writer)?;
+        assert_eq!(active_name(&state), "c.txt");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use super::AppState;
+use super::event::is_repeatable;
+use crate::command_palette::CommandPalette;
+use crate::message::MessageWriter;
+use crate::overlay::Overlay;
+use anyhow::{Context, Result};
+
+impl AppState {
+    /// Open the command palette. The chosen action runs with `count`.
+    pub(super) fn open_command_palette(&mut self, count: usize) -> Result<()> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+
+        // Bindings are looked up before the palette opens, so that
+        // they're the ones that apply to the buffer.
+        let palette = CommandPalette::new(&self.keymap_stack(), count);
+        self.overlay = Some(Overlay::CommandPalette(palette));
+        Ok(())
+    }
+
+    /// Close the palette and run the selected action. It can be
+    /// repeated like an action run from its keys.
+    pub(super) fn confirm_command_palette(
+        &mut self,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
+        let Some(Overlay::CommandPalette(palette)) = self.overlay.take() else {
+            return Ok(());
+        };
+        let action = palette
+            .selected_action()
+            .context("no matching command")?
+            .clone();
+        let count = palette.count();
+        if is_repeatable(&action) {
+            self.key_handler
+                .set_last_action(Some((action.clone(), count)));
+        }
+        self.dispatch_action(action, count, message_writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::state::test_util::{create_test_state, run, type_text};
+
+    #[test]
+    fn test_command_palette() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+
+        // Pick an action by typing part of its name.
+        run(&mut state, &writer, &[Action::OpenCommandPalette])?;
+        type_text(&mut state, &writer, "split side")?;
+        run(&mut state, &writer, &[Action::Confirm])?;
+        assert!(state.overlay.is_none());
+        assert_eq!(state.pane_tree.panes().len(), 2);
+
+        // It can be repeated.
+        run(&mut state, &writer, &[Action::RepeatLastAction])?;
+        assert_eq!(state.pane_tree.panes().len(), 3);
+
+        // Repeating from the palette repeats the action before it,
+        // rather than itself.
+        run(&mut state, &writer, &[Action::OpenCommandPalette])?;
+        type_text(&mut state, &writer, "repeat last")?;
+        run(&mut state, &writer, &[Action::Confirm])?;
+        assert_eq!(state.pane_tree.panes().len(), 4);
+        run(&mut state, &writer, &[Action::RepeatLastAction])?;
+        assert_eq!(state.pane_tree.panes().len(), 5);
+
+        // Nothing runs if nothing matches.
+        run(&mut state, &writer, &[Action::OpenCommandPalette])?;
+        type_text(&mut state, &writer, "zzz")?;
+        assert!(state.handle_action(Action::Confirm, &writer).is_err());
+        assert!(state.overlay.is_none());
+        assert_eq!(state.pane_tree.panes().len(), 5);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+use super::AppState;
+use super::event::check_editable;
+use crate::buffer::{AbsChar, BufferId};
+use crate::completion::{
+    CompletionCandidate, CompletionContext, CompletionWidget, word_start,
+};
+use crate::lsp::protocol::CompletionItem;
+use crate::overlay::Overlay;
+use crate::pane_tree::PaneId;
+use anyhow::{Context, Result};
+use tracing::debug;
+
+impl AppState {
+    /// Open the completion popup for the text before the cursor in
+    /// the active pane. If there's a single candidate and no language
+    /// server response to wait for, it gets inserted without opening
+    /// the popup.
+    pub(super) fn start_completion(&mut self) -> Result<()> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+        check_editable(self.active_buffer()?)?;
+
+        let lsp_pending = match self.request_completion() {
+            Ok(()) => true,
+            Err(err) => {
+                debug!("not requesting completions: {err}");
+                false
+            }
+        };
+
+        let pane = self.pane_tree.active();
+        let buf = self
+            .buffers
+            .get(pane.buffer_id())
+            .context("active pane points to invalid buffer")?;
+        let ctx = CompletionContext {
+            buffers: &self.buffers,
+            buffer: buf,
+            cursor: buf.cursor(pane.id()),
+        };
+        let candidates = self
+            .completion_sources
+            .iter()
+            .flat_map(|source| source.candidates(&ctx))
+            .collect();
+        let widget = CompletionWidget::new(pane, buf, candidates);
+
+        if !lsp_pending {
+            match widget.candidates().len() {
+                0 => {
+                    self.message = Some("no completions".to_owned());
+                    return Ok(());
+                }
+                1 => {
+                    let buf =
+                        self.buffers
+                            .get_mut(widget.target_buffer_id())
+                            .context("active pane points to invalid buffer")?;
+                    widget.apply(buf);
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        self.overlay = Some(Overlay::Complete(widget));
+        Ok(())
+    }
+
+    /// Add completions from a language server to the popup. They are
+    /// ignored if the popup has been closed or was opened somewhere
+    /// else since the request was made.
+    pub(super) fn add_lsp_completions(
+        &mut self,
+        buffer_id: &BufferId,
+        pane_id: &PaneId,
+        position: AbsChar,
+        items: Vec<CompletionItem>,
+    ) -> Result<()> {
+        let Some(Overlay::Complete(widget)) = &mut self.overlay else {
+            return Ok(());
+        };
+        if widget.target_buffer_id() != buffer_id
+            || widget.target_pane_id() != pane_id
+            || widget.cursor() != position
+        {
+            return Ok(());
+        }
+
+        let buf = self
+            .buffers
+            .get(buffer_id)
+            .context(format!("invalid buffer: {buffer_id}"))?;
+        let start = word_start(buf.text(), position);
+        widget.add_candidates(
+            items
+                .into_iter()
+                .map(|item| CompletionCandidate {
+                    label: item.label,
+                    text: item.insert_text,
+                    start,
+                    source: "lsp",
+                })
+                .collect(),
+        );
+        Ok(())
+    }
+
+    /// Close the popup and insert the selected completion. If nothing
+    /// matches, the text typed into the popup is inserted instead.
+    pub(super) fn confirm_completion(&mut self) -> Result<()> {
+        let Some(Overlay::Complete(widget)) = self.overlay.take() else {
+            return Ok(());
+        };
+        let buf = self
+            .buffers
+            .get_mut(widget.target_buffer_id())
+            .context("completion target buffer no longer exists")?;
+        check_editable(buf)?;
+        if !widget.apply(buf) {
+            widget.restore_query(buf);
+        }
+        Ok(())
+    }
+
+    /// Close the popup without completing. The text typed into the
+    /// popup is inserted into the target buffer so that it isn't lost.
+    pub(super) fn cancel_completion(&mut self) -> Result<()> {
+        let Some(Overlay::Complete(widget)) = self.overlay.take() else {
+            return Ok(());
+        };
+        let buf = self
+            .buffers
+            .get_mut(widget.target_buffer_id())
+            .context("completion target buffer no longer exists")?;
+        widget.restore_query(buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Action, Direction};
+    use crate::state::test_util::{active_text, create_test_state, type_text};
+
+    /// Test completing words from the open buffers.
+    #[test]
+    fn test_complete_words() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+
+        // A single candidate is inserted immediately.
+        type_text(&mut state, &writer, "foobar foobaz quux q")?;
+        state.handle_action(Action::Complete, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(active_text(&state), "foobar foobaz quux quux");
+
+        // Multiple candidates open the popup.
+        type_text(&mut state, &writer, " fo")?;
+        state.handle_action(Action::Complete, &writer)?;
+        let Some(Overlay::Complete(widget)) = &state.overlay else {
+            panic!("completion popup not open");
+        };
+        assert_eq!(widget.candidates().len(), 2);
+
+        // Typing filters the candidates, and selection wraps around.
+        type_text(&mut state, &writer, "z")?;
+        state
+            .handle_action(Action::SelectCandidate(Direction::Inc), &writer)?;
+        state.handle_action(Action::Confirm, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(active_text(&state), "foobar foobaz quux quux foobaz");
+
+        // Nothing to complete.
+        type_text(&mut state, &writer, " xyz")?;
+        state.handle_action(Action::Complete, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(state.message(), Some("no completions"));
+
+        Ok(())
+    }
+
+    /// Test that text typed into the popup isn't lost when it closes
+    /// without completing.
+    #[test]
+    fn test_complete_keeps_typed_text() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+
+        // Confirm with no matching candidate.
+        type_text(&mut state, &writer, "foobar foobaz fo")?;
+        state.handle_action(Action::Complete, &writer)?;
+        assert!(state.overlay.is_some());
+        type_text(&mut state, &writer, "x")?;
+        state.handle_action(Action::Confirm, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(active_text(&state), "foobar foobaz fox");
+
+        // Cancel.
+        type_text(&mut state, &writer, " fo")?;
+        state.handle_action(Action::Complete, &writer)?;
+        assert!(state.overlay.is_some());
+        type_text(&mut state, &writer, "ob")?;
+        state.handle_action(Action::Cancel, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(active_text(&state), "foobar foobaz fox foob");
+
+        Ok(())
+    }
+}
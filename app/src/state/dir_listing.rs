@@ -0,0 +1,241 @@
+use super::AppState;
+use crate::buffer::{Buffer, BufferId};
+use crate::dir_listing::DirEntry;
+use crate::dir_prompt::{DirOp, DirPrompt};
+use crate::overlay::Overlay;
+use anyhow::{Context, Result, bail};
+use fs_err as fs;
+use std::path::Path;
+
+impl AppState {
+    /// Show a listing of `dir` in the active pane. An existing
+    /// listing of the same directory is reused and refreshed.
+    pub(super) fn open_directory(&mut self, dir: &Path) -> Result<()> {
+        let dir = fs::canonicalize(dir)?;
+        let existing = self.buffers.values_mut().find(|buf| {
+            buf.dir_listing()
+                .is_some_and(|listing| listing.dir() == dir)
+        });
+        let buf_id = if let Some(buf) = existing {
+            buf.refresh_dir_listing()?;
+            buf.id().clone()
+        } else {
+            let buf = Buffer::from_directory(&dir)?;
+            let buf_id = buf.id().clone();
+            self.buffers.insert(buf_id.clone(), buf);
+            buf_id
+        };
+        let pane = self.pane_tree.active_mut();
+        if *pane.buffer_id() != buf_id {
+            pane.switch_buffer(&mut self.buffers, &buf_id);
+        }
+        Ok(())
+    }
+
+    /// Get the listing buffer in the active pane and the entry at its
+    /// cursor, if any.
+    fn active_dir_entry(&self) -> Result<(&Buffer, Option<&DirEntry>)> {
+        let pane = self.pane_tree.active();
+        let buf = self
+            .buffers
+            .get(pane.buffer_id())
+            .context("active pane points to invalid buffer")?;
+        let listing = buf
+            .dir_listing()
+            .context("active buffer is not a directory listing")?;
+        let line = buf.text().char_to_line(buf.cursor(pane.id()));
+        Ok((buf, listing.entry_at_line(line)))
+    }
+
+    /// Open the file or directory at the cursor of the active
+    /// listing.
+    pub(super) fn open_dir_entry(&mut self) -> Result<()> {
+        let (buf, entry) = self.active_dir_entry()?;
+        let Some(entry) = entry else {
+            return Ok(());
+        };
+        let path = buf.directory().unwrap_or(Path::new("/")).join(&entry.name);
+        if entry.is_dir {
+            self.open_directory(&path)
+        } else {
+            self.open_file_at_path(&path)
+        }
+    }
+
+    /// Re-read the directory of the active listing.
+    pub(super) fn refresh_active_dir_listing(&mut self) -> Result<()> {
+        let buf_id = self.active_dir_entry()?.0.id().clone();
+        self.refresh_dir_listing(&buf_id)
+    }
+
+    fn refresh_dir_listing(&mut self, buf_id: &BufferId) -> Result<()> {
+        self.buffers
+            .get_mut(buf_id)
+            .context(format!("invalid buffer: {buf_id}"))?
+            .refresh_dir_listing()
+    }
+
+    /// Open a prompt for creating, renaming or deleting an entry in
+    /// the active listing. `op` gets the name of the entry at the
+    /// cursor.
+    pub(super) fn open_dir_prompt(
+        &mut self,
+        op: impl FnOnce(&DirEntry) -> DirOp,
+    ) -> Result<()> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+
+        let (buf, entry) = self.active_dir_entry()?;
+        let entry = entry.context("no entry at the cursor")?;
+        if entry.is_parent() {
+            bail!("the parent directory can't be changed from here");
+        }
+        let dir = buf.directory().context("listing has no directory")?;
+        let prompt = DirPrompt::new(buf.id().clone(), dir, op(entry));
+        self.overlay = Some(Overlay::DirEntry(prompt));
+        Ok(())
+    }
+
+    /// Open a prompt for the name of an entry to create in the
+    /// active listing.
+    pub(super) fn open_create_dir_entry_prompt(&mut self) -> Result<()> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+
+        let buf = self.active_dir_entry()?.0;
+        let dir = buf.directory().context("listing has no directory")?;
+        let prompt = DirPrompt::new(buf.id().clone(), dir, DirOp::Create);
+        self.overlay = Some(Overlay::DirEntry(prompt));
+        Ok(())
+    }
+
+    /// Close the prompt, carry out its operation and refresh the
+    /// listing it was opened from.
+    pub(super) fn confirm_dir_prompt(&mut self) -> Result<()> {
+        let Some(Overlay::DirEntry(prompt)) = self.overlay.take() else {
+            return Ok(());
+        };
+        let result = prompt.apply();
+        self.refresh_dir_listing(prompt.listing_buffer_id())?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Action, Direction, FilterOutput, Move};
+    use crate::state::test_util::{create_test_state, run, type_text};
+
+    #[test]
+    fn test_dir_listing() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let dir = fs::canonicalize(tmp_dir.path())?;
+        fs::create_dir(dir.join("sub"))?;
+        fs::write(dir.join("sub/inner.txt"), "inner")?;
+        fs::write(dir.join("b.txt"), "b")?;
+
+        let (mut state, _reader, writer) = create_test_state()?;
+        let active_buf = |state: &AppState| -> BufferId {
+            state.pane_tree.active().buffer_id().clone()
+        };
+        let cursor_line = |state: &AppState| {
+            let pane = state.pane_tree.active();
+            let buf = &state.buffers[pane.buffer_id()];
+            buf.text().char_to_line(buf.cursor(pane.id())).0
+        };
+
+        // Opening a directory creates a listing, with the cursor on
+        // the first entry.
+        state.open_file_at_path(&dir)?;
+        let listing_id = active_buf(&state);
+        let text =
+            |state: &AppState| state.buffers[&listing_id].text().to_string();
+        assert!(text(&state).contains(" b.txt\n"));
+        assert_eq!(cursor_line(&state), 2);
+
+        // The listing can't be edited.
+        assert!(state.handle_action(Action::Insert('x'), &writer).is_err());
+        assert!(state.handle_action(Action::Complete, &writer).is_err());
+        assert!(state.overlay.is_none());
+
+        // Filtering the listing fails without running the command.
+        let marker = dir.join("filtered");
+        run(
+            &mut state,
+            &writer,
+            &[Action::FilterRegion(FilterOutput::Replace)],
+        )?;
+        type_text(&mut state, &writer, &format!("touch {}", marker.display()))?;
+        assert!(state.handle_action(Action::Confirm, &writer).is_err());
+        assert!(!marker.exists());
+
+        // Create a file and a directory. The cursor stays on b.txt.
+        for name in ["a.txt", "newdir/"] {
+            run(&mut state, &writer, &[Action::CreateDirEntry])?;
+            type_text(&mut state, &writer, name)?;
+            run(&mut state, &writer, &[Action::Confirm])?;
+        }
+        assert!(dir.join("a.txt").is_file());
+        assert!(dir.join("newdir").is_dir());
+        assert_eq!(cursor_line(&state), 3);
+
+        // Rename b.txt. The cursor follows it.
+        run(&mut state, &writer, &[Action::RenameDirEntry])?;
+        type_text(&mut state, &writer, "_renamed")?;
+        run(&mut state, &writer, &[Action::Confirm])?;
+        assert!(dir.join("b.txt_renamed").is_file());
+        assert!(!dir.join("b.txt").exists());
+        assert_eq!(cursor_line(&state), 3);
+
+        // Deleting requires confirmation.
+        run(&mut state, &writer, &[Action::DeleteDirEntry])?;
+        type_text(&mut state, &writer, "no")?;
+        assert!(state.handle_action(Action::Confirm, &writer).is_err());
+        assert!(dir.join("b.txt_renamed").exists());
+        run(&mut state, &writer, &[Action::DeleteDirEntry])?;
+        type_text(&mut state, &writer, "yes")?;
+        run(&mut state, &writer, &[Action::Confirm])?;
+        assert!(!dir.join("b.txt_renamed").exists());
+        assert!(!text(&state).contains("b.txt"));
+
+        // Changes made elsewhere show up after refreshing.
+        fs::write(dir.join("z.txt"), "")?;
+        run(&mut state, &writer, &[Action::RefreshDirectory])?;
+        assert!(text(&state).contains(" z.txt\n"));
+
+        // Open a subdirectory, then a file in it.
+        run(
+            &mut state,
+            &writer,
+            &[Action::Move(Move::Line, Direction::Inc)],
+        )?;
+        assert_eq!(cursor_line(&state), 4);
+        run(&mut state, &writer, &[Action::Confirm])?;
+        let sub_id = active_buf(&state);
+        assert_eq!(
+            state.buffers[&sub_id].directory(),
+            Some(dir.join("sub").as_path())
+        );
+        run(&mut state, &writer, &[Action::Confirm])?;
+        assert_eq!(
+            state.buffers[&active_buf(&state)].path(),
+            Some(dir.join("sub/inner.txt").as_path())
+        );
+
+        // Going up reuses the existing listing.
+        state.open_file_at_path(&dir.join("sub"))?;
+        assert_eq!(active_buf(&state), sub_id);
+        run(
+            &mut state,
+            &writer,
+            &[Action::Move(Move::Line, Direction::Dec)],
+        )?;
+        run(&mut state, &writer, &[Action::Confirm])?;
+        assert_eq!(active_buf(&state), listing_id);
+
+        Ok(())
+    }
+}
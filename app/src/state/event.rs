@@ -1,34 +1,123 @@
-use crate::action::{Action, Boundary, Direction, Move};
-use crate::buffer::{Buffer, BufferId, LinePosition};
+use crate::action::{Action, Boundary, Direction, FilterOutput, Move};
+use crate::buffer::{AbsChar, Buffer, BufferId, LinePosition};
+use crate::command_line::CommandLine;
 use crate::command_line_widget::CommandLineWidget;
-use crate::key::{Key, Modifiers};
+use crate::dir_prompt::DirOp;
+use crate::key::{Key, Modifier, Modifiers};
 use crate::key_map::{KeyMap, KeyMapLookup, KeyMapStack};
 use crate::key_sequence::{KeySequence, KeySequenceAtom};
+use crate::macro_prompt::MacroOp;
 use crate::message::{Message, MessageWriter};
+use crate::modal::{self, ModalState, Mode};
 use crate::overlay::Overlay;
 use crate::pane_tree::{Pane, PaneTree};
 use crate::path_chooser::PathChooser;
+use crate::prefix_arg::PrefixArg;
 use crate::search_widget::SearchWidget;
-use crate::state::AppState;
+use crate::state::{AppState, KeyHint};
 use crate::widget::Widget;
-use anyhow::{Context, Error, Result, anyhow};
+use crate::{dir_listing, filter};
+use anyhow::{Context, Error, Result, anyhow, bail};
 use fs_err as fs;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 use tracing::{error, info, instrument};
 
 pub(super) struct KeyHandler {
     base_keymap: KeyMap,
+
+    /// Bindings from the config, layered on top of the base keymap.
+    user_keymap: KeyMap,
+
     cur_seq: KeySequence,
+
+    /// Mode and pending operator for modal editing, if enabled.
+    modal: Option<ModalState>,
+
+    /// Count for the next action.
+    prefix_arg: PrefixArg,
+
+    /// The last action run from a key press, with its count, for
+    /// `RepeatLastAction`.
+    last_action: Option<(Action, usize)>,
+
+    /// Incremented on every key press, so that a delayed
+    /// `ShowKeyHints` only applies to the sequence it was sent for.
+    seq_id: u64,
 }
 
+/// How long to wait after a prefix is typed before showing the
+/// bindings that could complete it.
+const KEY_HINTS_DELAY: Duration = Duration::from_millis(500);
+
 impl KeyHandler {
     pub(super) fn new() -> Result<Self> {
         Ok(Self {
             base_keymap: KeyMap::base()?,
+            user_keymap: KeyMap::new("user"),
             cur_seq: KeySequence::default(),
+            modal: None,
+            prefix_arg: PrefixArg::default(),
+            last_action: None,
+            seq_id: 0,
         })
     }
+
+    pub(super) fn set_user_keymap(&mut self, keymap: KeyMap) {
+        self.user_keymap = keymap;
+    }
+
+    /// Turn modal editing on or off. It starts in normal mode.
+    pub(super) fn set_modal_editing(&mut self, enabled: bool) {
+        if enabled != self.modal.is_some() {
+            self.modal = enabled.then(ModalState::new);
+        }
+    }
+
+    pub(super) fn modal(&self) -> Option<&ModalState> {
+        self.modal.as_ref()
+    }
+
+    pub(super) fn modal_mut(&mut self) -> Option<&mut ModalState> {
+        self.modal.as_mut()
+    }
+
+    pub(super) fn prefix_arg_mut(&mut self) -> &mut PrefixArg {
+        &mut self.prefix_arg
+    }
+
+    pub(super) fn set_last_action(&mut self, action: Option<(Action, usize)>) {
+        self.last_action = action;
+    }
+}
+
+/// Check if `action` builds up the count for the next action, rather
+/// than taking a count itself.
+fn is_prefix_arg_action(action: &Action) -> bool {
+    matches!(action, Action::UniversalArgument | Action::CountDigit(_))
+}
+
+/// Check if `action` should be remembered for `RepeatLastAction`.
+pub(super) fn is_repeatable(action: &Action) -> bool {
+    !is_prefix_arg_action(action) && *action != Action::RepeatLastAction
+}
+
+/// Get the directory that a new file or process opened from `buf`
+/// should start in.
+pub(super) fn default_directory(buf: &Buffer) -> PathBuf {
+    buf.directory()
+        .map(|p| p.to_owned())
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default())
+}
+
+/// Fail if `buf`'s text can't be edited.
+pub(super) fn check_editable(buf: &Buffer) -> Result<()> {
+    if buf.is_read_only() {
+        bail!("buffer is read-only");
+    }
+    Ok(())
 }
 
 fn invalid_active_buffer_error() -> Error {
@@ -46,7 +135,7 @@ fn active_buffer_mut<'b>(
 }
 
 impl AppState {
-    fn active_buffer(&self) -> Result<&Buffer> {
+    pub(super) fn active_buffer(&self) -> Result<&Buffer> {
         if let Some(overlay) = &self.overlay {
             return Ok(overlay.buffer());
         }
@@ -72,7 +161,9 @@ impl AppState {
         Ok(buf)
     }
 
-    fn active_pane_buffer_mut(&mut self) -> Result<(&Pane, &mut Buffer)> {
+    pub(super) fn active_pane_buffer_mut(
+        &mut self,
+    ) -> Result<(&Pane, &mut Buffer)> {
         if let Some(overlay) = &mut self.overlay {
             return Ok(overlay.pane_buffer_mut());
         }
@@ -85,7 +176,7 @@ impl AppState {
         Ok((pane, buf))
     }
 
-    fn active_pane_mut_buffer_mut(
+    pub(super) fn active_pane_mut_buffer_mut(
         &mut self,
     ) -> Result<(&mut Pane, &mut Buffer)> {
         if let Some(overlay) = &mut self.overlay {
@@ -105,6 +196,7 @@ impl AppState {
         direction: Direction,
     ) -> Result<()> {
         let (pane, buf) = self.active_pane_buffer_mut()?;
+        check_editable(buf)?;
         let pos = buf.cursor(pane.id());
         let boundary = buf.find_boundary(pos, boundary, direction);
         if pos != boundary {
@@ -121,16 +213,22 @@ impl AppState {
     /// Insert a character into the active pane.
     fn insert_char(&mut self, c: char) -> Result<()> {
         let (pane, buf) = self.active_pane_buffer_mut()?;
+        check_editable(buf)?;
         let pos = buf.cursor(pane.id());
         buf.insert_char(c, pos);
         Ok(())
     }
 
-    fn move_cursor(&mut self, step: Move, dir: Direction) -> Result<()> {
+    pub(super) fn move_cursor(
+        &mut self,
+        step: Move,
+        dir: Direction,
+        count: usize,
+    ) -> Result<()> {
         let line_height = self.line_height;
         let (pane, buf) = self.active_pane_mut_buffer_mut()?;
 
-        buf.move_cursor(pane.id(), step, dir);
+        pane.move_cursor(buf, step, dir, count, line_height);
 
         let cursor = buf.cursor(pane.id());
         pane.maybe_rescroll(buf, cursor, line_height);
@@ -138,15 +236,124 @@ impl AppState {
         Ok(())
     }
 
-    /// Display an error message.
-    fn display_error(&mut self, error: Error) {
-        // TODO: think about how to display this in the UI.
-        println!("error: {error}");
+    /// Display an error message in the echo area.
+    pub(super) fn display_error(&mut self, error: Error) {
+        self.message = Some(format!("error: {error:#}"));
+    }
+
+    /// Create a command line prompt that starts in the directory of
+    /// the active buffer, with that directory's command history.
+    fn create_command_line_widget(&self) -> Result<CommandLineWidget> {
+        let directory = default_directory(self.active_buffer()?);
+        let history =
+            self.load_command_history(&directory).unwrap_or_else(|err| {
+                error!("failed to load command history: {err}");
+                Vec::new()
+            });
+        Ok(CommandLineWidget::new(directory, history))
+    }
+
+    /// Run the formatter configured for the active buffer's file
+    /// type (if any) on the buffer's contents. The result is a single
+    /// undo step.
+    fn format_active_buffer(&mut self) -> Result<()> {
+        let buf = self.active_buffer()?;
+        let Some(path) = buf.path() else {
+            return Ok(());
+        };
+        let Some(formatter) =
+            self.config.formatter_for(path, &buf.syntax_name())
+        else {
+            return Ok(());
+        };
+
+        let mut command_line = CommandLine::from_string(&formatter.command)?;
+        command_line.set_current_dir(buf.directory().map(|p| p.to_owned()));
+        let formatted =
+            filter::run_filter(&command_line, &buf.text().to_string())?;
+
+        self.active_buffer_mut()?.update_text(&formatted);
+        Ok(())
+    }
+
+    /// Write the active buffer to its file, formatting it first if a
+    /// formatter is configured. A formatter failure is reported but
+    /// does not prevent the save.
+    fn save_file(&mut self) -> Result<()> {
+        if self.active_buffer()?.path().is_none() {
+            bail!("attempted to save a buffer with no path");
+        }
+
+        if let Err(err) = self.format_active_buffer() {
+            error!("failed to format buffer: {err}");
+            self.display_error(err.context("failed to format buffer"));
+        }
+
+        let buf = self.active_buffer()?;
+        if let Some(path) = buf.path() {
+            fs::write(path, buf.text().to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Send the selection in the active pane (or the whole buffer if
+    /// there is no selection) through `command_line`. If the command
+    /// fails, the buffer is left unchanged.
+    fn filter_region(
+        &mut self,
+        command_line: &CommandLine,
+        output: FilterOutput,
+    ) -> Result<()> {
+        let (pane, buf) = self.active_pane_buffer_mut()?;
+        let range = buf
+            .selection(pane.id())
+            .unwrap_or(AbsChar(0)..AbsChar(buf.text().len_chars()));
+        let input = buf.text().slice(range.clone()).to_string();
+
+        // Check before running the command, which may have side
+        // effects.
+        if output == FilterOutput::Replace {
+            check_editable(buf)?;
+        }
+        let filtered = filter::run_filter(command_line, &input)?;
+
+        match output {
+            FilterOutput::Replace => {
+                buf.replace_text(range, &filtered);
+                buf.clear_mark(pane.id());
+            }
+            FilterOutput::NewBuffer => {
+                let mut new_buf = Buffer::create_empty();
+                new_buf.set_name(&format!("*{command_line}*"));
+                new_buf.set_text(&filtered);
+                let new_buf_id = new_buf.id().clone();
+                self.buffers.insert(new_buf_id.clone(), new_buf);
+                self.pane_tree
+                    .active_mut()
+                    .switch_buffer(&mut self.buffers, &new_buf_id);
+            }
+        }
+
+        Ok(())
     }
 
-    fn open_file_at_path(&mut self, path: &Path) -> Result<()> {
+    /// Open a file in a new buffer in the active pane. A directory is
+    /// opened as a listing.
+    pub fn open_file_at_path(&mut self, path: &Path) -> Result<()> {
+        if path.is_dir() {
+            return self.open_directory(path);
+        }
+
         // Load the file in a new buffer.
-        let buf = Buffer::from_path(path)?;
+        let mut buf = Buffer::from_path(path)?;
+        if let Err(err) = self.store_file_history(path) {
+            error!("failed to store file history: {err}");
+        }
+        match self.load_view_position(path) {
+            Ok(Some(view)) => buf.set_last_view(view),
+            Ok(None) => {}
+            Err(err) => error!("failed to load view position: {err}"),
+        }
         let buf_id = buf.id().clone();
         self.buffers.insert(buf_id.clone(), buf);
         self.pane_tree
@@ -163,12 +370,18 @@ impl AppState {
                 self.open_file_at_path(&path)?;
             }
             Some(Overlay::RunProcess(command_line_widget)) => {
+                let command_line = command_line_widget.command_line()?;
+                let directory = command_line_widget.directory().to_owned();
+                let text = command_line_widget.text();
+
                 let mut buf = Buffer::create_for_non_interactive_process();
                 let buf_id = buf.id().clone();
-                buf.run_non_interactive_process(
-                    command_line_widget.command_line(),
-                    message_writer,
-                )?;
+                buf.run_non_interactive_process(command_line, message_writer)?;
+
+                if let Err(err) = self.store_command_history(&directory, &text)
+                {
+                    error!("failed to store command history: {err}");
+                }
 
                 self.buffers.insert(buf_id.clone(), buf);
                 self.pane_tree
@@ -177,6 +390,20 @@ impl AppState {
 
                 self.overlay = None;
             }
+            Some(Overlay::Filter(command_line_widget, output)) => {
+                let output = *output;
+                let command_line = command_line_widget.command_line()?;
+                let directory = command_line_widget.directory().to_owned();
+                let text = command_line_widget.text();
+
+                self.overlay = None;
+                self.filter_region(&command_line, output)?;
+
+                if let Err(err) = self.store_command_history(&directory, &text)
+                {
+                    error!("failed to store command history: {err}");
+                }
+            }
             Some(Overlay::Search(_)) => {
                 self.overlay = None;
 
@@ -189,7 +416,29 @@ impl AppState {
 
                 buf.clear_search();
             }
-            None => {}
+            Some(Overlay::Complete(_)) => {
+                self.confirm_completion()?;
+            }
+            Some(Overlay::SwitchBuffer(_)) => {
+                self.confirm_buffer_switch()?;
+            }
+            Some(Overlay::FindFile(_)) => {
+                self.confirm_find_file()?;
+            }
+            Some(Overlay::DirEntry(_)) => {
+                self.confirm_dir_prompt()?;
+            }
+            Some(Overlay::Macro(_)) => {
+                self.confirm_macro_prompt(message_writer)?;
+            }
+            Some(Overlay::CommandPalette(_)) => {
+                self.confirm_command_palette(message_writer)?;
+            }
+            None => {
+                if self.active_buffer()?.dir_listing().is_some() {
+                    self.open_dir_entry()?;
+                }
+            }
         }
 
         Ok(())
@@ -213,7 +462,26 @@ impl AppState {
                     (pane.rect().height / line_height.0).round() as usize;
                 buf.search(&search.text(), pane, num_lines);
             }
-            Some(Overlay::RunProcess(_)) | None => {}
+            Some(Overlay::Complete(widget)) => {
+                widget.update_matches();
+            }
+            Some(Overlay::SwitchBuffer(switcher)) => {
+                switcher.update_matches();
+                self.preview_selected_buffer()?;
+            }
+            Some(Overlay::FindFile(finder)) => {
+                finder.update_matches();
+            }
+            Some(Overlay::CommandPalette(palette)) => {
+                palette.update_matches();
+            }
+            Some(
+                Overlay::RunProcess(_)
+                | Overlay::Filter(..)
+                | Overlay::DirEntry(_)
+                | Overlay::Macro(_),
+            )
+            | None => {}
         }
 
         Ok(())
@@ -244,8 +512,34 @@ impl AppState {
         action: Action,
         message_writer: &MessageWriter,
     ) -> Result<()> {
-        info!("handling action {:?}", action);
+        self.handle_action_with_count(action, 1, message_writer)
+    }
+
+    /// Handle `action`, repeated or scaled by `count`. Moving,
+    /// deleting and inserting are repeated `count` times; other
+    /// actions that don't take a count ignore it. Actions that
+    /// succeed are recorded into the keyboard macro being defined.
+    pub fn handle_action_with_count(
+        &mut self,
+        action: Action,
+        count: usize,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
+        info!("handling action {:?} with count {count}", action);
+
+        let recorded = action.clone();
+        self.dispatch_action(action, count, message_writer)?;
+        self.macros.record(&recorded, count);
+        Ok(())
+    }
 
+    /// Handle `action` without recording it into a keyboard macro.
+    pub(super) fn dispatch_action(
+        &mut self,
+        action: Action,
+        count: usize,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
         let buffer_changed;
 
         match action {
@@ -254,15 +548,95 @@ impl AppState {
                 buffer_changed = false;
             }
             Action::Insert(key) => {
-                self.insert_char(key)?;
+                for _ in 0..count {
+                    self.insert_char(key)?;
+                }
                 buffer_changed = true;
             }
             Action::Move(step, dir) => {
-                self.move_cursor(step, dir)?;
+                buffer_changed = self.modal_move(step, dir, count)?;
+            }
+            Action::Scroll(dir) => {
+                self.scroll(dir, count)?;
+                buffer_changed = false;
+            }
+            Action::Recenter => {
+                self.recenter()?;
+                buffer_changed = false;
+            }
+            Action::ToggleWrap => {
+                self.toggle_wrap()?;
+                buffer_changed = false;
+            }
+            Action::SetMode(mode) => {
+                self.set_mode(mode)?;
+                buffer_changed = false;
+            }
+            Action::Operator(operator) => {
+                buffer_changed = self.push_operator(operator, count)?;
+            }
+            Action::UniversalArgument => {
+                self.key_handler.prefix_arg.push_universal();
+                buffer_changed = false;
+            }
+            Action::RepeatLastAction => {
+                let Some((action, last_count)) =
+                    self.key_handler.last_action.clone()
+                else {
+                    bail!("no action to repeat");
+                };
+                for _ in 0..count {
+                    self.handle_action_with_count(
+                        action.clone(),
+                        last_count,
+                        message_writer,
+                    )?;
+                }
+                buffer_changed = false;
+            }
+            Action::StartMacro => {
+                self.start_macro()?;
+                buffer_changed = false;
+            }
+            Action::EndMacro => {
+                self.end_macro()?;
+                buffer_changed = false;
+            }
+            Action::ReplayMacro => {
+                self.replay_last_macro(count, message_writer)?;
+                buffer_changed = false;
+            }
+            Action::SaveMacro => {
+                self.open_macro_prompt(MacroOp::Save, count)?;
+                buffer_changed = false;
+            }
+            Action::RunSavedMacro => {
+                self.open_macro_prompt(MacroOp::Run, count)?;
+                buffer_changed = false;
+            }
+            Action::RunMacro(name) => {
+                self.run_saved_macro(&name, count, message_writer)?;
+                buffer_changed = false;
+            }
+            Action::CountDigit(digit) => {
+                buffer_changed = self.push_count_digit(digit)?;
+            }
+            Action::SetMark => {
+                let (pane, buf) = self.active_pane_buffer_mut()?;
+                buf.set_mark(pane.id(), buf.cursor(pane.id()));
+                buffer_changed = false;
+            }
+            Action::FilterRegion(output) => {
+                self.overlay = Some(Overlay::Filter(
+                    self.create_command_line_widget()?,
+                    output,
+                ));
                 buffer_changed = false;
             }
             Action::Delete(boundary, direction) => {
-                self.delete_text(boundary, direction)?;
+                for _ in 0..count {
+                    self.delete_text(boundary, direction)?;
+                }
                 buffer_changed = true;
             }
             Action::InteractiveSearch => {
@@ -275,18 +649,20 @@ impl AppState {
             }
             Action::Undo => {
                 let buf = self.active_buffer_mut()?;
+                check_editable(buf)?;
                 buf.undo();
                 buffer_changed = true;
             }
             Action::Redo => {
                 let buf = self.active_buffer_mut()?;
+                check_editable(buf)?;
                 buf.redo();
                 buffer_changed = true;
             }
             Action::SplitPane(orientation) => {
                 let buf =
                     active_buffer_mut(&self.pane_tree, &mut self.buffers)?;
-                self.pane_tree.split(orientation, buf);
+                self.pane_tree.split(orientation, buf)?;
                 buffer_changed = false;
             }
             Action::PreviousPane => {
@@ -297,6 +673,43 @@ impl AppState {
                 self.pane_tree.make_next_pane_active();
                 buffer_changed = false;
             }
+            Action::ClosePane => {
+                self.pane_tree.close_active(&mut self.buffers)?;
+                buffer_changed = false;
+            }
+            Action::CloseOtherPanes => {
+                self.pane_tree.close_other_panes(&mut self.buffers);
+                buffer_changed = false;
+            }
+            Action::ResizePane(orientation, direction) => {
+                // Resize by one line at a time.
+                let delta = match direction {
+                    Direction::Inc => self.line_height.0,
+                    Direction::Dec => -self.line_height.0,
+                };
+                self.pane_tree.resize_active(orientation, delta)?;
+                buffer_changed = false;
+            }
+            Action::BalancePanes => {
+                self.pane_tree.balance();
+                buffer_changed = false;
+            }
+            Action::FocusPane(orientation, direction) => {
+                self.pane_tree.focus(orientation, direction)?;
+                buffer_changed = false;
+            }
+            Action::SwapPane(orientation, direction) => {
+                self.pane_tree.swap(orientation, direction)?;
+                buffer_changed = false;
+            }
+            Action::MovePane(orientation, direction) => {
+                self.pane_tree.move_active(orientation, direction)?;
+                buffer_changed = false;
+            }
+            Action::RotateSplit => {
+                self.pane_tree.rotate_split()?;
+                buffer_changed = false;
+            }
             Action::DeleteBuffer => {
                 let active_buffer_id = self.active_buffer()?.id().clone();
 
@@ -324,17 +737,7 @@ impl AppState {
                 buffer_changed = false;
             }
             Action::OpenFile => {
-                let buf = self.active_buffer()?;
-                // TODO: actually should have a buf.directory() method,
-                // since in the future a dir buffer might display a
-                // directory rather than a file. Or a shell buffer.
-                let default_path = buf
-                    .path()
-                    .and_then(|p| p.parent())
-                    .map(|p| p.to_owned())
-                    .unwrap_or_else(|| {
-                        std::env::current_dir().unwrap_or_default()
-                    });
+                let default_path = default_directory(self.active_buffer()?);
 
                 self.overlay =
                     Some(Overlay::OpenFile(PathChooser::new(&default_path)?));
@@ -342,34 +745,131 @@ impl AppState {
                 buffer_changed = false;
             }
             Action::SaveFile => {
-                let buf = self.active_buffer_mut()?;
-                if let Some(path) = buf.path() {
-                    fs::write(path, buf.text().to_string())?;
-                } else {
-                    todo!("attempted to save a buffer with no path");
-                }
-                buffer_changed = false;
+                self.save_file()?;
+                // Formatting may have changed the text.
+                buffer_changed = true;
             }
             Action::Confirm => {
                 self.handle_confirm(message_writer)?;
                 buffer_changed = false;
             }
+            Action::ShowKeyHints(seq_id) => {
+                self.show_key_hints(seq_id);
+                buffer_changed = false;
+            }
             Action::Cancel => {
-                self.overlay = None;
+                if matches!(self.overlay, Some(Overlay::SwitchBuffer(_))) {
+                    self.cancel_buffer_switch();
+                } else if matches!(self.overlay, Some(Overlay::Complete(_))) {
+                    self.cancel_completion()?;
+                } else if self.overlay.is_some() {
+                    self.overlay = None;
+                } else {
+                    // Deselect.
+                    let (pane, buf) = self.active_pane_buffer_mut()?;
+                    buf.clear_mark(pane.id());
+                }
+                self.message = None;
                 // TODO: clear search highlight
                 buffer_changed = false;
             }
             Action::Autocomplete => {
                 if let Some(Overlay::OpenFile(open_file)) = &mut self.overlay {
-                    open_file.autocomplete()?;
+                    open_file.autocomplete();
                 }
                 buffer_changed = true;
             }
+            Action::Complete => {
+                self.start_completion()?;
+                buffer_changed = false;
+            }
+            Action::SelectCandidate(dir) => {
+                match &mut self.overlay {
+                    Some(Overlay::Complete(widget)) => widget.select(dir),
+                    Some(Overlay::SwitchBuffer(switcher)) => {
+                        switcher.select(dir);
+                        self.preview_selected_buffer()?;
+                    }
+                    Some(Overlay::FindFile(finder)) => finder.select(dir),
+                    Some(Overlay::CommandPalette(palette)) => {
+                        palette.select(dir);
+                    }
+                    Some(Overlay::OpenFile(open_file)) => open_file.select(dir),
+                    _ => {}
+                }
+                buffer_changed = false;
+            }
+            Action::OpenCommandPalette => {
+                self.open_command_palette(count)?;
+                buffer_changed = false;
+            }
+            Action::SwitchToBuffer => {
+                self.open_buffer_switcher()?;
+                buffer_changed = false;
+            }
+            Action::FindFileInProject => {
+                self.open_file_finder(message_writer)?;
+                buffer_changed = false;
+            }
+            Action::RefreshDirectory => {
+                self.refresh_active_dir_listing()?;
+                buffer_changed = false;
+            }
+            Action::CreateDirEntry => {
+                self.open_create_dir_entry_prompt()?;
+                buffer_changed = false;
+            }
+            Action::RenameDirEntry => {
+                self.open_dir_prompt(|entry| {
+                    DirOp::Rename(entry.name.clone())
+                })?;
+                buffer_changed = false;
+            }
+            Action::DeleteDirEntry => {
+                self.open_dir_prompt(|entry| {
+                    DirOp::Delete(entry.name.clone())
+                })?;
+                buffer_changed = false;
+            }
+            Action::FoundFiles(search_id, paths) => {
+                self.add_found_files(&search_id, paths);
+                buffer_changed = false;
+            }
+            Action::GoToDefinition => {
+                self.request_definition()?;
+                buffer_changed = false;
+            }
+            Action::ShowHover => {
+                self.request_hover()?;
+                buffer_changed = false;
+            }
+            Action::Lsp(event) => {
+                buffer_changed = self.handle_lsp_event(event)?;
+            }
             Action::RunNonInteractiveProcess => {
-                self.overlay =
-                    Some(Overlay::RunProcess(CommandLineWidget::new()));
+                self.overlay = Some(Overlay::RunProcess(
+                    self.create_command_line_widget()?,
+                ));
                 buffer_changed = false;
             }
+            Action::PreviousHistoryItem => {
+                if let Some(
+                    Overlay::RunProcess(widget) | Overlay::Filter(widget, _),
+                ) = &mut self.overlay
+                {
+                    widget.history_previous();
+                }
+                buffer_changed = true;
+            }
+            Action::NextHistoryItem => {
+                if let Some(
+                    Overlay::RunProcess(widget) | Overlay::Filter(widget, _),
+                ) = &mut self.overlay
+                {
+                    widget.history_next();
+                }
+                buffer_changed = true;
+            }
             Action::RerunProcess => {
                 let buf = self.active_buffer_mut()?;
                 let buf_id = buf.id().clone();
@@ -409,10 +909,15 @@ impl AppState {
             }
         }
 
+        self.follow_cursor_column()?;
+
         if buffer_changed {
             self.handle_buffer_changed()?;
         }
 
+        self.record_buffer_use();
+        self.update_language_servers(message_writer);
+
         if let Err(err) = self.persistence_store() {
             error!("failed to persist state: {err}");
         }
@@ -420,47 +925,153 @@ impl AppState {
         Ok(())
     }
 
-    pub fn handle_key_press(
-        &mut self,
-        key: Key,
-        modifiers: Modifiers,
-        message_writer: &MessageWriter,
-    ) {
+    /// Get the keymaps that apply in the current state, lowest
+    /// priority first.
+    pub(super) fn keymap_stack(&self) -> KeyMapStack {
         let mut keymap_stack = KeyMapStack::default();
         keymap_stack.push(Ok(self.key_handler.base_keymap.clone()));
 
+        // Mode keymaps only apply to buffers, so prompts can always
+        // be typed in.
+        if let Some(modal) = &self.key_handler.modal
+            && self.overlay.is_none()
+        {
+            keymap_stack.push(modal::keymap(modal.mode()));
+            if modal.mode() != Mode::Insert {
+                keymap_stack.disable_insert();
+            }
+        }
+
+        keymap_stack.push(Ok(self.key_handler.user_keymap.clone()));
+
         if let Some(overlay) = &self.overlay {
             keymap_stack.push(overlay.get_keymap());
+        } else if self
+            .active_buffer()
+            .is_ok_and(|buf| buf.dir_listing().is_some())
+        {
+            keymap_stack.push(dir_listing::keymap());
         }
+        keymap_stack
+    }
 
-        // Ignore lone modifier presses.
+    /// Send `Action::ShowKeyHints` for the pending sequence after a
+    /// delay.
+    fn schedule_key_hints(&self, message_writer: &MessageWriter) {
+        let message_writer = match message_writer.try_clone() {
+            Ok(message_writer) => message_writer,
+            Err(err) => {
+                error!("failed to clone message writer: {err}");
+                return;
+            }
+        };
+        let seq_id = self.key_handler.seq_id;
+        thread::spawn(move || {
+            thread::sleep(KEY_HINTS_DELAY);
+            let msg = Message::Action(Action::ShowKeyHints(seq_id));
+            if let Err(err) = message_writer.send(msg) {
+                error!("failed to send key hints: {err}");
+            }
+        });
+    }
+
+    /// List the bindings that could complete the pending sequence,
+    /// unless another key has been pressed since `seq_id` was sent.
+    fn show_key_hints(&mut self, seq_id: u64) {
+        let cur_seq = &self.key_handler.cur_seq;
+        if seq_id != self.key_handler.seq_id || cur_seq.0.is_empty() {
+            return;
+        }
+        self.key_hints = self
+            .keymap_stack()
+            .continuations(cur_seq)
+            .into_iter()
+            .map(|(keys, action)| KeyHint {
+                keys: KeySequence(keys.0[cur_seq.0.len()..].to_vec())
+                    .to_string(),
+                action: format!("{action:?}"),
+            })
+            .collect();
+    }
+
+    pub fn handle_key_press(
+        &mut self,
+        key: Key,
+        modifiers: Modifiers,
+        message_writer: &MessageWriter,
+    ) {
+        // Ignore modifier presses, including one modifier pressed
+        // while holding another, e.g. shift while holding alt.
         if key.is_modifier() {
             return;
         }
 
-        // TODO: we want to ignore combo modifier presses too if no
-        // non-modifier key is selected, e.g. pressing alt and then
-        // shift, but currently that is treated as a valid
-        // sequence. Need to figure out how to prevent that.
+        // Any key press dismisses the previous message and hints.
+        self.message = None;
+        self.key_hints.clear();
+        self.key_handler.seq_id += 1;
 
         let atom = KeySequenceAtom::from_event(key, modifiers);
+
+        // <ctrl>g aborts a partially typed sequence or count,
+        // whatever the keymaps bind it to.
+        if (!self.key_handler.cur_seq.0.is_empty()
+            || !self.key_handler.prefix_arg.is_empty())
+            && atom.key == Key::Char('g')
+            && atom.modifiers == Modifier::Control
+        {
+            self.key_handler.cur_seq.0.clear();
+            self.key_handler.prefix_arg = PrefixArg::default();
+            return;
+        }
+
+        // After <ctrl>u, digits are part of the count.
+        if self.key_handler.cur_seq.0.is_empty()
+            && self.key_handler.prefix_arg.accepts_digits()
+            && atom.modifiers.is_empty()
+            && let Some(digit) = atom.key.to_char().and_then(|c| c.to_digit(10))
+        {
+            self.key_handler.prefix_arg.push_digit(digit as u8);
+            self.message = Some(self.key_handler.prefix_arg.to_string());
+            return;
+        }
+
         self.key_handler.cur_seq.0.push(atom);
 
         let mut clear_seq = true;
-        match keymap_stack.lookup(&self.key_handler.cur_seq) {
+        match self.keymap_stack().lookup(&self.key_handler.cur_seq) {
             KeyMapLookup::BadSequence => {
-                // TODO: display some kind of non-blocking error
-                dbg!("bad seq", &self.key_handler.cur_seq);
+                self.message =
+                    Some(format!("{} is not bound", self.key_handler.cur_seq));
+                self.key_handler.prefix_arg = PrefixArg::default();
             }
             KeyMapLookup::Prefix => {
+                // Wait for the sequence to be completed, showing what
+                // has been typed so far.
                 clear_seq = false;
-                // Waiting for the sequence to be completed.
+                self.message = Some(format!("{}+", self.key_handler.cur_seq));
+                self.schedule_key_hints(message_writer);
             }
             KeyMapLookup::Action(action) => {
-                if let Err(err) = self.handle_action(action, message_writer) {
+                let count = if is_prefix_arg_action(&action) {
+                    1
+                } else {
+                    self.key_handler.prefix_arg.take()
+                };
+                if is_repeatable(&action) {
+                    self.key_handler.last_action =
+                        Some((action.clone(), count));
+                }
+                if let Err(err) =
+                    self.handle_action_with_count(action, count, message_writer)
+                {
                     error!("failed to handle action: {err}");
                     self.display_error(err);
                 }
+                if !self.key_handler.prefix_arg.is_empty() {
+                    self.message =
+                        Some(self.key_handler.prefix_arg.to_string());
+                }
             }
         }
 
@@ -473,12 +1084,12 @@ impl AppState {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::message::create_message_pipe;
-
-    // TODO: simplify AppState::load, then maybe won't need this anymore.
-    pub(crate) fn create_empty_app_state() -> AppState {
-        AppState::load(&[], Err(anyhow!("")))
-    }
+    use crate::LineHeight;
+    use crate::config::Config;
+    use crate::message::MessageReader;
+    use crate::state::test_util::{
+        active_text, create_empty_app_state, create_test_state,
+    };
 
     // TODO: experimenting with gtk test.
     #[test]
@@ -493,12 +1104,158 @@ mod tests {
         assert_eq!(app_state.buffers.len(), 1);
     }
 
+    /// Test that bindings from the config override the base keymap.
+    #[test]
+    fn test_user_keymap() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        state.set_config(Config::from_yaml(
+            "keymap:
+  <ctrl>f: !Insert z
+  <ctrl>x+<ctrl>x: Nope
+",
+        )?);
+        assert!(state.message().unwrap().starts_with(
+            "invalid key bindings in config:
+line 3: invalid binding for \"<ctrl>x+<ctrl>x\": unknown variant `Nope`"
+        ));
+
+        let mut ctrl = Modifiers::new();
+        ctrl.enable_modifier(Modifier::Control);
+        state.handle_key_press(Key::Char('f'), ctrl, &writer);
+        assert_eq!(state.active_buffer()?.text().to_string(), "z");
+
+        Ok(())
+    }
+
+    /// Test feedback while typing a key sequence, and cancelling it.
+    #[test]
+    fn test_pending_key_sequence() -> Result<()> {
+        let (mut state, mut reader, writer) = create_test_state()?;
+        let mut ctrl = Modifiers::new();
+        ctrl.enable_modifier(Modifier::Control);
+
+        // A prefix is shown in the echo area, and after a delay the
+        // possible continuations are listed.
+        state.handle_key_press(Key::Char('x'), ctrl, &writer);
+        assert_eq!(state.message(), Some("<ctrl>x+"));
+        assert!(state.key_hints().is_empty());
+
+        // Modifiers pressed on their own don't add to the sequence.
+        let mut alt_shift = Modifiers::from(Modifier::Alt);
+        state.handle_key_press(
+            Key::Modifier(Modifier::Alt),
+            alt_shift,
+            &writer,
+        );
+        alt_shift.enable_modifier(Modifier::Shift);
+        state.handle_key_press(
+            Key::Modifier(Modifier::Meta),
+            alt_shift,
+            &writer,
+        );
+        assert_eq!(state.message(), Some("<ctrl>x+"));
+        let Message::Action(action) = reader.read()? else {
+            panic!();
+        };
+        state.handle_action(action, &writer)?;
+        let hints = state.key_hints();
+        assert!(
+            hints
+                .iter()
+                .any(|hint| hint.keys == "<ctrl>f" && hint.action == "OpenFile")
+        );
+        assert!(hints.iter().all(|hint| !hint.keys.starts_with("<ctrl>x")));
+
+        // <ctrl>g aborts the sequence, so the next key is inserted.
+        state.handle_key_press(Key::Char('g'), ctrl, &writer);
+        assert_eq!(state.message(), None);
+        assert!(state.key_hints().is_empty());
+        state.handle_key_press(Key::Char('f'), Modifiers::new(), &writer);
+        assert_eq!(state.active_buffer()?.text().to_string(), "f");
+
+        // Hints sent for an old sequence are ignored.
+        state.handle_key_press(Key::Char('x'), ctrl, &writer);
+        state.handle_action(Action::ShowKeyHints(0), &writer)?;
+        assert!(state.key_hints().is_empty());
+
+        // Unbound sequences are reported.
+        state.handle_key_press(Key::Char('q'), Modifiers::new(), &writer);
+        assert_eq!(state.message(), Some("<ctrl>x+q is not bound"));
+        assert_eq!(state.active_buffer()?.text().to_string(), "f");
+
+        Ok(())
+    }
+
+    /// Test counts typed with <ctrl>u, and repeating the last action.
+    #[test]
+    fn test_prefix_arg() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        let mut ctrl = Modifiers::new();
+        ctrl.enable_modifier(Modifier::Control);
+
+        // <ctrl>u alone is four.
+        state.handle_key_press(Key::Char('u'), ctrl, &writer);
+        assert_eq!(state.message(), Some("<ctrl>u 4-"));
+        state.handle_key_press(Key::Char('a'), Modifiers::new(), &writer);
+        assert_eq!(active_text(&state), "aaaa");
+
+        // Digits after it give the count.
+        state.handle_key_press(Key::Char('u'), ctrl, &writer);
+        state.handle_key_press(Key::Char('1'), Modifiers::new(), &writer);
+        state.handle_key_press(Key::Char('0'), Modifiers::new(), &writer);
+        assert_eq!(state.message(), Some("<ctrl>u 10-"));
+        state.handle_key_press(Key::Char('b'), Modifiers::new(), &writer);
+        assert_eq!(active_text(&state), format!("aaaa{}", "b".repeat(10)));
+
+        // The count applies to deletion, and the deletion can be
+        // repeated.
+        state.handle_key_press(Key::Char('u'), ctrl, &writer);
+        state.handle_key_press(Key::Char('3'), Modifiers::new(), &writer);
+        state.handle_key_press(Key::Backspace, Modifiers::new(), &writer);
+        assert_eq!(active_text(&state), format!("aaaa{}", "b".repeat(7)));
+        state.handle_key_press(Key::Char('x'), ctrl, &writer);
+        state.handle_key_press(Key::Char('z'), Modifiers::new(), &writer);
+        assert_eq!(active_text(&state), format!("aaaa{}", "b".repeat(4)));
+
+        // <ctrl>g drops the count.
+        state.handle_key_press(Key::Char('u'), ctrl, &writer);
+        state.handle_key_press(Key::Char('g'), ctrl, &writer);
+        state.handle_key_press(Key::Char('c'), Modifiers::new(), &writer);
+        assert_eq!(active_text(&state), format!("aaaa{}c", "b".repeat(4)));
+
+        Ok(())
+    }
+
+    /// Test that moving by a page uses the pane's height.
+    #[test]
+    fn test_move_page() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        state.set_line_height(LineHeight(10.0));
+        state.recalc_layout(400.0, 210.0);
+        let text: String = (0..100).map(|i| format!("{i}\n")).collect();
+        state.active_buffer_mut()?.set_text(&text);
+
+        let cursor_line = |state: &AppState| -> Result<usize> {
+            let buf = state.active_buffer()?;
+            let pane = state.pane_tree.active();
+            Ok(buf.text().char_to_line(buf.cursor(pane.id())).0)
+        };
+
+        // 20 lines of pane, less the info bar and two lines of
+        // context.
+        let page = Action::Move(Move::Page, Direction::Inc);
+        state.handle_action(page.clone(), &writer)?;
+        assert_eq!(cursor_line(&state)?, 17);
+        state.handle_action_with_count(page, 2, &writer)?;
+        assert_eq!(cursor_line(&state)?, 51);
+
+        Ok(())
+    }
+
     /// Test running a non-interactive process in a buffer.
     #[test]
     fn test_non_interactive_process() -> Result<()> {
-        let mut state = create_empty_app_state();
-
-        let (mut reader, writer) = create_message_pipe()?;
+        let (mut state, mut reader, writer) = create_test_state()?;
 
         state.handle_action(Action::RunNonInteractiveProcess, &writer)?;
 
@@ -537,12 +1294,183 @@ mod tests {
         Ok(())
     }
 
+    /// Run a non-interactive process and wait for it to finish.
+    /// Returns the ID of the process's buffer.
+    fn run_process_to_completion(
+        state: &mut AppState,
+        reader: &mut MessageReader,
+        writer: &MessageWriter,
+        command: &str,
+    ) -> Result<BufferId> {
+        state.handle_action(Action::RunNonInteractiveProcess, writer)?;
+        for c in command.chars() {
+            state.handle_action(Action::Insert(c), writer)?;
+        }
+        state.handle_action(Action::Confirm, writer)?;
+
+        let buf_id = state.pane_tree.active().buffer_id().clone();
+
+        loop {
+            let Message::Action(action) = reader.read()? else {
+                panic!();
+            };
+            let finished = action == Action::ProcessFinished(buf_id.clone());
+            state.handle_action(action, writer)?;
+            if finished {
+                return Ok(buf_id);
+            }
+        }
+    }
+
+    /// Test that a process runs in the directory of the active
+    /// buffer, with environment overrides, and that the command gets
+    /// added to the history.
+    #[test]
+    fn test_process_dir_env_history() -> Result<()> {
+        let (mut state, mut reader, writer) = create_test_state()?;
+
+        let tmp_dir = tempfile::tempdir()?;
+        let tmp_dir = tmp_dir.path().canonicalize()?;
+        state.enable_persistence_at(tmp_dir.join("test.sqlite3"));
+        let tmp_path = tmp_dir.join("testfile");
+        fs::write(&tmp_path, "test data\n")?;
+        state.open_file_at_path(&tmp_path)?;
+
+        let buf_id = run_process_to_completion(
+            &mut state,
+            &mut reader,
+            &writer,
+            "EMMA_TEST_VAR=xyz sh -c 'pwd; echo $EMMA_TEST_VAR'",
+        )?;
+        assert_eq!(
+            state.buffers[&buf_id].text().to_string(),
+            format!("{}\nxyz\n", tmp_dir.display())
+        );
+
+        // Unbalanced quotes are an error rather than a panic, and
+        // leave the prompt open.
+        state.handle_action(Action::RunNonInteractiveProcess, &writer)?;
+        for c in "echo 'oops".chars() {
+            state.handle_action(Action::Insert(c), &writer)?;
+        }
+        assert!(state.handle_action(Action::Confirm, &writer).is_err());
+        assert!(matches!(state.overlay, Some(Overlay::RunProcess(_))));
+        state.handle_action(Action::Cancel, &writer)?;
+
+        // The process buffer runs in the same directory, so the
+        // previous command is in the history.
+        state.handle_action(Action::RunNonInteractiveProcess, &writer)?;
+        state.handle_action(Action::PreviousHistoryItem, &writer)?;
+        assert_eq!(
+            state.active_buffer()?.text().to_string(),
+            "EMMA_TEST_VAR=xyz sh -c 'pwd; echo $EMMA_TEST_VAR'"
+        );
+
+        Ok(())
+    }
+
+    /// Test filtering the selection or the whole buffer through a
+    /// command.
+    #[test]
+    fn test_filter_region() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+
+        let filter = |state: &mut AppState, output, command: &str| {
+            state.handle_action(Action::FilterRegion(output), &writer)?;
+            for c in command.chars() {
+                state.handle_action(Action::Insert(c), &writer)?;
+            }
+            state.handle_action(Action::Confirm, &writer)
+        };
+
+        let (pane, buf) = state.active_pane_buffer_mut()?;
+        buf.set_text("c\nb\na\nz\ny\n");
+        let pane_id = pane.id().clone();
+
+        // Select the first three lines.
+        state.handle_action(Action::SetMark, &writer)?;
+        state.active_buffer_mut()?.set_cursor(&pane_id, AbsChar(6));
+        filter(&mut state, FilterOutput::Replace, "sort")?;
+        assert_eq!(
+            state.active_buffer()?.text().to_string(),
+            "a\nb\nc\nz\ny\n"
+        );
+        assert!(state.active_buffer()?.selection(&pane_id).is_none());
+
+        // Errors leave the text unchanged.
+        let err = filter(
+            &mut state,
+            FilterOutput::Replace,
+            "sh -c 'echo bad input >&2; exit 1'",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("bad input"));
+        assert_eq!(
+            state.active_buffer()?.text().to_string(),
+            "a\nb\nc\nz\ny\n"
+        );
+
+        // Filter the whole buffer into a new buffer.
+        let orig_buf_id = state.active_buffer()?.id().clone();
+        filter(&mut state, FilterOutput::NewBuffer, "sort -r")?;
+        assert_ne!(state.active_buffer()?.id(), &orig_buf_id);
+        assert_eq!(
+            state.active_buffer()?.text().to_string(),
+            "z\ny\nc\nb\na\n"
+        );
+        assert_eq!(
+            state.buffers[&orig_buf_id].text().to_string(),
+            "a\nb\nc\nz\ny\n"
+        );
+
+        Ok(())
+    }
+
+    /// Test that a configured formatter runs on save, and that a
+    /// failing formatter doesn't prevent saving.
+    #[test]
+    fn test_format_on_save() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        state.set_config(serde_yaml::from_str(
+            "
+formatters:
+  - glob: '*.sorted'
+    command: sort
+  - glob: '*.broken'
+    command: sh -c 'echo formatter error >&2; exit 1'
+",
+        )?);
+
+        let tmp_dir = tempfile::tempdir()?;
+        let tmp_path = tmp_dir.path().join("test.sorted");
+        fs::write(&tmp_path, "b\na\n")?;
+        state.open_file_at_path(&tmp_path)?;
+        state.handle_action(Action::Insert('c'), &writer)?;
+        state.handle_action(Action::Insert('\n'), &writer)?;
+
+        state.handle_action(Action::SaveFile, &writer)?;
+        assert_eq!(fs::read_to_string(&tmp_path)?, "a\nb\nc\n");
+        assert_eq!(state.active_buffer()?.text().to_string(), "a\nb\nc\n");
+
+        // The formatting is a single undo step.
+        state.handle_action(Action::Undo, &writer)?;
+        assert_eq!(state.active_buffer()?.text().to_string(), "c\nb\na\n");
+
+        let tmp_path = tmp_dir.path().join("test.broken");
+        fs::write(&tmp_path, "b\na\n")?;
+        state.open_file_at_path(&tmp_path)?;
+        state.handle_action(Action::Insert('c'), &writer)?;
+        state.handle_action(Action::SaveFile, &writer)?;
+        assert_eq!(fs::read_to_string(&tmp_path)?, "cb\na\n");
+        assert!(state.message().unwrap().contains("formatter error"));
+
+        Ok(())
+    }
+
     // TODO: experimental test.
     #[test]
     fn test_file_open() -> Result<()> {
-        let (_reader, writer) = create_message_pipe()?;
-
-        let mut state = create_empty_app_state();
+        let (mut state, _reader, writer) = create_test_state()?;
 
         // Create test files.
         let tmp_dir = tempfile::tempdir()?;
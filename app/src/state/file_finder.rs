@@ -0,0 +1,133 @@
+use super::AppState;
+use crate::file_finder::FileFinder;
+use crate::message::MessageWriter;
+use crate::overlay::Overlay;
+use anyhow::Result;
+use std::path::PathBuf;
+use tracing::error;
+
+impl AppState {
+    /// Open the file finder for the project containing the active
+    /// buffer, and start listing the project's files.
+    pub(super) fn open_file_finder(
+        &mut self,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+
+        let recent = self.load_file_history().unwrap_or_else(|err| {
+            error!("failed to load file history: {err}");
+            Vec::new()
+        });
+        let dir = super::event::default_directory(self.active_buffer()?);
+        let finder = FileFinder::new(&dir, &recent);
+        finder.start_walk(message_writer.try_clone()?);
+        self.overlay = Some(Overlay::FindFile(finder));
+        Ok(())
+    }
+
+    /// Add files found by a walk to the finder. They are ignored if
+    /// the finder has been closed or reopened since the walk started.
+    pub(super) fn add_found_files(
+        &mut self,
+        search_id: &str,
+        paths: Vec<PathBuf>,
+    ) {
+        if let Some(Overlay::FindFile(finder)) = &mut self.overlay
+            && finder.search_id() == search_id
+        {
+            finder.add_files(paths);
+        }
+    }
+
+    /// Close the finder and open the selected file.
+    pub(super) fn confirm_find_file(&mut self) -> Result<()> {
+        let Some(Overlay::FindFile(finder)) = &self.overlay else {
+            return Ok(());
+        };
+        let Some(path) = finder.selected_path() else {
+            return Ok(());
+        };
+        self.overlay = None;
+        self.open_file_at_path(&path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::message::Message;
+    use crate::state::test_util::create_test_state;
+    use std::fs;
+
+    #[test]
+    fn test_find_file() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let root = tmp_dir.path().join("project");
+        fs::create_dir_all(root.join(".git"))?;
+        fs::create_dir_all(root.join("src"))?;
+        for name in ["a.rs", "b.rs", "src/c.rs"] {
+            fs::write(root.join(name), "")?;
+        }
+        let active_path = |state: &AppState| {
+            let pane = state.pane_tree.active();
+            state.buffers[pane.buffer_id()].path().map(|p| p.to_owned())
+        };
+
+        let (mut state, mut reader, writer) = create_test_state()?;
+        state.enable_persistence_at(tmp_dir.path().join("db.sqlite3"));
+        state.open_file_at_path(&root.join("src/c.rs"))?;
+        state.open_file_at_path(&root.join("b.rs"))?;
+
+        // Open the finder and run the event loop until all the files
+        // have been found.
+        state.handle_action(Action::FindFileInProject, &writer)?;
+        loop {
+            let Message::Action(action) = reader.read()? else {
+                panic!();
+            };
+            state.handle_action(action, &writer)?;
+            let Some(Overlay::FindFile(finder)) = &state.overlay else {
+                panic!("file finder not open");
+            };
+            if finder.candidates().len() == 3 {
+                break;
+            }
+        }
+
+        // Recently opened files are listed first.
+        let Some(Overlay::FindFile(finder)) = &state.overlay else {
+            panic!("file finder not open");
+        };
+        let paths: Vec<_> =
+            finder.candidates().matches().map(|e| &e.display).collect();
+        assert_eq!(paths, ["b.rs", "src/c.rs", "a.rs"]);
+
+        state.handle_action(Action::Insert('a'), &writer)?;
+        state.handle_action(Action::Confirm, &writer)?;
+        assert!(state.overlay.is_none());
+        assert_eq!(active_path(&state), Some(root.join("a.rs")));
+
+        // Results from an old search are ignored.
+        state.handle_action(Action::FindFileInProject, &writer)?;
+        state.handle_action(
+            Action::FoundFiles("search-old".to_owned(), vec!["x.rs".into()]),
+            &writer,
+        )?;
+        let Some(Overlay::FindFile(finder)) = &state.overlay else {
+            panic!("file finder not open");
+        };
+        assert!(
+            finder
+                .candidates()
+                .candidates()
+                .iter()
+                .all(|entry| entry.display != "x.rs")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use super::AppState;
+use crate::keyboard_macro::{self, MacroStep};
+use crate::macro_prompt::{MacroOp, MacroPrompt};
+use crate::message::MessageWriter;
+use crate::overlay::Overlay;
+use anyhow::{Context, Result, bail};
+
+impl AppState {
+    pub(super) fn start_macro(&mut self) -> Result<()> {
+        self.macros.start()?;
+        self.message = Some("Defining macro...".to_owned());
+        Ok(())
+    }
+
+    pub(super) fn end_macro(&mut self) -> Result<()> {
+        let len = self.macros.end()?;
+        let plural = if len == 1 { "" } else { "s" };
+        self.message = Some(format!("Macro defined ({len} action{plural})"));
+        Ok(())
+    }
+
+    /// Run the last recorded macro `count` times.
+    pub(super) fn replay_last_macro(
+        &mut self,
+        count: usize,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
+        if self.macros.is_recording() {
+            bail!("can't replay a macro while defining one");
+        }
+        let steps = self.macros.last().to_vec();
+        if steps.is_empty() {
+            bail!("no macro has been defined");
+        }
+        self.replay_macro(&steps, count, message_writer)
+    }
+
+    /// Run the saved macro called `name` `count` times.
+    pub(super) fn run_saved_macro(
+        &mut self,
+        name: &str,
+        count: usize,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
+        let macros = keyboard_macro::load_macros(&self.macros.path()?)?;
+        let steps = macros
+            .get(name)
+            .with_context(|| format!("no macro named \"{name}\""))?;
+        self.replay_macro(steps, count, message_writer)
+    }
+
+    /// Run `steps` `count` times. The edits made to each buffer are
+    /// undone in one step. The replay stops at the first action that
+    /// fails.
+    fn replay_macro(
+        &mut self,
+        steps: &[MacroStep],
+        count: usize,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
+        self.macros.begin_replay()?;
+        let outermost = self.macros.replay_depth() == 1;
+        if outermost {
+            for buf in self.buffers.values_mut() {
+                buf.begin_undo_group();
+            }
+        }
+
+        let mut result = Ok(());
+        'replay: for _ in 0..count {
+            for step in steps {
+                result = self.handle_action_with_count(
+                    step.action.clone(),
+                    step.count,
+                    message_writer,
+                );
+                if result.is_err() {
+                    break 'replay;
+                }
+            }
+        }
+
+        if outermost {
+            for buf in self.buffers.values_mut() {
+                buf.end_undo_group();
+            }
+        }
+        self.macros.end_replay();
+        result.context("macro stopped")
+    }
+
+    /// Open a prompt for a macro name.
+    pub(super) fn open_macro_prompt(
+        &mut self,
+        op: MacroOp,
+        count: usize,
+    ) -> Result<()> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+        if op == MacroOp::Save {
+            if self.macros.is_recording() {
+                bail!("finish defining the macro before saving it");
+            }
+            if self.macros.last().is_empty() {
+                bail!("no macro has been defined");
+            }
+        }
+        self.overlay = Some(Overlay::Macro(MacroPrompt::new(op, count)));
+        Ok(())
+    }
+
+    /// Close the prompt and save or run the macro it names.
+    pub(super) fn confirm_macro_prompt(
+        &mut self,
+        message_writer: &MessageWriter,
+    ) -> Result<()> {
+        let Some(Overlay::Macro(prompt)) = self.overlay.take() else {
+            return Ok(());
+        };
+        let name = prompt.name()?;
+        match prompt.op() {
+            MacroOp::Save => {
+                let path = self.macros.path()?;
+                keyboard_macro::save_macro(&path, &name, self.macros.last())?;
+                self.message = Some(format!("Saved macro \"{name}\""));
+                Ok(())
+            }
+            MacroOp::Run => {
+                self.run_saved_macro(&name, prompt.count(), message_writer)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Action, Boundary, Direction, Move};
+    use crate::buffer::AbsChar;
+    use crate::key::{Key, Modifier, Modifiers};
+    use crate::state::test_util::{
+        active_text, create_test_state, run, type_text,
+    };
+
+    #[test]
+    fn test_keyboard_macro() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let (mut state, _reader, writer) = create_test_state()?;
+        state.macros.set_path(tmp_dir.path().join("macros.yml"));
+
+        let next_line = Action::Move(Move::Line, Direction::Inc);
+        let line_end =
+            Action::Move(Move::Boundary(Boundary::LineEnd), Direction::Inc);
+
+        run(&mut state, &writer, &[Action::ReplayMacro])
+            .expect_err("no macro defined yet");
+        type_text(&mut state, &writer, "a\nb\nc\nd")?;
+        run(
+            &mut state,
+            &writer,
+            &[Action::Move(
+                Move::Boundary(Boundary::BufferEnd),
+                Direction::Dec,
+            )],
+        )?;
+
+        // Record appending ";" to a line and moving to the next.
+        run(&mut state, &writer, &[Action::StartMacro])?;
+        assert!(state.is_recording_macro());
+        run(&mut state, &writer, std::slice::from_ref(&line_end))?;
+        state.handle_action_with_count(Action::Insert(';'), 1, &writer)?;
+        run(&mut state, &writer, &[next_line.clone(), Action::EndMacro])?;
+        assert!(!state.is_recording_macro());
+        assert_eq!(state.message(), Some("Macro defined (3 actions)"));
+        assert_eq!(active_text(&state), "a;\nb\nc\nd");
+
+        // Replay twice, undone in one step.
+        state.handle_action_with_count(Action::ReplayMacro, 2, &writer)?;
+        assert_eq!(active_text(&state), "a;\nb;\nc;\nd");
+        run(&mut state, &writer, &[Action::Undo])?;
+        assert_eq!(active_text(&state), "a;\nb\nc\nd");
+        run(&mut state, &writer, &[Action::Redo])?;
+
+        // Save it, then run it by name.
+        run(&mut state, &writer, &[Action::SaveMacro])?;
+        for c in "semi".chars() {
+            run(&mut state, &writer, &[Action::Insert(c)])?;
+        }
+        run(&mut state, &writer, &[Action::Confirm])?;
+        run(&mut state, &writer, &[Action::RunMacro("semi".to_owned())])?;
+        assert_eq!(active_text(&state), "a;\nb;\nc;\nd;");
+        run(&mut state, &writer, &[Action::RunMacro("other".to_owned())])
+            .expect_err("no such macro");
+
+        // A failing action stops the replay, and what ran before it
+        // is still undone in one step.
+        let (pane, buf) = state.active_pane_buffer_mut()?;
+        buf.set_cursor(pane.id(), AbsChar(0));
+        let err = state
+            .replay_macro(
+                &[
+                    MacroStep {
+                        action: Action::Insert('x'),
+                        count: 1,
+                    },
+                    MacroStep {
+                        action: Action::RunMacro("missing".to_owned()),
+                        count: 1,
+                    },
+                    MacroStep {
+                        action: Action::Insert('y'),
+                        count: 1,
+                    },
+                ],
+                3,
+                &writer,
+            )
+            .expect_err("missing macro");
+        assert!(err.to_string().contains("macro stopped"));
+        assert_eq!(active_text(&state), "xa;\nb;\nc;\nd;");
+        run(&mut state, &writer, &[Action::Undo])?;
+        assert_eq!(active_text(&state), "a;\nb;\nc;\nd;");
+
+        Ok(())
+    }
+
+    /// Test that a count is replayed only as part of the action it
+    /// applies to.
+    #[test]
+    fn test_keyboard_macro_with_count() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        let mut ctrl = Modifiers::new();
+        ctrl.enable_modifier(Modifier::Control);
+
+        state.handle_action(Action::StartMacro, &writer)?;
+        state.handle_key_press(Key::Char('u'), ctrl, &writer);
+        state.handle_key_press(Key::Char('3'), Modifiers::new(), &writer);
+        state.handle_key_press(Key::Char('a'), Modifiers::new(), &writer);
+        state.handle_action(Action::EndMacro, &writer)?;
+        assert_eq!(state.message(), Some("Macro defined (1 action)"));
+        assert_eq!(active_text(&state), "aaa");
+
+        // The next key press after a replay doesn't get a count.
+        state.handle_action(Action::ReplayMacro, &writer)?;
+        state.handle_action(Action::ReplayMacro, &writer)?;
+        assert_eq!(active_text(&state), "aaaaaaaaa");
+        state.handle_key_press(Key::Char('b'), Modifiers::new(), &writer);
+        assert_eq!(active_text(&state), "aaaaaaaaab");
+
+        Ok(())
+    }
+}
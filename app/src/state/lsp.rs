@@ -0,0 +1,213 @@
+use super::AppState;
+use crate::buffer::{Buffer, Diagnostic, DiagnosticSeverity};
+use crate::lsp::protocol::{self, Location, Position};
+use crate::lsp::{LspEvent, PendingRequest};
+use crate::message::MessageWriter;
+use crate::pane_tree::Pane;
+use crate::rope::Rope;
+use anyhow::{Context, Result, anyhow};
+use serde_json::json;
+use tracing::error;
+
+fn convert_diagnostic(text: &Rope, diag: &protocol::Diagnostic) -> Diagnostic {
+    Diagnostic {
+        range: diag.range.to_abs_range(text),
+        severity: match diag.severity {
+            Some(2) => DiagnosticSeverity::Warning,
+            Some(3) => DiagnosticSeverity::Information,
+            Some(4) => DiagnosticSeverity::Hint,
+            _ => DiagnosticSeverity::Error,
+        },
+        message: diag.message.clone(),
+    }
+}
+
+impl AppState {
+    /// Start language servers and send buffer changes to them. This
+    /// runs after every action.
+    pub(super) fn update_language_servers(
+        &mut self,
+        message_writer: &MessageWriter,
+    ) {
+        self.language_servers.sync(
+            &mut self.buffers,
+            &self.config.language_servers,
+            message_writer,
+        );
+    }
+
+    /// Send a request about the cursor position in the active pane to
+    /// the language server for the pane's buffer.
+    fn send_lsp_request(
+        &mut self,
+        method: &str,
+        make_request: impl FnOnce(&Pane, &Buffer) -> PendingRequest,
+    ) -> Result<()> {
+        let pane = self.pane_tree.active();
+        let buf = self
+            .buffers
+            .get(pane.buffer_id())
+            .context("active pane points to invalid buffer")?;
+        let server = self
+            .language_servers
+            .server_for_buffer_mut(buf.id())
+            .ok_or_else(|| anyhow!("no language server for this buffer"))?;
+
+        let path = buf.path().context("buffer has no path")?;
+        let params = json!({
+            "textDocument": {"uri": protocol::path_to_uri(path)},
+            "position": Position::from_abs_char(
+                buf.text(),
+                buf.cursor(pane.id()),
+            ),
+        });
+        server.request(method, params, make_request(pane, buf))
+    }
+
+    pub(super) fn request_definition(&mut self) -> Result<()> {
+        self.send_lsp_request("textDocument/definition", |_, _| {
+            PendingRequest::Definition
+        })
+    }
+
+    pub(super) fn request_hover(&mut self) -> Result<()> {
+        self.send_lsp_request("textDocument/hover", |_, _| {
+            PendingRequest::Hover
+        })
+    }
+
+    pub(super) fn request_completion(&mut self) -> Result<()> {
+        self.send_lsp_request("textDocument/completion", |pane, buf| {
+            PendingRequest::Completion {
+                buffer_id: buf.id().clone(),
+                pane_id: pane.id().clone(),
+                position: buf.cursor(pane.id()),
+            }
+        })
+    }
+
+    /// Handle an event from a language server. Returns true if the
+    /// active buffer changed. Errors caused by the server's response
+    /// are shown in the echo area rather than returned.
+    pub(super) fn handle_lsp_event(&mut self, event: LspEvent) -> Result<bool> {
+        if let Err(err) = self.apply_lsp_event(event) {
+            error!("failed to handle language server event: {err}");
+            self.display_error(err);
+        }
+        Ok(false)
+    }
+
+    fn apply_lsp_event(&mut self, event: LspEvent) -> Result<()> {
+        match event {
+            LspEvent::Initialized {
+                server,
+                incremental_sync,
+            } => {
+                self.language_servers
+                    .set_initialized(&server, incremental_sync)?;
+            }
+            LspEvent::Diagnostics { path, diagnostics } => {
+                for buf in self.buffers.values_mut() {
+                    if buf.path() == Some(&path) {
+                        let diagnostics = diagnostics
+                            .iter()
+                            .map(|diag| convert_diagnostic(buf.text(), diag))
+                            .collect();
+                        buf.set_diagnostics(diagnostics);
+                    }
+                }
+            }
+            LspEvent::Definition(location) => {
+                if let Some(location) = location {
+                    self.go_to_location(&location)?;
+                } else {
+                    self.message = Some("no definition found".to_owned());
+                }
+            }
+            LspEvent::Hover(text) => {
+                self.message = Some(
+                    text.unwrap_or_else(|| "no information available".into()),
+                );
+            }
+            LspEvent::Completion {
+                buffer_id,
+                pane_id,
+                position,
+                items,
+            } => {
+                self.add_lsp_completions(
+                    &buffer_id, &pane_id, position, items,
+                )?;
+            }
+            LspEvent::InitializeFailed { server, message } => {
+                error!("{message}");
+                self.language_servers.remove_server(&server);
+                self.display_error(anyhow!(message));
+            }
+            LspEvent::Error(message) => {
+                error!("{message}");
+                self.display_error(anyhow!(message));
+            }
+        }
+        Ok(())
+    }
+
+    /// Show `location` in the active pane, opening the file if needed.
+    fn go_to_location(&mut self, location: &Location) -> Result<()> {
+        let path = protocol::uri_to_path(&location.uri)?;
+
+        let existing = self
+            .buffers
+            .values()
+            .find(|buf| buf.path() == Some(&path))
+            .map(|buf| buf.id().clone());
+        match existing {
+            Some(buf_id) if buf_id == *self.pane_tree.active().buffer_id() => {}
+            Some(buf_id) => {
+                self.pane_tree
+                    .active_mut()
+                    .switch_buffer(&mut self.buffers, &buf_id);
+            }
+            None => self.open_file_at_path(&path)?,
+        }
+
+        let line_height = self.line_height;
+        let pane = self.pane_tree.active_mut();
+        let buf = self
+            .buffers
+            .get_mut(pane.buffer_id())
+            .context("active pane points to invalid buffer")?;
+        let pos = location.range.start.to_abs_char(buf.text());
+        buf.set_cursor(pane.id(), pos);
+        pane.maybe_rescroll(buf, pos, line_height);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::lsp::protocol::Range;
+    use crate::state::test_util::create_test_state;
+
+    /// Test that a bad response from the server is reported instead
+    /// of failing the action.
+    #[test]
+    fn test_bad_definition_location() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+
+        let location = Location {
+            uri: "https://example.com/test.txt".to_owned(),
+            range: Range::default(),
+        };
+        state.handle_action(
+            Action::Lsp(LspEvent::Definition(Some(location))),
+            &writer,
+        )?;
+        assert!(state.message().unwrap().starts_with("error: "));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use super::AppState;
+use super::event::check_editable;
+use crate::action::{Action, Boundary, Direction, Move};
+use crate::buffer::{AbsChar, Buffer};
+use crate::modal::{Mode, Operator};
+use crate::rope::AbsLine;
+use anyhow::{Context, Result};
+use std::ops::Range;
+
+/// Extend `range` to cover whole lines. For a deletion, the newline
+/// before the range is included if the range runs to the end of the
+/// text, so that no empty line is left behind. For a change, the
+/// final newline is left out so that there's a line to type into.
+fn line_range(
+    buf: &Buffer,
+    range: Range<AbsChar>,
+    operator: Operator,
+) -> Range<AbsChar> {
+    let text = buf.text();
+    let first = text.char_to_line(range.start);
+    let last = text.char_to_line(range.end);
+    let mut start = text.line_to_char(first);
+    let mut end = text.len_chars();
+    if last < text.max_line_index() {
+        end = text.line_to_char(AbsLine(last.0 + 1));
+        if operator == Operator::Change {
+            end -= 1;
+        }
+    } else if operator == Operator::Delete && start > 0 {
+        start -= 1;
+    }
+    AbsChar(start)..AbsChar(end)
+}
+
+impl AppState {
+    /// Move the cursor `count` times. If an operator is waiting,
+    /// apply it to the text the move covers instead, with the count
+    /// typed before the operator multiplying `count`. Returns whether
+    /// the text changed.
+    pub(super) fn modal_move(
+        &mut self,
+        step: Move,
+        dir: Direction,
+        count: usize,
+    ) -> Result<bool> {
+        let operator = self
+            .key_handler
+            .modal_mut()
+            .and_then(|modal| modal.take_operator());
+        if let Some((operator, operator_count)) = operator {
+            self.apply_operator_to_move(
+                operator,
+                step,
+                dir,
+                operator_count * count,
+            )?;
+            return Ok(true);
+        }
+        self.move_cursor(step, dir, count)?;
+        Ok(false)
+    }
+
+    /// Apply `operator` to the text between the cursor and where
+    /// moving `count` times would put it. Moving by lines covers
+    /// whole lines.
+    fn apply_operator_to_move(
+        &mut self,
+        operator: Operator,
+        step: Move,
+        dir: Direction,
+        count: usize,
+    ) -> Result<()> {
+        let line_height = self.line_height;
+        let (pane, buf) = self.active_pane_buffer_mut()?;
+        check_editable(buf)?;
+        let start = buf.cursor(pane.id());
+        pane.move_cursor(buf, step, dir, count, line_height);
+        let end = buf.cursor(pane.id());
+        buf.set_cursor(pane.id(), start);
+
+        let mut range = start.min(end)..start.max(end);
+        if matches!(step, Move::Line | Move::Page) {
+            range = line_range(buf, range, operator);
+        }
+        self.apply_operator(operator, range)
+    }
+
+    fn apply_operator(
+        &mut self,
+        operator: Operator,
+        range: Range<AbsChar>,
+    ) -> Result<()> {
+        let (pane, buf) = self.active_pane_buffer_mut()?;
+        check_editable(buf)?;
+        if !range.is_empty() {
+            buf.delete_text(range.clone());
+        }
+        buf.set_cursor(pane.id(), range.start);
+        match operator {
+            Operator::Delete => self.set_mode(Mode::Normal),
+            Operator::Change => self.set_mode(Mode::Insert),
+        }
+    }
+
+    /// Switch modes. Visual mode selects from the cursor, and leaving
+    /// it clears the selection.
+    pub(super) fn set_mode(&mut self, mode: Mode) -> Result<()> {
+        let Some(modal) = self.key_handler.modal_mut() else {
+            return Ok(());
+        };
+        let prev_mode = modal.mode();
+        modal.set_mode(mode);
+
+        let (pane, buf) = self.active_pane_buffer_mut()?;
+        if mode == Mode::Visual && prev_mode != Mode::Visual {
+            buf.set_mark(pane.id(), buf.cursor(pane.id()));
+        } else if mode != Mode::Visual && prev_mode == Mode::Visual {
+            buf.clear_mark(pane.id());
+        }
+        Ok(())
+    }
+
+    /// Handle an operator key. In visual mode it applies to the
+    /// selection right away. Otherwise it waits for a motion, or
+    /// applies to `count` whole lines if typed twice. Returns whether
+    /// the text changed.
+    pub(super) fn push_operator(
+        &mut self,
+        operator: Operator,
+        count: usize,
+    ) -> Result<bool> {
+        let Some(modal) = self.key_handler.modal_mut() else {
+            return Ok(false);
+        };
+        if modal.mode() == Mode::Visual {
+            let (pane, buf) = self.active_pane_buffer_mut()?;
+            let range = buf
+                .selection(pane.id())
+                .context("visual mode without a selection")?;
+            self.apply_operator(operator, range)?;
+            return Ok(true);
+        }
+        let Some(lines) = modal.push_operator(operator, count) else {
+            return Ok(false);
+        };
+
+        // Typed twice, e.g. "dd".
+        self.apply_operator_to_move(
+            operator,
+            Move::Line,
+            Direction::Inc,
+            lines - 1,
+        )?;
+        Ok(true)
+    }
+
+    /// Handle a digit key in normal mode. A zero with no count before
+    /// it moves to the start of the line. Returns whether the text
+    /// changed.
+    pub(super) fn push_count_digit(&mut self, digit: u8) -> Result<bool> {
+        if self.key_handler.modal().is_none()
+            || self.key_handler.prefix_arg_mut().push_digit(digit)
+        {
+            return Ok(false);
+        }
+        // Count digits aren't recorded into macros, so record the
+        // move instead.
+        let (step, dir) = (Move::Boundary(Boundary::LineEnd), Direction::Dec);
+        self.macros.record(&Action::Move(step, dir), 1);
+        self.modal_move(step, dir, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::key::{Key, Modifiers};
+    use crate::state::test_util::{active_text, create_test_state};
+
+    #[test]
+    fn test_modal_editing() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        state.set_config(Config::from_yaml("modal-editing: true")?);
+        assert_eq!(state.mode(), Some(Mode::Normal));
+
+        let type_keys = |state: &mut AppState, keys: &str| {
+            for c in keys.chars() {
+                let key = match c {
+                    '\x1b' => Key::Escape,
+                    c => Key::Char(c),
+                };
+                state.handle_key_press(key, Modifiers::new(), &writer);
+            }
+        };
+        let cursor = |state: &AppState| -> Result<usize> {
+            let pane = state.pane_tree.active();
+            Ok(state.active_buffer()?.cursor(pane.id()).0)
+        };
+
+        // Plain keys don't insert in normal mode.
+        type_keys(&mut state, "q");
+        assert_eq!(active_text(&state), "");
+
+        type_keys(&mut state, "ione\ntwo\nthree\nfour\nfive\x1b");
+        assert_eq!(state.mode(), Some(Mode::Normal));
+        assert_eq!(active_text(&state), "one\ntwo\nthree\nfour\nfive");
+
+        // Counts repeat motions, and "0" goes to the line start.
+        type_keys(&mut state, "gg2jl");
+        assert_eq!(cursor(&state)?, 9);
+        type_keys(&mut state, "0");
+        assert_eq!(cursor(&state)?, 8);
+
+        // Delete to the end of the line, then two whole lines.
+        type_keys(&mut state, "ld$");
+        assert_eq!(active_text(&state), "one\ntwo\nt\nfour\nfive");
+        type_keys(&mut state, "2dd");
+        assert_eq!(active_text(&state), "one\ntwo\nfive");
+
+        // A line motion covers whole lines, and the last line takes
+        // the newline before it.
+        type_keys(&mut state, "dk");
+        assert_eq!(active_text(&state), "one");
+
+        // Change switches to insert mode.
+        type_keys(&mut state, "gg$ccfirst\x1b");
+        assert_eq!(active_text(&state), "first");
+        assert_eq!(state.mode(), Some(Mode::Normal));
+
+        // Operators apply to the visual selection.
+        type_keys(&mut state, "0vlld");
+        assert_eq!(active_text(&state), "rst");
+        assert_eq!(state.mode(), Some(Mode::Normal));
+        let pane = state.pane_tree.active();
+        assert!(state.active_buffer()?.selection(pane.id()).is_none());
+
+        // Undo restores the text.
+        type_keys(&mut state, "u");
+        assert_eq!(active_text(&state), "first");
+
+        Ok(())
+    }
+}
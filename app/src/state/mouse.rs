@@ -0,0 +1,334 @@
+use super::AppState;
+use crate::action::Direction;
+use crate::buffer::{AbsChar, Buffer, LinePosition};
+use crate::completion::is_word_char;
+use crate::mouse::{MouseEvent, Point};
+use crate::pane_tree::{PANE_TEXT_MARGIN, Pane, PaneId};
+use crate::{CharWidth, LineHeight};
+use anyhow::{Result, anyhow};
+use std::ops::Range;
+use tracing::error;
+
+/// Number of lines scrolled by one notch of the mouse wheel.
+const SCROLL_LINES_PER_STEP: usize = 3;
+
+/// A selection being made by dragging the mouse.
+pub(super) struct MouseDrag {
+    pane_id: PaneId,
+
+    /// Where the selection started. After a double or triple click
+    /// this is the start of the word or line.
+    anchor: AbsChar,
+
+    /// Where the cursor was last put, so that small movements within
+    /// one character don't reset the selection.
+    last: AbsChar,
+}
+
+/// Get the text position drawn at `point` in `pane`. Points outside
+/// the pane's text go to the nearest visible line and column.
+fn position_in_pane(
+    pane: &Pane,
+    buf: &Buffer,
+    point: Point,
+    line_height: LineHeight,
+    char_width: CharWidth,
+) -> LinePosition {
+    let rect = pane.rect();
+    let row = ((point.y - rect.y - PANE_TEXT_MARGIN) / line_height.0)
+        .floor()
+        .max(0.0) as usize;
+    let row = row.min(pane.visible_lines(line_height) - 1);
+    let column = ((point.x - rect.x) / char_width.0).floor().max(0.0) as usize;
+    pane.position_at(buf, row, column)
+}
+
+/// Get the range of the word at `pos`. Outside a word, this is the
+/// single character at `pos`.
+fn word_range(buf: &Buffer, pos: AbsChar) -> Range<AbsChar> {
+    let line_pos = LinePosition::from_abs_char(pos, buf);
+    let line_start = pos.0 - line_pos.offset.0;
+    let chars: Vec<char> =
+        buf.text().line(line_pos.line).to_string().chars().collect();
+    let offset = line_pos.offset.0;
+
+    match chars.get(offset) {
+        Some(c) if is_word_char(*c) => {
+            let start = chars[..offset]
+                .iter()
+                .rposition(|c| !is_word_char(*c))
+                .map_or(0, |i| i + 1);
+            let end = chars[offset..]
+                .iter()
+                .position(|c| !is_word_char(*c))
+                .map_or(chars.len(), |i| offset + i);
+            AbsChar(line_start + start)..AbsChar(line_start + end)
+        }
+        Some('\n') | None => pos..pos,
+        Some(_) => pos..AbsChar(pos.0 + 1),
+    }
+}
+
+/// Get the range of the line at `pos`, including its newline.
+fn line_range(buf: &Buffer, pos: AbsChar) -> Range<AbsChar> {
+    let text = buf.text();
+    let line = text.char_to_line(pos);
+    let start = AbsChar(text.line_to_char(line));
+    let end = AbsChar(start.0 + text.line(line).len_chars());
+    start..end
+}
+
+impl AppState {
+    /// Find the pane at `point`, and the text position drawn there.
+    /// Returns `None` if `point` isn't over a pane.
+    pub fn position_at(&self, point: Point) -> Option<(PaneId, LinePosition)> {
+        let pane = self
+            .pane_tree
+            .panes()
+            .into_iter()
+            .find(|pane| pane.rect().contains(point.x, point.y))?;
+        let buf = self.buffers.get(pane.buffer_id())?;
+        let pos = position_in_pane(
+            pane,
+            buf,
+            point,
+            self.line_height,
+            self.char_width,
+        );
+        Some((pane.id().clone(), pos))
+    }
+
+    /// Handle mouse input on the panes. It's ignored while an overlay
+    /// is open, since overlays are driven by the keyboard.
+    pub fn handle_mouse_event(&mut self, event: MouseEvent) {
+        if self.overlay.is_some() {
+            return;
+        }
+
+        let result = match event {
+            MouseEvent::Press { point, clicks } => {
+                self.mouse_press(point, clicks)
+            }
+            MouseEvent::Drag(point) => self.mouse_drag(point),
+            MouseEvent::Scroll { point, steps } => {
+                self.mouse_scroll(point, steps);
+                Ok(())
+            }
+        };
+        if let Err(err) = result.and_then(|()| self.follow_cursor_column()) {
+            self.display_error(err);
+        }
+
+        self.record_buffer_use();
+        if let Err(err) = self.persistence_store() {
+            error!("failed to persist state: {err}");
+        }
+    }
+
+    /// Focus the pane under `point` and put the cursor there. A double
+    /// click selects the word there, and a triple click the line.
+    fn mouse_press(&mut self, point: Point, clicks: u32) -> Result<()> {
+        self.mouse_drag = None;
+        let Some((pane_id, line_pos)) = self.position_at(point) else {
+            return Ok(());
+        };
+        self.pane_tree.set_active(&pane_id);
+
+        let buf = self.mouse_buffer_mut(&pane_id)?;
+        let pos = line_pos.to_abs_char(buf);
+        let range = match clicks {
+            0 | 1 => pos..pos,
+            2 => word_range(buf, pos),
+            _ => line_range(buf, pos),
+        };
+        if range.is_empty() {
+            buf.clear_mark(&pane_id);
+        } else {
+            buf.set_mark(&pane_id, range.start);
+        }
+        buf.set_cursor(&pane_id, range.end);
+
+        self.mouse_drag = Some(MouseDrag {
+            pane_id,
+            anchor: range.start,
+            last: range.end,
+        });
+        Ok(())
+    }
+
+    /// Select from where the drag started to `point`. Points outside
+    /// the pane select to the nearest visible position.
+    fn mouse_drag(&mut self, point: Point) -> Result<()> {
+        let Some(drag) = &self.mouse_drag else {
+            return Ok(());
+        };
+        let pane = self
+            .pane_tree
+            .panes()
+            .into_iter()
+            .find(|pane| *pane.id() == drag.pane_id)
+            .ok_or_else(|| anyhow!("pane closed while dragging"))?;
+        let buf = self
+            .buffers
+            .get(pane.buffer_id())
+            .ok_or_else(|| anyhow!("pane points to invalid buffer"))?;
+        let pos = position_in_pane(
+            pane,
+            buf,
+            point,
+            self.line_height,
+            self.char_width,
+        )
+        .to_abs_char(buf);
+        if pos == drag.last {
+            return Ok(());
+        }
+
+        let pane_id = drag.pane_id.clone();
+        let anchor = drag.anchor;
+        let buf = self.mouse_buffer_mut(&pane_id)?;
+        buf.set_mark(&pane_id, anchor);
+        buf.set_cursor(&pane_id, pos);
+        if let Some(drag) = &mut self.mouse_drag {
+            drag.last = pos;
+        }
+        Ok(())
+    }
+
+    /// Scroll the pane under `point` without moving its cursor,
+    /// unless it would go out of view.
+    fn mouse_scroll(&mut self, point: Point, steps: i32) {
+        let Some(pane) = self
+            .pane_tree
+            .panes_mut()
+            .into_iter()
+            .find(|pane| pane.rect().contains(point.x, point.y))
+        else {
+            return;
+        };
+        let Some(buf) = self.buffers.get_mut(pane.buffer_id()) else {
+            return;
+        };
+
+        let lines = steps.unsigned_abs() as usize * SCROLL_LINES_PER_STEP;
+        let dir = if steps < 0 {
+            Direction::Dec
+        } else {
+            Direction::Inc
+        };
+        pane.scroll(buf, lines, dir, self.line_height);
+    }
+
+    fn mouse_buffer_mut(&mut self, pane_id: &PaneId) -> Result<&mut Buffer> {
+        let pane = self
+            .pane_tree
+            .panes()
+            .into_iter()
+            .find(|pane| pane.id() == pane_id)
+            .ok_or_else(|| anyhow!("no pane with that id"))?;
+        self.buffers
+            .get_mut(pane.buffer_id())
+            .ok_or_else(|| anyhow!("pane points to invalid buffer"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::pane_tree::Orientation;
+    use crate::rope::AbsLine;
+    use crate::state::test_util::create_test_state;
+
+    #[test]
+    fn test_mouse() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        for c in "one two\n\tthree\nfour".chars() {
+            state.handle_action(Action::Insert(c), &writer)?;
+        }
+        state.recalc_layout(400.0, 200.0);
+
+        // Lines are 20 pixels high and columns 10 pixels wide, below
+        // a small margin.
+        let at = |col: f64, row: f64| {
+            Point::new(col * 10.0 + 5.0, row * 20.0 + PANE_TEXT_MARGIN + 5.0)
+        };
+        let click = |state: &mut AppState, point, clicks| {
+            state.handle_mouse_event(MouseEvent::Press { point, clicks });
+        };
+        let cursor = |state: &AppState| {
+            let buf = state.active_buffer().unwrap();
+            let pane = state.pane_tree.active();
+            (buf.cursor(pane.id()).0, buf.selection(pane.id()))
+        };
+
+        click(&mut state, at(5.0, 0.0), 1);
+        assert_eq!(cursor(&state), (5, None));
+
+        // The tab spans the first eight columns.
+        click(&mut state, at(7.0, 1.0), 1);
+        assert_eq!(cursor(&state), (8, None));
+        click(&mut state, at(9.0, 1.0), 1);
+        assert_eq!(cursor(&state), (10, None));
+
+        // Past the end of a line or the buffer.
+        click(&mut state, at(30.0, 0.0), 1);
+        assert_eq!(cursor(&state), (7, None));
+        click(&mut state, at(2.0, 6.0), 1);
+        assert_eq!(cursor(&state), (17, None));
+
+        // Double click selects a word, triple click a line.
+        click(&mut state, at(5.0, 0.0), 2);
+        assert_eq!(cursor(&state), (7, Some(AbsChar(4)..AbsChar(7))));
+        click(&mut state, at(5.0, 0.0), 3);
+        assert_eq!(cursor(&state), (8, Some(AbsChar(0)..AbsChar(8))));
+
+        // Dragging selects from the press.
+        click(&mut state, at(1.0, 0.0), 1);
+        state.handle_mouse_event(MouseEvent::Drag(at(1.2, 0.0)));
+        assert_eq!(cursor(&state), (1, None));
+        state.handle_mouse_event(MouseEvent::Drag(at(2.0, 2.0)));
+        assert_eq!(cursor(&state), (17, Some(AbsChar(1)..AbsChar(17))));
+
+        // The wheel scrolls without moving the cursor, and doesn't go
+        // past the last line.
+        state.handle_mouse_event(MouseEvent::Scroll {
+            point: at(0.0, 0.0),
+            steps: 1,
+        });
+        assert_eq!(state.pane_tree.active().top_line(), AbsLine(2));
+        assert_eq!(cursor(&state).0, 17);
+        state.handle_mouse_event(MouseEvent::Scroll {
+            point: at(0.0, 0.0),
+            steps: -1,
+        });
+        assert_eq!(state.pane_tree.active().top_line(), AbsLine(0));
+
+        // Clicking another pane focuses it.
+        state.handle_action(
+            Action::SplitPane(Orientation::Horizontal),
+            &writer,
+        )?;
+        state.recalc_layout(400.0, 200.0);
+        let panes: Vec<PaneId> = state
+            .pane_tree
+            .panes()
+            .iter()
+            .map(|p| p.id().clone())
+            .collect();
+        for pane_id in &panes {
+            let rect = state
+                .pane_tree
+                .panes()
+                .into_iter()
+                .find(|p| p.id() == pane_id)
+                .unwrap()
+                .rect()
+                .clone();
+            click(&mut state, Point::new(rect.x + 5.0, rect.y + 5.0), 1);
+            assert_eq!(state.pane_tree.active().id(), pane_id);
+        }
+
+        Ok(())
+    }
+}
@@ -1,11 +1,13 @@
 use super::AppState;
-use crate::buffer::{BufferId, CursorMap};
+use crate::buffer::{
+    AbsChar, AbsLine, Buffer, BufferId, CursorMap, ViewPosition,
+};
 use anyhow::{Result, anyhow};
 use fs_err as fs;
 use rusqlite::Connection;
 use std::ffi::OsStr;
 use std::os::unix::ffi::OsStrExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::error;
 
 /// Name of the sqlite3 database file used to store cached data. This
@@ -14,6 +16,16 @@ use tracing::error;
 /// buffers and pane tree layout after restarting the app.
 const DB_NAME: &str = "emma.sqlite3";
 
+/// Maximum number of commands returned when loading the command
+/// history for a directory.
+const MAX_COMMAND_HISTORY: usize = 100;
+
+/// Maximum number of files to remember the view position of.
+const MAX_VIEW_POSITIONS: usize = 1000;
+
+/// Maximum number of recently opened files to remember.
+const MAX_FILE_HISTORY: usize = 1000;
+
 /// Get the cache directory path, e.g. "~/.cache/emma".
 fn cache_dir() -> Result<PathBuf> {
     Ok(dirs::cache_dir()
@@ -21,6 +33,168 @@ fn cache_dir() -> Result<PathBuf> {
         .join("emma"))
 }
 
+/// Get the path of the database in the cache directory.
+pub(super) fn default_db_path() -> Result<PathBuf> {
+    Ok(cache_dir()?.join(DB_NAME))
+}
+
+/// Open the database, creating it and its tables if necessary.
+fn open_db(db_path: &Path) -> Result<Connection> {
+    // Try to create the directory. Ignore the error, it might
+    // already exist.
+    if let Some(dir) = db_path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+
+    let conn = Connection::open(db_path)?;
+
+    // Create the tables if not already present.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS kv (
+            key   TEXT PRIMARY KEY,
+            value TEXT
+        )",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS buffers (
+            buffer_id TEXT PRIMARY KEY,
+            path BLOB,
+            cursors TEXT
+        )",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS view_positions (
+            path BLOB PRIMARY KEY,
+            cursor INTEGER,
+            top_line INTEGER
+        )",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS file_history (
+            path BLOB PRIMARY KEY
+        )",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS command_history (
+            directory BLOB,
+            command TEXT
+        )",
+        (),
+    )?;
+
+    Ok(conn)
+}
+
+/// Add `command` to the history for `dir`. If the command is already
+/// in the history it gets moved to the most-recent position.
+fn add_to_command_history(
+    conn: &mut Connection,
+    dir: &Path,
+    command: &str,
+) -> Result<()> {
+    let dir = dir.as_os_str().as_bytes();
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM command_history WHERE directory = ?1 AND command = ?2",
+        (dir, command),
+    )?;
+    tx.execute(
+        "INSERT INTO command_history (directory, command) VALUES (?1, ?2)",
+        (dir, command),
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Get the command history for `dir`, most recent first.
+fn load_command_history(conn: &Connection, dir: &Path) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT command FROM command_history WHERE directory = ?1
+         ORDER BY rowid DESC LIMIT ?2",
+    )?;
+    let iter = stmt.query_map(
+        (dir.as_os_str().as_bytes(), MAX_COMMAND_HISTORY),
+        |row| row.get(0),
+    )?;
+    Ok(iter.collect::<Result<_, _>>()?)
+}
+
+/// Add `path` to the recently opened files. If it's already there it
+/// gets moved to the most-recent position. Only the most recent
+/// `MAX_FILE_HISTORY` files are kept.
+fn add_to_file_history(conn: &mut Connection, path: &Path) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "REPLACE INTO file_history (path) VALUES (?1)",
+        (path.as_os_str().as_bytes(),),
+    )?;
+    tx.execute(
+        "DELETE FROM file_history WHERE rowid NOT IN
+         (SELECT rowid FROM file_history ORDER BY rowid DESC LIMIT ?1)",
+        (MAX_FILE_HISTORY,),
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Get the recently opened files, most recent first.
+fn load_file_history(conn: &Connection) -> Result<Vec<PathBuf>> {
+    let mut stmt =
+        conn.prepare("SELECT path FROM file_history ORDER BY rowid DESC")?;
+    let iter = stmt.query_map([], |row| {
+        let path: Vec<u8> = row.get(0)?;
+        Ok(PathBuf::from(OsStr::from_bytes(&path)))
+    })?;
+    Ok(iter.collect::<Result<_, _>>()?)
+}
+
+/// Remember the view position of each file in `views`. Only the most
+/// recently stored `MAX_VIEW_POSITIONS` files are kept.
+fn store_view_positions(
+    conn: &mut Connection,
+    views: &[(&Path, ViewPosition)],
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    for (path, view) in views {
+        tx.execute(
+            "REPLACE INTO view_positions (path, cursor, top_line)
+             VALUES (?1, ?2, ?3)",
+            (path.as_os_str().as_bytes(), view.cursor.0, view.top_line.0),
+        )?;
+    }
+    tx.execute(
+        "DELETE FROM view_positions WHERE rowid NOT IN
+         (SELECT rowid FROM view_positions ORDER BY rowid DESC LIMIT ?1)",
+        (MAX_VIEW_POSITIONS,),
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Get the last stored view position of `path`.
+fn load_view_position(
+    conn: &Connection,
+    path: &Path,
+) -> Result<Option<ViewPosition>> {
+    let mut stmt = conn.prepare(
+        "SELECT cursor, top_line FROM view_positions WHERE path = ?1",
+    )?;
+    let mut rows = stmt.query((path.as_os_str().as_bytes(),))?;
+    let Some(row) = rows.next()? else {
+        return Ok(None);
+    };
+    Ok(Some(ViewPosition {
+        cursor: AbsChar(row.get(0)?),
+        top_line: AbsLine(row.get(1)?),
+    }))
+}
+
 #[derive(Debug)]
 pub struct PersistedBuffer {
     pub buffer_id: BufferId,
@@ -30,36 +204,11 @@ pub struct PersistedBuffer {
 
 impl AppState {
     pub fn persistence_store(&self) -> Result<()> {
-        if !self.is_persistence_enabled {
+        let Some(db_path) = &self.db_path else {
             return Ok(());
-        }
+        };
 
-        let cache_dir = cache_dir()?;
-
-        // Try to create the directory. Ignore the error, it might
-        // already exist.
-        let _ = fs::create_dir_all(&cache_dir);
-
-        // Open the database, creating it if necessary.
-        let db_path = cache_dir.join(DB_NAME);
-        let mut conn = Connection::open(db_path)?;
-
-        // Create the tables if not already present.
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS kv (
-                key   TEXT PRIMARY KEY,
-                value TEXT
-            )",
-            (),
-        )?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS buffers (
-                buffer_id TEXT PRIMARY KEY,
-                path BLOB,
-                cursors TEXT
-            )",
-            (),
-        )?;
+        let mut conn = open_db(db_path)?;
 
         let json = serde_json::to_string(&self.pane_tree)?;
         conn.execute(
@@ -82,14 +231,99 @@ impl AppState {
         }
         tx.commit()?;
 
+        let views: Vec<_> = self
+            .buffers
+            .values()
+            .filter_map(|buf| Some((buf.path()?, self.current_view(buf))))
+            .collect();
+        store_view_positions(&mut conn, &views)?;
+
         Ok(())
     }
 
+    /// Get the view position of `buf` in the active pane, or some
+    /// other pane if the active pane isn't showing it. If no pane is
+    /// showing it, use the position it was last viewed at.
+    fn current_view(&self, buf: &Buffer) -> ViewPosition {
+        let mut panes = self.pane_tree.panes();
+        panes.sort_by_key(|pane| !pane.is_active());
+        panes
+            .into_iter()
+            .find(|pane| pane.buffer_id() == buf.id())
+            .map(|pane| ViewPosition {
+                cursor: buf.cursor(pane.id()),
+                top_line: pane.top_line(),
+            })
+            .unwrap_or_else(|| buf.last_view())
+    }
+
+    /// Get the view position `path` had when it was last open. Returns
+    /// `None` if persistence is not enabled.
+    pub(super) fn load_view_position(
+        &self,
+        path: &Path,
+    ) -> Result<Option<ViewPosition>> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(None);
+        };
+
+        let conn = open_db(db_path)?;
+        load_view_position(&conn, path)
+    }
+
+    /// Record a command that was run in `dir`. Does nothing if
+    /// persistence is not enabled.
+    pub(super) fn store_command_history(
+        &self,
+        dir: &Path,
+        command: &str,
+    ) -> Result<()> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(());
+        };
+
+        let mut conn = open_db(db_path)?;
+        add_to_command_history(&mut conn, dir, command)
+    }
+
+    /// Record that `path` was opened. Does nothing if persistence is
+    /// not enabled.
+    pub(super) fn store_file_history(&self, path: &Path) -> Result<()> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(());
+        };
+
+        let mut conn = open_db(db_path)?;
+        add_to_file_history(&mut conn, path)
+    }
+
+    /// Get the recently opened files, most recent first. Returns an
+    /// empty list if persistence is not enabled.
+    pub(super) fn load_file_history(&self) -> Result<Vec<PathBuf>> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(Vec::new());
+        };
+
+        let conn = open_db(db_path)?;
+        load_file_history(&conn)
+    }
+
+    /// Get the commands previously run in `dir`, most recent
+    /// first. Returns an empty list if persistence is not enabled.
+    pub(super) fn load_command_history(
+        &self,
+        dir: &Path,
+    ) -> Result<Vec<String>> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(Vec::new());
+        };
+
+        let conn = open_db(db_path)?;
+        load_command_history(&conn, dir)
+    }
+
     pub fn load_persisted_buffers() -> Result<Vec<PersistedBuffer>> {
-        // TODO: dedup
-        let cache_dir = cache_dir()?;
-        let db_path = cache_dir.join(DB_NAME);
-        let conn = Connection::open(db_path)?;
+        let conn = open_db(&default_db_path()?)?;
 
         let mut stmt =
             conn.prepare("SELECT buffer_id, path, cursors FROM buffers")?;
@@ -114,10 +348,7 @@ impl AppState {
 
     /// Load JSON that describes the pane tree.
     pub fn load_persisted_pane_tree() -> Result<String> {
-        // TODO: dedup
-        let cache_dir = cache_dir()?;
-        let db_path = cache_dir.join(DB_NAME);
-        let conn = Connection::open(db_path)?;
+        let conn = open_db(&default_db_path()?)?;
 
         let mut stmt =
             conn.prepare("SELECT value FROM kv WHERE key = 'pane_tree'")?;
@@ -126,3 +357,106 @@ impl AppState {
         Ok(pane_tree_json)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Action, Direction, Move};
+    use crate::state::test_util::{create_empty_app_state, create_test_state};
+
+    #[test]
+    fn test_command_history() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut conn = open_db(&tmp_dir.path().join(DB_NAME))?;
+
+        let dir1 = Path::new("/dir1");
+        let dir2 = Path::new("/dir2");
+
+        assert!(load_command_history(&conn, dir1)?.is_empty());
+
+        add_to_command_history(&mut conn, dir1, "make")?;
+        add_to_command_history(&mut conn, dir1, "cargo test")?;
+        add_to_command_history(&mut conn, dir2, "ls")?;
+        assert_eq!(load_command_history(&conn, dir1)?, ["cargo test", "make"]);
+        assert_eq!(load_command_history(&conn, dir2)?, ["ls"]);
+
+        // Re-running a command moves it to the front without
+        // duplicating it.
+        add_to_command_history(&mut conn, dir1, "make")?;
+        assert_eq!(load_command_history(&conn, dir1)?, ["make", "cargo test"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_history() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut conn = open_db(&tmp_dir.path().join(DB_NAME))?;
+
+        assert!(load_file_history(&conn)?.is_empty());
+
+        add_to_file_history(&mut conn, Path::new("/a"))?;
+        add_to_file_history(&mut conn, Path::new("/b"))?;
+        add_to_file_history(&mut conn, Path::new("/a"))?;
+        assert_eq!(
+            load_file_history(&conn)?,
+            [Path::new("/a"), Path::new("/b")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_view_positions() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let mut conn = open_db(&tmp_dir.path().join(DB_NAME))?;
+
+        let path1 = Path::new("/file1");
+        let path2 = Path::new("/file2");
+        let view = |cursor, top_line| ViewPosition {
+            cursor: AbsChar(cursor),
+            top_line: AbsLine(top_line),
+        };
+
+        assert_eq!(load_view_position(&conn, path1)?, None);
+        store_view_positions(&mut conn, &[(path1, view(10, 1))])?;
+        store_view_positions(
+            &mut conn,
+            &[(path1, view(20, 2)), (path2, view(30, 3))],
+        )?;
+        assert_eq!(load_view_position(&conn, path1)?, Some(view(20, 2)));
+        assert_eq!(load_view_position(&conn, path2)?, Some(view(30, 3)));
+
+        Ok(())
+    }
+
+    /// Test that reopening a file restores the cursor.
+    #[test]
+    fn test_reopen_file() -> Result<()> {
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("file");
+        fs::write(&path, "line1\nline2\nline3\n")?;
+        let cursor = |state: &AppState| {
+            let pane = state.pane_tree.active();
+            state.buffers[pane.buffer_id()].cursor(pane.id())
+        };
+
+        let (mut state, _reader, writer) = create_test_state()?;
+        state.enable_persistence_at(tmp_dir.path().join(DB_NAME));
+        state.open_file_at_path(&path)?;
+        for _ in 0..2 {
+            state.handle_action(
+                Action::Move(Move::Line, Direction::Inc),
+                &writer,
+            )?;
+        }
+        assert_eq!(cursor(&state), AbsChar(12));
+
+        let mut state = create_empty_app_state();
+        state.enable_persistence_at(tmp_dir.path().join(DB_NAME));
+        state.open_file_at_path(&path)?;
+        assert_eq!(cursor(&state), AbsChar(12));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use super::AppState;
+use crate::action::Direction;
+use crate::buffer::AbsChar;
+use crate::pane_tree::{PaneId, RecenterPosition};
+use crate::rope::AbsLine;
+use anyhow::{Result, anyhow};
+
+/// The view left by the last recenter, so that recentering again
+/// without scrolling or moving the cursor in between moves on to the
+/// next position.
+pub(super) struct LastRecenter {
+    pane_id: PaneId,
+    top_line: AbsLine,
+    cursor: AbsChar,
+    position: RecenterPosition,
+}
+
+impl AppState {
+    /// Scroll the active pane by `lines` without moving the cursor,
+    /// unless it would go out of view.
+    pub(super) fn scroll(
+        &mut self,
+        dir: Direction,
+        lines: usize,
+    ) -> Result<()> {
+        let line_height = self.line_height;
+        let (pane, buf) = self.active_pane_mut_buffer_mut()?;
+        pane.scroll(buf, lines, dir, line_height);
+        Ok(())
+    }
+
+    /// Scroll the cursor's line to the middle of the active pane.
+    /// Repeating this moves it to the top, then the bottom, and then
+    /// back to the middle.
+    pub(super) fn recenter(&mut self) -> Result<()> {
+        let line_height = self.line_height;
+        let last = self.last_recenter.take();
+        let (pane, buf) = self.active_pane_mut_buffer_mut()?;
+        let cursor = buf.cursor(pane.id());
+
+        let position = match last {
+            Some(last)
+                if last.pane_id == *pane.id()
+                    && last.top_line == pane.top_line()
+                    && last.cursor == cursor =>
+            {
+                last.position.next()
+            }
+            _ => RecenterPosition::Middle,
+        };
+        pane.recenter(buf, position, line_height);
+
+        self.last_recenter = Some(LastRecenter {
+            pane_id: pane.id().clone(),
+            top_line: pane.top_line(),
+            cursor,
+            position,
+        });
+        Ok(())
+    }
+
+    /// Scroll the active pane sideways if its cursor has gone off
+    /// either side, e.g. by typing past the right edge.
+    pub(super) fn follow_cursor_column(&mut self) -> Result<()> {
+        let (pane, buf) = self.active_pane_mut_buffer_mut()?;
+        pane.follow_column(buf, buf.cursor(pane.id()));
+        Ok(())
+    }
+
+    /// Turn wrapping in the active pane on or off, keeping the cursor
+    /// in view.
+    pub(super) fn toggle_wrap(&mut self) -> Result<()> {
+        let pane = self.pane_tree.active_mut();
+        let buf = self
+            .buffers
+            .get(pane.buffer_id())
+            .ok_or_else(|| anyhow!("active pane points to invalid buffer"))?;
+        pane.set_wrap(!pane.is_wrapped());
+        pane.maybe_rescroll(buf, buf.cursor(pane.id()), self.line_height);
+        self.message = Some(
+            if pane.is_wrapped() {
+                "Wrapping long lines"
+            } else {
+                "Not wrapping long lines"
+            }
+            .to_owned(),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LineHeight;
+    use crate::action::{Action, Move};
+    use crate::state::test_util::create_test_state;
+
+    #[test]
+    fn test_scroll_and_recenter() -> Result<()> {
+        let (mut state, _reader, writer) = create_test_state()?;
+        state.set_line_height(LineHeight(10.0));
+        // 20 lines, less the info bar.
+        state.recalc_layout(400.0, 210.0);
+        let text: String = (0..100).map(|i| format!("{i}\n")).collect();
+        let buf_id = state.pane_tree.active().buffer_id().clone();
+        state.buffers.get_mut(&buf_id).unwrap().set_text(&text);
+
+        let view = |state: &AppState| -> Result<(usize, usize)> {
+            let buf = state.active_buffer()?;
+            let pane = state.pane_tree.active();
+            let cursor_line = buf.text().char_to_line(buf.cursor(pane.id()));
+            Ok((pane.top_line().0, cursor_line.0))
+        };
+        let scroll_down = Action::Scroll(Direction::Inc);
+        let scroll_up = Action::Scroll(Direction::Dec);
+
+        // The cursor stays put while it's in view, then is pushed
+        // along by the top of the pane.
+        state.handle_action_with_count(scroll_down.clone(), 5, &writer)?;
+        assert_eq!(view(&state)?, (5, 5));
+        state.handle_action_with_count(
+            Action::Move(Move::Line, Direction::Inc),
+            3,
+            &writer,
+        )?;
+        state.handle_action_with_count(scroll_down, 2, &writer)?;
+        assert_eq!(view(&state)?, (7, 8));
+
+        // Likewise by the bottom, and scrolling stops at the start.
+        state.handle_action_with_count(scroll_up.clone(), 5, &writer)?;
+        assert_eq!(view(&state)?, (2, 8));
+        state.handle_action_with_count(scroll_up, 10, &writer)?;
+        assert_eq!(view(&state)?, (0, 8));
+        assert_eq!(
+            state
+                .pane_tree
+                .active()
+                .scroll_position(state.active_buffer()?, state.line_height),
+            "Top"
+        );
+
+        // Recentering cycles through the middle, top and bottom.
+        state.handle_action_with_count(
+            Action::Move(Move::Line, Direction::Inc),
+            42,
+            &writer,
+        )?;
+        state.handle_action(Action::Recenter, &writer)?;
+        assert_eq!(view(&state)?, (41, 50));
+        state.handle_action(Action::Recenter, &writer)?;
+        assert_eq!(view(&state)?, (50, 50));
+        state.handle_action(Action::Recenter, &writer)?;
+        assert_eq!(view(&state)?, (32, 50));
+        state.handle_action(Action::Recenter, &writer)?;
+        assert_eq!(view(&state)?, (41, 50));
+
+        // Moving starts the cycle over.
+        state
+            .handle_action(Action::Move(Move::Line, Direction::Inc), &writer)?;
+        state.handle_action(Action::Recenter, &writer)?;
+        assert_eq!(view(&state)?, (42, 51));
+        assert_eq!(
+            state
+                .pane_tree
+                .active()
+                .scroll_position(state.active_buffer()?, state.line_height),
+            "41%"
+        );
+
+        Ok(())
+    }
+}
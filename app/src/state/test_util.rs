@@ -0,0 +1,49 @@
+//! Setup shared by the state tests.
+
+use super::AppState;
+use crate::action::Action;
+use crate::message::{MessageReader, MessageWriter, create_message_pipe};
+use anyhow::{Result, anyhow};
+
+// TODO: simplify AppState::load, then maybe won't need this anymore.
+pub(super) fn create_empty_app_state() -> AppState {
+    AppState::load(&[], Err(anyhow!("")))
+}
+
+/// Create an empty state along with the message pipe that actions
+/// are handled with. The reader has to be kept alive even if it
+/// isn't read, since writing to a closed pipe fails.
+pub(super) fn create_test_state()
+-> Result<(AppState, MessageReader, MessageWriter)> {
+    let (reader, writer) = create_message_pipe()?;
+    Ok((create_empty_app_state(), reader, writer))
+}
+
+pub(super) fn run(
+    state: &mut AppState,
+    writer: &MessageWriter,
+    actions: &[Action],
+) -> Result<()> {
+    for action in actions {
+        state.handle_action(action.clone(), writer)?;
+    }
+    Ok(())
+}
+
+/// Insert each character of `text` as if it was typed.
+pub(super) fn type_text(
+    state: &mut AppState,
+    writer: &MessageWriter,
+    text: &str,
+) -> Result<()> {
+    for c in text.chars() {
+        state.handle_action(Action::Insert(c), writer)?;
+    }
+    Ok(())
+}
+
+/// Get the text of the active pane's buffer.
+pub(super) fn active_text(state: &AppState) -> String {
+    let pane = state.pane_tree.active();
+    state.buffers[pane.buffer_id()].text().to_string()
+}
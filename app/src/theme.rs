@@ -37,6 +37,10 @@ struct YamlThemeSettings {
     info_bar_active: Option<YamlThemeItem>,
     info_bar_inactive: Option<YamlThemeItem>,
     search_match: Option<YamlThemeItem>,
+    selection: Option<YamlThemeItem>,
+    diagnostic_error: Option<YamlThemeItem>,
+    diagnostic_warning: Option<YamlThemeItem>,
+    diagnostic_info: Option<YamlThemeItem>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -99,6 +103,11 @@ impl YamlTheme {
         expand_item(&mut self.settings.info_bar_inactive)?;
 
         expand_item(&mut self.settings.search_match)?;
+        expand_item(&mut self.settings.selection)?;
+
+        expand_item(&mut self.settings.diagnostic_error)?;
+        expand_item(&mut self.settings.diagnostic_warning)?;
+        expand_item(&mut self.settings.diagnostic_info)?;
 
         for scope in self.scopes.values_mut() {
             expand(&mut scope.foreground)?;
@@ -182,6 +191,11 @@ pub struct Theme {
     pub info_bar_active: ForeAndBack,
     pub info_bar_inactive: ForeAndBack,
     pub search_match: ForeAndBack,
+    pub selection: ForeAndBack,
+    // Diagnostics are drawn as an underline in the foreground color.
+    pub diagnostic_error: ForeAndBack,
+    pub diagnostic_warning: ForeAndBack,
+    pub diagnostic_info: ForeAndBack,
 }
 
 impl Theme {
@@ -235,6 +249,26 @@ impl Theme {
                 rgb(0, 0, 0),
                 rgb(255, 128, 128),
             )?,
+            selection: ForeAndBack::parse_with_default(
+                &yaml.settings.selection,
+                rgb(255, 255, 255),
+                rgb(64, 64, 128),
+            )?,
+            diagnostic_error: ForeAndBack::parse_with_default(
+                &yaml.settings.diagnostic_error,
+                rgb(255, 0, 0),
+                rgb(0, 0, 0),
+            )?,
+            diagnostic_warning: ForeAndBack::parse_with_default(
+                &yaml.settings.diagnostic_warning,
+                rgb(255, 165, 0),
+                rgb(0, 0, 0),
+            )?,
+            diagnostic_info: ForeAndBack::parse_with_default(
+                &yaml.settings.diagnostic_info,
+                rgb(0, 128, 255),
+                rgb(0, 0, 0),
+            )?,
         })
     }
 
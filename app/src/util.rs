@@ -1,5 +1,6 @@
 use rand::distr::Alphanumeric;
 use rand::{Rng, rng};
+use std::path::{Path, PathBuf};
 
 pub fn make_id(prefix: &str) -> String {
     let r: String = rng()
@@ -9,3 +10,13 @@ pub fn make_id(prefix: &str) -> String {
         .collect();
     format!("{prefix}-{r}")
 }
+
+/// Find the root of the project containing `dir`, i.e. the nearest
+/// ancestor with a `.git` entry. If there isn't one, `dir` itself is
+/// used.
+pub fn find_project_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(dir)
+        .to_owned()
+}
@@ -0,0 +1,313 @@
+//! Soft wrapping: splitting a line of text into the rows it's drawn
+//! on, when it's wider than its pane. Without wrapping, the line is
+//! truncated to the part that's scrolled into view instead.
+//!
+//! Text is drawn in a monospace font, so widths are counted in
+//! columns. Tabs go to the next tab stop, and wide characters such as
+//! CJK ideographs take two columns.
+
+use crate::grapheme::next_grapheme_boundary;
+use crate::rope::RopeSlice;
+use std::ops::Range;
+
+/// Tab stops are every this many columns, as Pango draws them.
+pub const TAB_WIDTH: usize = 8;
+
+/// Check if `c` is drawn two columns wide. This covers the main East
+/// Asian wide and fullwidth blocks, and emoji.
+fn is_wide(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1100..=0x115f
+            | 0x2e80..=0x303e
+            | 0x3041..=0x33ff
+            | 0x3400..=0x4dbf
+            | 0x4e00..=0x9fff
+            | 0xa000..=0xa4cf
+            | 0xac00..=0xd7a3
+            | 0xf900..=0xfaff
+            | 0xfe30..=0xfe4f
+            | 0xff00..=0xff60
+            | 0xffe0..=0xffe6
+            | 0x1f300..=0x1f64f
+            | 0x1f900..=0x1f9ff
+            | 0x20000..=0x3fffd
+    )
+}
+
+/// Get the number of columns taken by a grapheme starting with `c`,
+/// drawn at `column`.
+pub fn grapheme_width(c: char, column: usize) -> usize {
+    if c == '\t' {
+        TAB_WIDTH - column % TAB_WIDTH
+    } else if is_wide(c) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Call `f` with the char range of each grapheme in `range` of
+/// `line`, and the column it starts at relative to the start of the
+/// range. Stops early if `f` returns false.
+fn for_each_grapheme(
+    line: &RopeSlice,
+    range: Range<usize>,
+    mut f: impl FnMut(Range<usize>, usize, usize) -> bool,
+) {
+    let mut offset = range.start;
+    let mut column = 0;
+    while offset < range.end {
+        let next = next_grapheme_boundary(line, offset).0.min(range.end);
+        let width = grapheme_width(line.char(offset), column);
+        if !f(offset..next, column, width) {
+            return;
+        }
+        column += width;
+        offset = next;
+    }
+}
+
+/// Split the first `len` chars of `line` into rows at most `columns`
+/// wide, returning the char range of each row. Rows break after
+/// whitespace where possible, so that a word is only split if it's
+/// wider than a row. Whitespace that doesn't fit is left at the end
+/// of the row rather than starting the next one. There's always at
+/// least one row.
+pub fn wrap_line(
+    line: &RopeSlice,
+    len: usize,
+    columns: usize,
+) -> Vec<Range<usize>> {
+    let columns = columns.max(1);
+    let mut rows = Vec::new();
+    let mut start = 0;
+    loop {
+        let mut end = len;
+        let mut break_at = None;
+        let mut hanging = false;
+        for_each_grapheme(line, start..len, |grapheme, column, width| {
+            let c = line.char(grapheme.start);
+            if hanging {
+                if c.is_whitespace() {
+                    return true;
+                }
+                end = grapheme.start;
+                return false;
+            }
+            if column + width <= columns || column == 0 {
+                if c.is_whitespace() {
+                    break_at = Some(grapheme.end);
+                }
+                return true;
+            }
+            if c.is_whitespace() {
+                hanging = true;
+                return true;
+            }
+            end = break_at.unwrap_or(grapheme.start);
+            false
+        });
+        rows.push(start..end);
+        if end >= len {
+            return rows;
+        }
+        start = end;
+    }
+}
+
+/// Get the column that `offset` is drawn at in `row` of `line`.
+pub fn column_at_offset(
+    line: &RopeSlice,
+    row: Range<usize>,
+    offset: usize,
+) -> usize {
+    let mut result = 0;
+    for_each_grapheme(line, row, |grapheme, column, width| {
+        result = column + width;
+        if grapheme.end > offset {
+            result = column;
+            return false;
+        }
+        true
+    });
+    result
+}
+
+/// Get the offset of the grapheme drawn at `column` in `row` of
+/// `line`. Columns past the end of the row give the end of the row.
+pub fn offset_at_column(
+    line: &RopeSlice,
+    row: Range<usize>,
+    column: usize,
+) -> usize {
+    let mut result = row.end;
+    for_each_grapheme(line, row, |grapheme, start_column, width| {
+        if column < start_column + width {
+            result = grapheme.start;
+            return false;
+        }
+        true
+    });
+    result
+}
+
+/// The part of a line that's drawn when it's scrolled sideways
+/// rather than wrapped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Truncated {
+    /// Char range of the graphemes drawn.
+    pub range: Range<usize>,
+
+    /// Column the range starts at, counted from the left edge of the
+    /// pane.
+    pub indent: usize,
+
+    /// Whether text is cut off at the left edge. If so, the first
+    /// column shows an indicator instead of text.
+    pub cut_left: bool,
+
+    /// Whether text is cut off at the right edge. If so, the last
+    /// column shows an indicator instead of text.
+    pub cut_right: bool,
+}
+
+/// Get the part of the first `len` chars of `line` that's drawn in
+/// `columns` columns, with `left_column` columns scrolled off the
+/// left edge. Graphemes that are partly cut off aren't drawn.
+pub fn truncate_line(
+    line: &RopeSlice,
+    len: usize,
+    left_column: usize,
+    columns: usize,
+) -> Truncated {
+    let cut_left = left_column > 0;
+    let first_column = left_column + usize::from(cut_left);
+    let right_edge = left_column + columns.max(2);
+
+    let mut start = None;
+    let mut end = None;
+    let mut line_width = 0;
+    let mut cut_right = false;
+    for_each_grapheme(line, 0..len, |grapheme, column, width| {
+        if start.is_none() && column >= first_column {
+            start = Some((grapheme.start, column));
+        }
+        if end.is_none() && column + width >= right_edge {
+            end = Some(grapheme.start);
+        }
+        line_width = column + width;
+        if line_width > right_edge {
+            cut_right = true;
+            return false;
+        }
+        true
+    });
+
+    // If the whole line is off to the left, what's left to draw is
+    // the newline after it.
+    let (start, start_column) = start.unwrap_or((len, line_width));
+    let end = if cut_right {
+        end.unwrap_or(len).max(start)
+    } else {
+        len
+    };
+    Truncated {
+        range: start..end,
+        indent: start_column.saturating_sub(left_column),
+        cut_left,
+        cut_right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rope::Rope;
+
+    fn rows(text: &str, columns: usize) -> Vec<&str> {
+        let rope = Rope::from_str(text);
+        let line = rope.slice(..);
+        let len = text.chars().count();
+        wrap_line(&line, len, columns)
+            .into_iter()
+            .map(|row| {
+                let byte = |char_idx| {
+                    text.char_indices()
+                        .nth(char_idx)
+                        .map_or(text.len(), |(i, _)| i)
+                };
+                &text[byte(row.start)..byte(row.end)]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_wrap_line() {
+        assert_eq!(rows("", 10), [""]);
+        assert_eq!(rows("short", 10), ["short"]);
+        assert_eq!(rows("exactly 10", 10), ["exactly 10"]);
+
+        // Breaks after spaces, leaving them at the end of the row.
+        assert_eq!(rows("one two three", 8), ["one two ", "three"]);
+        assert_eq!(rows("one two  three", 7), ["one two  ", "three"]);
+
+        // Words wider than a row are split.
+        assert_eq!(rows("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(rows("ab cdefghij", 4), ["ab ", "cdef", "ghij"]);
+
+        // Tabs go to the next stop, and wide chars take two columns.
+        assert_eq!(rows("a\tbc", 9), ["a\t", "bc"]);
+        assert_eq!(rows("漢字かな", 5), ["漢字", "かな"]);
+
+        // Graphemes aren't split.
+        assert_eq!(rows("ae\u{301}b", 2), ["ae\u{301}", "b"]);
+    }
+
+    #[test]
+    fn test_columns() {
+        let rope = Rope::from_str("a\tb漢c");
+        let line = rope.slice(..);
+        let row = 0..5;
+        let columns: Vec<usize> = (0..=5)
+            .map(|i| column_at_offset(&line, row.clone(), i))
+            .collect();
+        assert_eq!(columns, [0, 1, 8, 9, 11, 12]);
+
+        let offsets: Vec<usize> = [0, 1, 5, 8, 9, 10, 11, 20]
+            .into_iter()
+            .map(|column| offset_at_column(&line, row.clone(), column))
+            .collect();
+        assert_eq!(offsets, [0, 1, 1, 2, 3, 3, 4, 5]);
+
+        // Columns are relative to the start of the row.
+        assert_eq!(column_at_offset(&line, 2..5, 4), 3);
+        assert_eq!(offset_at_column(&line, 2..5, 3), 4);
+    }
+
+    #[test]
+    fn test_truncate_line() {
+        let truncate = |text: &str, left_column, columns| {
+            let rope = Rope::from_str(text);
+            let len = text.chars().count();
+            let t = truncate_line(&rope.slice(..), len, left_column, columns);
+            (t.range, t.indent, t.cut_left, t.cut_right)
+        };
+
+        assert_eq!(truncate("abcdef", 0, 10), (0..6, 0, false, false));
+        assert_eq!(truncate("abcdef", 0, 6), (0..6, 0, false, false));
+
+        // The indicators take the place of the first and last
+        // columns.
+        assert_eq!(truncate("abcdefgh", 0, 6), (0..5, 0, false, true));
+        assert_eq!(truncate("abcdefgh", 2, 6), (3..8, 1, true, false));
+        assert_eq!(truncate("abcdefghij", 2, 6), (3..7, 1, true, true));
+
+        // Graphemes partly cut off aren't drawn.
+        assert_eq!(truncate("漢字かな", 1, 4), (1..2, 1, true, true));
+        assert_eq!(truncate("a\tbc", 2, 8), (2..4, 6, true, false));
+
+        // Scrolled past the end of the line.
+        assert_eq!(truncate("abc", 5, 6), (3..3, 0, true, false));
+    }
+}
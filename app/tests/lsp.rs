@@ -0,0 +1,173 @@
+//! Test the LSP client against the fake server in
+//! `support/fake_lsp_server.rs`. The fake server is run by starting
+//! this test binary again with `FAKE_SERVER_FLAG`, so that it doesn't
+//! need to be installed as a binary of the crate.
+
+#[path = "support/fake_lsp_server.rs"]
+mod fake_lsp_server;
+
+use anyhow::{Result, anyhow};
+use emma_app::action::{Action, Boundary, Direction, Move};
+use emma_app::buffer::{AbsChar, Buffer, DiagnosticSeverity};
+use emma_app::config::{Config, LanguageServerConfig};
+use emma_app::message::{
+    Message, MessageReader, MessageWriter, create_message_pipe,
+};
+use emma_app::overlay::Overlay;
+use emma_app::state::AppState;
+use fs_err as fs;
+use std::ops::Range;
+use std::time::Duration;
+use std::{env, thread};
+
+/// Command line flag that makes the test binary run the fake server
+/// instead of the test.
+const FAKE_SERVER_FLAG: &str = "--fake-lsp-server";
+
+fn active_buffer(state: &AppState) -> &Buffer {
+    &state.buffers()[state.pane_tree().active().buffer_id()]
+}
+
+fn diagnostic_ranges(state: &AppState) -> Vec<Range<AbsChar>> {
+    active_buffer(state)
+        .diagnostics()
+        .iter()
+        .map(|d| d.range.clone())
+        .collect()
+}
+
+fn insert_str(
+    state: &mut AppState,
+    writer: &MessageWriter,
+    s: &str,
+) -> Result<()> {
+    for c in s.chars() {
+        state.handle_action(Action::Insert(c), writer)?;
+    }
+    Ok(())
+}
+
+/// Handle messages from the server until `done` returns true.
+fn run_until(
+    state: &mut AppState,
+    reader: &mut MessageReader,
+    writer: &MessageWriter,
+    done: impl Fn(&AppState) -> bool,
+) -> Result<()> {
+    while !done(state) {
+        match reader.read()? {
+            Message::Action(action) => state.handle_action(action, writer)?,
+            Message::Close => return Err(anyhow!("timed out")),
+        }
+    }
+    Ok(())
+}
+
+fn test_lsp() -> Result<()> {
+    let (mut reader, writer) = create_message_pipe()?;
+
+    // Fail instead of hanging if an expected response never arrives.
+    let timeout_writer = writer.try_clone()?;
+    thread::spawn(move || {
+        thread::sleep(Duration::from_secs(30));
+        let _ = timeout_writer.send(Message::Close);
+    });
+
+    let mut state = AppState::load(&[], Err(anyhow!("")));
+    let mut config = Config::default();
+    config.language_servers.push(LanguageServerConfig {
+        syntax: "Plain Text".to_owned(),
+        command: format!(
+            "{} {FAKE_SERVER_FLAG}",
+            shlex::try_quote(&env::current_exe()?.to_string_lossy())?
+        ),
+        language_id: None,
+    });
+    state.set_config(config);
+
+    let tmp_dir = tempfile::tempdir()?;
+    let path = tmp_dir.path().join("test.txt");
+    fs::write(&path, "def hello\nbad\n")?;
+    state.open_file_at_path(&path)?;
+
+    // The server is started after the next action.
+    state.handle_action(Action::Move(Move::Line, Direction::Inc), &writer)?;
+    run_until(&mut state, &mut reader, &writer, |state| {
+        !active_buffer(state).diagnostics().is_empty()
+    })?;
+    assert_eq!(diagnostic_ranges(&state), [AbsChar(10)..AbsChar(13)]);
+    assert_eq!(
+        active_buffer(&state).diagnostics()[0].severity,
+        DiagnosticSeverity::Error
+    );
+
+    // Edit the text. The diagnostics are only correct if the edits
+    // were synced correctly. The emoji is two UTF-16 code units.
+    state.handle_action(
+        Action::Move(Move::Boundary(Boundary::BufferEnd), Direction::Dec),
+        &writer,
+    )?;
+    insert_str(&mut state, &writer, "😀bad ")?;
+    run_until(&mut state, &mut reader, &writer, |state| {
+        diagnostic_ranges(state)
+            == [AbsChar(1)..AbsChar(4), AbsChar(15)..AbsChar(18)]
+    })?;
+
+    // Hover.
+    state.handle_action(Action::ShowHover, &writer)?;
+    run_until(&mut state, &mut reader, &writer, |state| {
+        state.message().is_some()
+    })?;
+    assert_eq!(state.message(), Some("line 0: 😀bad def hello"));
+
+    // Go to definition.
+    state.handle_action(
+        Action::Move(Move::Boundary(Boundary::BufferEnd), Direction::Dec),
+        &writer,
+    )?;
+    state.handle_action(Action::Move(Move::Line, Direction::Inc), &writer)?;
+    state.handle_action(
+        Action::Move(Move::Boundary(Boundary::LineEnd), Direction::Inc),
+        &writer,
+    )?;
+    insert_str(&mut state, &writer, " hello")?;
+    state.handle_action(Action::GoToDefinition, &writer)?;
+    let pane_id = state.pane_tree().active().id().clone();
+    run_until(&mut state, &mut reader, &writer, |state| {
+        active_buffer(state).cursor(&pane_id) == AbsChar(9)
+    })?;
+
+    // Completion.
+    state.handle_action(
+        Action::Move(Move::Boundary(Boundary::BufferEnd), Direction::Inc),
+        &writer,
+    )?;
+    insert_str(&mut state, &writer, "he")?;
+    state.handle_action(Action::Complete, &writer)?;
+    // The popup stays open for the language server's candidates, which
+    // replace the matching ones from the open buffers.
+    run_until(&mut state, &mut reader, &writer, |state| {
+        matches!(
+            state.overlay(),
+            Some(Overlay::Complete(widget)) if widget
+                .candidates()
+                .selected()
+                .is_some_and(|c| c.source == "lsp")
+        )
+    })?;
+    state.handle_action(Action::Confirm, &writer)?;
+    assert_eq!(
+        active_buffer(&state).text().to_string(),
+        "😀bad def hello\nbad hello\nhello"
+    );
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    if env::args().any(|arg| arg == FAKE_SERVER_FLAG) {
+        fake_lsp_server::run();
+        return Ok(());
+    }
+    test_lsp()
+}
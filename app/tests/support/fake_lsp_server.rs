@@ -0,0 +1,195 @@
+//! Minimal language server used to test the LSP client. Its responses
+//! are scripted from the contents of the open documents:
+//!
+//! * Every occurrence of "bad" is reported as an error diagnostic.
+//! * Hover returns "line N: <text of line N>".
+//! * The definition of a word is the word following the first "def ".
+//! * Completion returns every word in the document, other than the
+//!   one being completed.
+//!
+//! Since the responses depend on the server's copy of the text, they
+//! also check that the client's edits were synchronized correctly.
+
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(len) = line.strip_prefix("Content-Length: ") {
+            content_length = len.parse().ok()?;
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn write_message(msg: &Value) {
+    let body = msg.to_string();
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    stdout.flush().unwrap();
+}
+
+/// Convert an LSP position to a byte offset in `text`.
+fn position_to_offset(text: &str, pos: &Value) -> usize {
+    let line = pos["line"].as_u64().unwrap() as usize;
+    let character = pos["character"].as_u64().unwrap() as usize;
+
+    let line_start: usize =
+        text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn offset_to_position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({
+        "start": offset_to_position(text, start),
+        "end": offset_to_position(text, end),
+    })
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn word_at(text: &str, offset: usize) -> Range<usize> {
+    let start = text[..offset]
+        .rfind(|c| !is_word_char(c))
+        .map_or(0, |i| i + 1);
+    let end = text[offset..]
+        .find(|c| !is_word_char(c))
+        .map_or(text.len(), |i| offset + i);
+    start..end
+}
+
+fn publish_diagnostics(uri: &str, text: &str) {
+    let diagnostics: Vec<Value> = text
+        .match_indices("bad")
+        .map(|(i, m)| {
+            json!({
+                "range": range(text, i, i + m.len()),
+                "severity": 1,
+                "message": "bad word",
+            })
+        })
+        .collect();
+    write_message(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    }));
+}
+
+/// Serve requests on stdin until the client sends "exit".
+pub fn run() {
+    let mut stdin = io::stdin().lock();
+    let mut documents: HashMap<String, String> = HashMap::new();
+
+    while let Some(msg) = read_message(&mut stdin) {
+        let method = msg["method"].as_str().unwrap_or_default();
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        let result = match method {
+            "initialize" => json!({"capabilities": {
+                "textDocumentSync": 2,
+                "hoverProvider": true,
+                "definitionProvider": true,
+                "completionProvider": {},
+            }}),
+            "shutdown" => Value::Null,
+            "exit" => return,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap();
+                documents.insert(uri.to_owned(), text.to_owned());
+                publish_diagnostics(uri, text);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let text = documents.get_mut(uri).unwrap();
+                for change in params["contentChanges"].as_array().unwrap() {
+                    let new_text = change["text"].as_str().unwrap();
+                    if change.get("range").is_some() {
+                        let start =
+                            position_to_offset(text, &change["range"]["start"]);
+                        let end =
+                            position_to_offset(text, &change["range"]["end"]);
+                        text.replace_range(start..end, new_text);
+                    } else {
+                        *text = new_text.to_owned();
+                    }
+                }
+                publish_diagnostics(uri, text);
+                continue;
+            }
+            "textDocument/hover" => {
+                let text = &documents[uri];
+                let line =
+                    params["position"]["line"].as_u64().unwrap() as usize;
+                let line_text = text.lines().nth(line).unwrap_or_default();
+                json!({"contents": {
+                    "kind": "plaintext",
+                    "value": format!("line {line}: {line_text}"),
+                }})
+            }
+            "textDocument/definition" => {
+                let text = &documents[uri];
+                let offset = position_to_offset(text, &params["position"]);
+                let word = &text[word_at(text, offset)];
+                match text.find(&format!("def {word}")) {
+                    Some(i) if !word.is_empty() => json!({
+                        "uri": uri,
+                        "range": range(text, i + 4, i + 4 + word.len()),
+                    }),
+                    _ => Value::Null,
+                }
+            }
+            "textDocument/completion" => {
+                let mut text = documents[uri].clone();
+                let offset = position_to_offset(&text, &params["position"]);
+                text.replace_range(word_at(&text, offset), "");
+                let words: BTreeSet<&str> = text
+                    .split(|c| !is_word_char(c))
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                let items: Vec<Value> =
+                    words.iter().map(|w| json!({"label": w})).collect();
+                json!(items)
+            }
+            _ => continue,
+        };
+
+        if let Some(id) = msg.get("id") {
+            write_message(
+                &json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            );
+        }
+    }
+}
@@ -1,18 +1,27 @@
 use anyhow::Result;
-use emma_app::LineHeight;
 use emma_app::buffer::{
-    Buffer, LineMatches, LinePosition, LinesIterItem, StyleSpan, StyledLine,
+    AbsChar, Buffer, DiagnosticSeverity, LineMatches, LinePosition,
+    LinesIterItem, StyleSpan, StyledLine,
 };
+use emma_app::buffer_switcher::{BufferEntry, MAX_VISIBLE_BUFFERS};
+use emma_app::candidate_list::CandidateList;
+use emma_app::command_palette::{CommandEntry, MAX_VISIBLE_COMMANDS};
+use emma_app::completion::CompletionWidget;
+use emma_app::file_finder::{FileEntry, MAX_VISIBLE_FILES};
 use emma_app::grapheme::next_grapheme_boundary;
+use emma_app::modal::Mode;
 use emma_app::overlay::Overlay;
-use emma_app::pane_tree::Pane;
+use emma_app::pane_tree::{PANE_TEXT_MARGIN, Pane, Rect};
+use emma_app::path_chooser::MAX_VISIBLE_SUGGESTIONS;
 use emma_app::rope::{LineDataVec, RopeSlice};
 use emma_app::state::AppState;
 use emma_app::theme::Theme;
 use emma_app::widget::Widget;
+use emma_app::{CharWidth, LineHeight};
 use gtk4::pango::{self, Layout};
 use gtk4::prelude::WidgetExt;
 use gtk4::{DrawingArea, cairo};
+use std::borrow::Cow;
 use std::fmt;
 use std::ops::Range;
 use syntect::highlighting::Style;
@@ -41,7 +50,7 @@ fn pango_unscale(i: i32) -> f64 {
     i as f64 / pango::SCALE as f64
 }
 
-#[derive(Default)]
+#[derive(Clone, Copy, Default)]
 struct Point {
     x: f64,
     y: f64,
@@ -63,12 +72,47 @@ pub fn calculate_line_height(widget: &DrawingArea) -> LineHeight {
     LineHeight(pango_unscale(metrics.height()))
 }
 
+pub fn calculate_char_width(widget: &DrawingArea) -> CharWidth {
+    let pctx = widget.pango_context();
+    let font_desc = pctx.font_description();
+
+    let language = None;
+    let metrics = pctx.metrics(font_desc.as_ref(), language);
+
+    CharWidth(pango_unscale(metrics.approximate_char_width()))
+}
+
 struct StyledLayout {
     layout: Layout,
     // TODO: this should be a reference but then things get *really*
     // complicated with the borrow checker.
     style: Style,
     is_cursor: bool,
+    /// Which of the line's rows this is drawn on, when wrapped.
+    row: usize,
+}
+
+/// Where a line is drawn in a pane.
+struct LineView {
+    /// Char ranges of the rows the line is drawn on.
+    rows: Vec<Range<usize>>,
+    /// Chars scrolled into view. With wrapping off, chars cut off at
+    /// either side of the pane aren't drawn.
+    visible: Range<usize>,
+    /// Rows scrolled off the top of the pane.
+    skip_rows: usize,
+    /// Where each row starts.
+    left: f64,
+    /// Where the first row that isn't skipped is drawn.
+    top: f64,
+}
+
+/// Get the index of the row in `rows` that `offset` is drawn on. The
+/// newline at the end of the line is drawn on the last row.
+fn row_of_offset(rows: &[Range<usize>], offset: usize) -> usize {
+    rows.iter()
+        .rposition(|row| row.start <= offset)
+        .unwrap_or(0)
 }
 
 fn apply_match_style(
@@ -127,83 +171,26 @@ fn apply_match_style(
     StyledLine(output)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn match_style() -> Style {
-        Style::default()
-    }
-
-    fn style1() -> Style {
-        let mut style = Style::default();
-        style.foreground.r = 1;
-        style
-    }
-
-    #[test]
-    fn test_apply_match_style() {
-        let base_spans = StyledLine(vec![StyleSpan {
-            len: 5,
-            style: style1(),
-        }]);
-        let mut matches = LineMatches { spans: vec![] };
-
-        fn label(styled_line: &StyledLine) -> Vec<(&'static str, usize)> {
-            styled_line
-                .0
-                .iter()
-                .map(|span| {
-                    let name = if span.style == match_style() {
-                        "match"
-                    } else if span.style == style1() {
-                        "style1"
-                    } else {
-                        "unknown"
-                    };
-                    (name, span.len)
-                })
-                .collect()
-        }
-
-        fn check(
-            base_spans: &StyledLine,
-            matches: &LineMatches,
-            expected: &[(&str, usize)],
-        ) {
-            let mods = apply_match_style(&base_spans, matches, &match_style());
-            assert_eq!(label(&mods), expected);
-        }
-
-        // No matches
-        check(&base_spans, &matches, &[("style1", 5)]);
-
-        // One match, replaces the base span
-        matches.spans = vec![0..5];
-        check(&base_spans, &matches, &[("match", 5)]);
-
-        // One match at the start of the base span
-        matches.spans = vec![0..3];
-        check(&base_spans, &matches, &[("match", 3), ("style1", 2)]);
-
-        // One match at the end of the base span
-        matches.spans = vec![3..5];
-        check(&base_spans, &matches, &[("style1", 3), ("match", 2)]);
-    }
-}
-
 struct DrawPane<'a> {
     ctx: &'a cairo::Context,
     widget: &'a DrawingArea,
     pane: &'a Pane,
     buf: &'a Buffer,
     line_height: LineHeight,
+    char_width: CharWidth,
     theme: &'a Theme,
     span_buf: String,
     margin: f64,
     cursor: LinePosition,
+    selection: Option<Range<AbsChar>>,
     len_lines: usize,
     pos: Point,
+    /// Where the cursor was drawn, if it's in view.
+    cursor_point: Option<Point>,
+    /// Editing mode shown in the info bar, if modal editing is on.
+    mode: Option<Mode>,
+    /// Whether to show that a keyboard macro is being recorded.
+    recording_macro: bool,
 }
 
 impl fmt::Debug for DrawPane<'_> {
@@ -245,6 +232,7 @@ impl DrawPane<'_> {
     fn styled_layouts_from_line(
         &mut self,
         line: &LinesIterItem,
+        view: &LineView,
     ) -> LineDataVec<StyledLayout> {
         let mut output = LineDataVec::new(line.index);
 
@@ -254,32 +242,60 @@ impl DrawPane<'_> {
             ..Style::default()
         };
 
-        let base_style_spans = self.buf.style_spans().get(line.index).unwrap();
-        let mut style_spans = base_style_spans;
+        let selection_style = Style {
+            background: self.theme.selection.background,
+            foreground: self.theme.selection.foreground,
+            ..Style::default()
+        };
+
         // TODO: share across iterations
-        let modified_style_spans;
+        let mut style_spans =
+            Cow::Borrowed(self.buf.style_spans().get(line.index).unwrap());
         if let Some(search) = self.buf.search_state()
             && let Some(matches) = search.line_matches(self.pane, line.index)
         {
-            modified_style_spans =
-                apply_match_style(base_style_spans, matches, &match_style);
-
-            style_spans = &modified_style_spans;
+            style_spans = Cow::Owned(apply_match_style(
+                &style_spans,
+                matches,
+                &match_style,
+            ));
+        }
+        if let Some(selection) = self.selection_in_line(line) {
+            style_spans = Cow::Owned(apply_match_style(
+                &style_spans,
+                &LineMatches {
+                    spans: vec![selection],
+                },
+                &selection_style,
+            ));
         }
 
         let mut span_offset = 0;
         for span in &style_spans.0 {
             debug!("span: {} chars", span.len);
-            let mut push =
-                |me: &mut DrawPane, range: Range<usize>, is_cursor| {
-                    if !range.is_empty() {
-                        output.push(StyledLayout {
-                            layout: me.layout_line_range(&line.slice, range),
-                            style: span.style,
-                            is_cursor,
-                        });
-                    }
-                };
+            // Leave out what's scrolled out of view, and split the
+            // range where the line wraps, so that each layout is
+            // drawn on one row.
+            let mut push = |me: &mut DrawPane,
+                            range: Range<usize>,
+                            is_cursor| {
+                let mut start = range.start.max(view.visible.start);
+                let range_end = range.end.min(view.visible.end);
+                while start < range_end {
+                    let row = row_of_offset(&view.rows, start);
+                    let end = match view.rows.get(row + 1) {
+                        Some(next) => range_end.min(next.start),
+                        None => range_end,
+                    };
+                    output.push(StyledLayout {
+                        layout: me.layout_line_range(&line.slice, start..end),
+                        style: span.style,
+                        is_cursor,
+                        row,
+                    });
+                    start = end;
+                }
+            };
 
             let span_range = span_offset..span_offset + span.len;
             span_offset += span.len;
@@ -308,12 +324,14 @@ impl DrawPane<'_> {
         if self.cursor.line == line.index
             && line.index.0 + 1 == self.len_lines
             && self.cursor.offset.0 == line.slice.len_chars()
+            && view.visible.contains(&self.cursor.offset.0)
         {
             debug!("eof cursor");
             output.push(StyledLayout {
                 layout: self.create_layout(""),
                 style: Style::default(),
                 is_cursor: true,
+                row: view.rows.len() - 1,
             });
             return output;
         }
@@ -321,7 +339,118 @@ impl DrawPane<'_> {
         output
     }
 
+    /// Get the part of the selection within `line` as a range of char
+    /// offsets from the start of the line.
+    fn selection_in_line(&self, line: &LinesIterItem) -> Option<Range<usize>> {
+        let selection = self.selection.as_ref()?;
+        let line_start = self.buf.text().line_to_char(line.index);
+        let line_end = line_start + line.slice.len_chars();
+
+        let start = selection.start.0.max(line_start);
+        let end = selection.end.0.min(line_end);
+        if start < end {
+            Some(start - line_start..end - line_start)
+        } else {
+            None
+        }
+    }
+
+    /// Get the parts of the buffer's diagnostics within `line` as
+    /// ranges of char offsets from the start of the line.
+    fn diagnostics_in_line(
+        &self,
+        line: &LinesIterItem,
+    ) -> Vec<(Range<usize>, DiagnosticSeverity)> {
+        let line_start = self.buf.text().line_to_char(line.index);
+        let line_end = line_start + line.slice.len_chars();
+
+        let mut output = Vec::new();
+        for diag in self.buf.diagnostics() {
+            let range = &diag.range;
+            if range.start.0 > line_end || range.end.0 < line_start {
+                continue;
+            }
+            let start = range.start.0.max(line_start) - line_start;
+            let end = range.end.0.min(line_end) - line_start;
+            if range.is_empty() {
+                // Show an empty range as one char wide.
+                output.push((start..start + 1, diag.severity));
+            } else if start < end {
+                output.push((start..end, diag.severity));
+            }
+        }
+        output
+    }
+
+    /// Underline the buffer's diagnostics within the part of `line`
+    /// in `view`.
+    fn draw_diagnostics(
+        &mut self,
+        line: &LinesIterItem,
+        view: &LineView,
+    ) -> Result<()> {
+        let diagnostics = self.diagnostics_in_line(line);
+        if diagnostics.is_empty() {
+            return Ok(());
+        }
+
+        let rows = &view.rows;
+        let len_chars = line.slice.len_chars().min(view.visible.end);
+        for (range, severity) in diagnostics {
+            if range.end <= view.visible.start
+                || range.start >= view.visible.end
+            {
+                continue;
+            }
+            let first_row =
+                row_of_offset(rows, range.start).max(view.skip_rows);
+            let last_row = row_of_offset(rows, range.end.saturating_sub(1));
+            for row in first_row..=last_row {
+                let row_start = rows[row].start;
+                let mut width_to = |offset: usize| {
+                    let offset = offset.clamp(row_start, len_chars);
+                    pango_unscale(
+                        self.layout_line_range(&line.slice, row_start..offset)
+                            .size()
+                            .0,
+                    )
+                };
+                let x0 = view.left + width_to(range.start);
+                let end = match rows.get(row + 1) {
+                    Some(next) => range.end.min(next.start),
+                    None => range.end,
+                };
+                let mut x1 = view.left + width_to(end);
+                if x1 <= x0 {
+                    // E.g. the diagnostic is on a newline.
+                    x1 = x0 + self.line_height.0 / 2.0;
+                }
+                let y = view.top
+                    + (row - view.skip_rows + 1) as f64 * self.line_height.0
+                    - 1.0;
+
+                let color = match severity {
+                    DiagnosticSeverity::Error => &self.theme.diagnostic_error,
+                    DiagnosticSeverity::Warning => {
+                        &self.theme.diagnostic_warning
+                    }
+                    DiagnosticSeverity::Information
+                    | DiagnosticSeverity::Hint => &self.theme.diagnostic_info,
+                };
+                set_source_from_syntect_color(self.ctx, &color.foreground);
+                self.ctx.set_line_width(1.0);
+                self.ctx.move_to(x0, y);
+                self.ctx.line_to(x1, y);
+                self.ctx.stroke()?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn draw_cursor(&mut self, styled_layout: &StyledLayout) -> Result<()> {
+        self.cursor_point = Some(self.pos);
+
         if !self.pane.is_cursor_visible() {
             debug!("cursor not visible");
             return Ok(());
@@ -363,17 +492,76 @@ impl DrawPane<'_> {
         Ok(())
     }
 
-    fn draw_line(&mut self, line: &LinesIterItem) -> Result<()> {
-        self.pos.x = self.pane.rect().x;
+    /// Draw an indicator that text is cut off at the edge of the pane.
+    fn draw_truncation_indicator(&mut self, x: f64, y: f64) {
+        let layout = self.create_layout("$");
+        set_source_rgba_from_u8(self.ctx, 220, 220, 204, 128);
+        self.ctx.move_to(x, y);
+        pangocairo::functions::show_layout(self.ctx, &layout);
+    }
+
+    /// Draw `line`, skipping the first `skip_rows` rows if it's
+    /// wrapped and scrolled partway into view.
+    fn draw_line(
+        &mut self,
+        line: &LinesIterItem,
+        skip_rows: usize,
+    ) -> Result<()> {
+        let rect = self.pane.rect();
+        let top = self.pos.y;
 
         self.ctx.move_to(self.margin, self.pos.y);
 
         set_source_rgb_from_u8(self.ctx, 220, 220, 204);
 
-        let styled_layouts = self.styled_layouts_from_line(line);
+        let truncated = (!self.pane.is_wrapped())
+            .then(|| self.pane.truncate_line(self.buf, line.index));
+        let view = match &truncated {
+            Some(truncated) => {
+                // The newline is in view unless the line is cut off.
+                let end = if truncated.cut_right {
+                    truncated.range.end
+                } else {
+                    usize::MAX
+                };
+                LineView {
+                    rows: vec![truncated.range.clone()],
+                    visible: truncated.range.start..end,
+                    skip_rows: 0,
+                    left: rect.x + truncated.indent as f64 * self.char_width.0,
+                    top,
+                }
+            }
+            None => {
+                let rows = self.pane.rows(self.buf, line.index);
+                LineView {
+                    skip_rows: skip_rows.min(rows.len() - 1),
+                    rows,
+                    visible: 0..usize::MAX,
+                    left: rect.x,
+                    top,
+                }
+            }
+        };
+        let skip_rows = view.skip_rows;
+        self.pos.x = view.left;
+        let styled_layouts = self.styled_layouts_from_line(line, &view);
 
+        let mut row = skip_rows;
         for styled_layout in styled_layouts.iter() {
             let styled_layout = &styled_layout.data;
+            if styled_layout.row < skip_rows {
+                continue;
+            }
+            if styled_layout.row != row {
+                row = styled_layout.row;
+                self.pos.x = view.left;
+                self.pos.y =
+                    top + (row - skip_rows) as f64 * self.line_height.0;
+            }
+            if self.pos.y > rect.y + rect.height {
+                break;
+            }
 
             // Draw background
             set_source_from_syntect_color(
@@ -406,7 +594,19 @@ impl DrawPane<'_> {
             self.draw_layout(&styled_layout.layout);
         }
 
-        self.pos.y += self.line_height.0;
+        if let Some(truncated) = &truncated {
+            if truncated.cut_left {
+                self.draw_truncation_indicator(rect.x, top);
+            }
+            if truncated.cut_right {
+                self.draw_truncation_indicator(self.pos.x, top);
+            }
+        }
+
+        self.draw_diagnostics(line, &view)?;
+
+        self.pos.y =
+            top + (view.rows.len() - skip_rows) as f64 * self.line_height.0;
         Ok(())
     }
 
@@ -431,27 +631,73 @@ impl DrawPane<'_> {
         );
         self.ctx.fill()?;
 
-        if let Some(path) = self.buf.path() {
-            let name = path.file_name().expect("path has no file name");
+        if self.pane.is_active() {
+            set_source_from_syntect_color(
+                self.ctx,
+                &self.theme.info_bar_active.foreground,
+            );
+        } else {
+            set_source_from_syntect_color(
+                self.ctx,
+                &self.theme.info_bar_inactive.foreground,
+            );
+        }
 
-            if self.pane.is_active() {
-                set_source_from_syntect_color(
-                    self.ctx,
-                    &self.theme.info_bar_active.foreground,
-                );
-            } else {
-                set_source_from_syntect_color(
-                    self.ctx,
-                    &self.theme.info_bar_inactive.foreground,
-                );
-            }
+        let mut text = self.buf.display_name();
+        if let Some(mode) = self.mode {
+            text = format!("{mode} {text}");
+        }
+        if self.recording_macro {
+            text = format!("REC {text}");
+        }
+        let layout = self.create_layout(&text);
+
+        self.pos.x = rect.x;
+        self.pos.y = rect.y + rect.height - self.line_height.0;
+        self.draw_layout(&layout);
+
+        // How far through the buffer the pane is, at the right end.
+        let layout = self.create_layout(
+            &self.pane.scroll_position(self.buf, self.line_height),
+        );
+        self.pos.x = rect.x + rect.width
+            - pango_unscale(layout.size().0)
+            - self.line_height.0 / 2.0;
+        self.draw_layout(&layout);
+
+        Ok(())
+    }
 
-            let layout = self.create_layout(&name.to_string_lossy());
+    /// Draw a thumb at the right edge of the pane showing which part
+    /// of the buffer is in view, unless all of it is.
+    fn draw_scrollbar(&mut self) -> Result<()> {
+        let Some(visible) =
+            self.pane.visible_fraction(self.buf, self.line_height)
+        else {
+            return Ok(());
+        };
 
-            self.pos.x = rect.x;
-            self.pos.y = rect.y + rect.height - self.line_height.0;
-            self.draw_layout(&layout);
+        let rect = self.pane.rect();
+        let mut track_height = rect.height;
+        if self.pane.show_info_bar() {
+            track_height -= self.line_height.0;
         }
+        let width = 4.0;
+        let border = 0.5;
+        // Keep the thumb big enough to see in long buffers.
+        let height =
+            ((visible.end - visible.start) * track_height).max(width * 2.0);
+        let y = (rect.y + visible.start * track_height)
+            .min(rect.y + track_height - height);
+
+        set_source_rgba_from_u8(self.ctx, 220, 220, 204, 96);
+        self.ctx.rectangle(
+            rect.x + rect.width - border - width,
+            y,
+            width,
+            height,
+        );
+        self.ctx.fill()?;
 
         Ok(())
     }
@@ -473,11 +719,14 @@ impl DrawPane<'_> {
             self.buf.cursor(self.pane.id()),
             self.buf,
         );
+        self.selection = self.buf.selection(self.pane.id());
 
         self.pos.y = rect.y + self.margin;
 
+        let mut skip_rows = self.pane.top_row();
         for line in self.buf.text().lines_at(self.pane.top_line()) {
-            self.draw_line(&line)?;
+            self.draw_line(&line, skip_rows)?;
+            skip_rows = 0;
 
             // Stop if rendering past the bottom of the widget. TODO:
             // is this the right calculation?
@@ -486,6 +735,8 @@ impl DrawPane<'_> {
             }
         }
 
+        self.draw_scrollbar()?;
+
         if self.pane.show_info_bar() {
             self.draw_info_bar()?;
         }
@@ -514,6 +765,7 @@ pub(super) fn draw(
         error!("fill failed: {}", err);
     }
 
+    let mut active_cursor_point = None;
     for pane in state.pane_tree().panes() {
         let buf = state.buffers().get(pane.buffer_id()).unwrap();
 
@@ -523,28 +775,248 @@ pub(super) fn draw(
             pane,
             buf,
             line_height,
+            char_width: state.char_width(),
             theme,
             span_buf: String::new(),
-            margin: 2.0,
+            margin: PANE_TEXT_MARGIN,
             cursor: LinePosition::default(),
+            selection: None,
             len_lines: buf.text().len_lines(),
             pos: Point::default(),
+            cursor_point: None,
+            mode: if pane.is_active() { state.mode() } else { None },
+            recording_macro: pane.is_active() && state.is_recording_macro(),
         };
         if let Err(err) = dp.draw() {
             error!("failed to draw pane: {}", err);
         }
+        if pane.is_active() {
+            active_cursor_point = dp.cursor_point;
+        }
+    }
+
+    draw_echo_area(state, widget, ctx);
+
+    match state.overlay() {
+        Some(Overlay::Complete(completion)) => {
+            // The popup is anchored to the cursor, so it's only shown
+            // while the cursor is in view.
+            if let Some(anchor) = active_cursor_point {
+                let popup = CompletionPopup {
+                    completion,
+                    widget,
+                    ctx,
+                    line_height,
+                    theme,
+                };
+                if let Err(err) = popup.draw(anchor, width, height) {
+                    error!("failed to draw completion popup: {}", err);
+                }
+            }
+        }
+        Some(overlay) => {
+            draw_interactive_widget(
+                overlay,
+                widget,
+                ctx,
+                line_height,
+                state.char_width(),
+                theme,
+            );
+        }
+        None => {}
+    }
+
+    if let Err(err) = draw_key_hints(state, widget, ctx, line_height) {
+        error!("failed to draw key hints: {}", err);
+    }
+}
+
+/// Maximum number of candidates shown in the completion popup.
+const MAX_COMPLETION_ROWS: usize = 10;
+
+struct CompletionPopup<'a> {
+    completion: &'a CompletionWidget,
+    widget: &'a DrawingArea,
+    ctx: &'a cairo::Context,
+    line_height: LineHeight,
+    theme: &'a Theme,
+}
+
+impl CompletionPopup<'_> {
+    /// Draw the popup below the cursor at `anchor`, or above it if
+    /// there isn't room below. The first row shows any text typed
+    /// since the popup opened, followed by the matching candidates
+    /// with their sources.
+    fn draw(&self, anchor: Point, width: f64, height: f64) -> Result<()> {
+        let candidates = self.completion.candidates();
+        let visible = candidates.visible_range(MAX_COMPLETION_ROWS);
+        let rows: Vec<(Layout, Layout)> = candidates
+            .matches()
+            .skip(visible.start)
+            .take(visible.len())
+            .map(|c| {
+                (
+                    self.widget.create_pango_layout(Some(&c.label)),
+                    self.widget.create_pango_layout(Some(c.source)),
+                )
+            })
+            .collect();
+
+        let mut query = self.completion.query();
+        if rows.is_empty() {
+            query += " (no matches)";
+        }
+        let query_layout = if query.is_empty() {
+            None
+        } else {
+            Some(self.widget.create_pango_layout(Some(&query)))
+        };
+
+        let padding = self.line_height.0 / 2.0;
+        let label_width = rows
+            .iter()
+            .map(|(label, _)| pango_unscale(label.size().0))
+            .fold(0.0, f64::max);
+        let source_width = rows
+            .iter()
+            .map(|(_, source)| pango_unscale(source.size().0))
+            .fold(0.0, f64::max);
+        let popup_width = query_layout
+            .iter()
+            .map(|layout| pango_unscale(layout.size().0))
+            .fold(label_width + padding + source_width, f64::max)
+            + padding * 2.0;
+        let num_rows = rows.len() + usize::from(query_layout.is_some());
+        let popup_height = num_rows as f64 * self.line_height.0;
+
+        let x = anchor.x.min(width - popup_width).max(0.0);
+        let mut y = anchor.y + self.line_height.0;
+        if y + popup_height > height {
+            y = (anchor.y - popup_height).max(0.0);
+        }
+
+        // Background.
+        self.ctx.rectangle(x, y, popup_width, popup_height);
+        set_source_rgb_from_u8(self.ctx, 63, 63, 100);
+        self.ctx.fill()?;
+
+        if let Some(layout) = &query_layout {
+            set_source_rgb_from_u8(self.ctx, 200, 200, 200);
+            self.ctx.move_to(x + padding, y);
+            pangocairo::functions::show_layout(self.ctx, layout);
+            y += self.line_height.0;
+        }
+
+        let selected = candidates.selected_index();
+        for (index, (label, source)) in rows.iter().enumerate() {
+            if visible.start + index == selected {
+                set_source_from_syntect_color(
+                    self.ctx,
+                    &self.theme.selection.background,
+                );
+                self.ctx.rectangle(x, y, popup_width, self.line_height.0);
+                self.ctx.fill()?;
+            }
+
+            set_source_rgb_from_u8(self.ctx, 220, 220, 204);
+            self.ctx.move_to(x + padding, y);
+            pangocairo::functions::show_layout(self.ctx, label);
+
+            set_source_rgb_from_u8(self.ctx, 150, 150, 150);
+            self.ctx.move_to(
+                x + popup_width - padding - pango_unscale(source.size().0),
+                y,
+            );
+            pangocairo::functions::show_layout(self.ctx, source);
+
+            y += self.line_height.0;
+        }
+
+        Ok(())
     }
+}
 
-    if let Some(overlay) = &state.overlay() {
-        draw_interactive_widget(overlay, widget, ctx, line_height, theme);
+fn draw_echo_area(
+    state: &AppState,
+    widget: &DrawingArea,
+    ctx: &cairo::Context,
+) {
+    let r = state.echo_area();
+    ctx.rectangle(r.x, r.y, r.width, r.height);
+    set_source_rgb_from_u8(ctx, 63, 63, 63);
+    if let Err(err) = ctx.fill() {
+        error!("fill failed: {}", err);
+    }
+
+    if let Some(message) = state.message() {
+        let layout = widget.create_pango_layout(Some(message));
+        set_source_rgb_from_u8(ctx, 220, 220, 204);
+        ctx.move_to(r.x, r.y);
+        pangocairo::functions::show_layout(ctx, &layout);
     }
 }
 
+/// Draw the bindings that could complete the pending key sequence
+/// just above the echo area, in as many rows as fit.
+fn draw_key_hints(
+    state: &AppState,
+    widget: &DrawingArea,
+    ctx: &cairo::Context,
+    line_height: LineHeight,
+) -> Result<()> {
+    let hints = state.key_hints();
+    if hints.is_empty() {
+        return Ok(());
+    }
+
+    let echo_area = state.echo_area();
+    let max_rows = (echo_area.y / line_height.0) as usize;
+    let rows: Vec<(Layout, Layout)> = hints
+        .iter()
+        .take(max_rows)
+        .map(|hint| {
+            (
+                widget.create_pango_layout(Some(&hint.keys)),
+                widget.create_pango_layout(Some(&hint.action)),
+            )
+        })
+        .collect();
+
+    let padding = line_height.0 / 2.0;
+    let keys_width = rows
+        .iter()
+        .map(|(keys, _)| pango_unscale(keys.size().0))
+        .fold(0.0, f64::max);
+    let height = rows.len() as f64 * line_height.0;
+    let mut y = echo_area.y - height;
+
+    // Background.
+    ctx.rectangle(echo_area.x, y, echo_area.width, height);
+    set_source_rgb_from_u8(ctx, 63, 63, 100);
+    ctx.fill()?;
+
+    for (keys, action) in &rows {
+        set_source_rgb_from_u8(ctx, 220, 220, 204);
+        ctx.move_to(echo_area.x + padding, y);
+        pangocairo::functions::show_layout(ctx, keys);
+
+        set_source_rgb_from_u8(ctx, 150, 150, 150);
+        ctx.move_to(echo_area.x + padding * 2.0 + keys_width, y);
+        pangocairo::functions::show_layout(ctx, action);
+
+        y += line_height.0;
+    }
+
+    Ok(())
+}
+
 fn draw_interactive_widget(
     overlay: &Overlay,
     widget: &DrawingArea,
     ctx: &cairo::Context,
     line_height: LineHeight,
+    char_width: CharWidth,
     theme: &Theme,
 ) {
     // Fill in the background.
@@ -585,22 +1057,190 @@ fn draw_interactive_widget(
         pane: overlay.pane(),
         buf,
         line_height,
+        char_width,
         theme,
         span_buf: String::new(),
-        margin: 2.0,
+        margin: PANE_TEXT_MARGIN,
         cursor: LinePosition::default(),
+        selection: None,
         len_lines: buf.text().len_lines(),
         pos: Point::default(),
+        cursor_point: None,
+        mode: None,
+        recording_macro: false,
     };
     if let Err(err) = dp.draw() {
         error!("failed to draw pane: {}", err);
     }
 
     // Suggestions.
-    if let Overlay::OpenFile(open_file) = overlay {
-        let layout = widget.create_pango_layout(Some(&open_file.suggestions()));
-        set_source_rgb_from_u8(ctx, 200, 200, 200);
-        ctx.move_to(r.x, r.y + line_height.0 * 2.0);
-        pangocairo::functions::show_layout(ctx, &layout);
+    match overlay {
+        Overlay::OpenFile(open_file) => {
+            let list = CandidateRows {
+                candidates: open_file.suggestions(),
+                max_rows: MAX_VISIBLE_SUGGESTIONS,
+                label: |name: &String| (name.as_str(), None),
+            };
+            if let Err(err) = list.draw(r, widget, ctx, line_height, theme) {
+                error!("failed to draw path suggestions: {}", err);
+            }
+        }
+        Overlay::SwitchBuffer(switcher) => {
+            let list = CandidateRows {
+                candidates: switcher.candidates(),
+                max_rows: MAX_VISIBLE_BUFFERS,
+                label: |entry: &BufferEntry| {
+                    (entry.name.as_str(), entry.path.as_deref())
+                },
+            };
+            if let Err(err) = list.draw(r, widget, ctx, line_height, theme) {
+                error!("failed to draw buffer list: {}", err);
+            }
+        }
+        Overlay::FindFile(finder) => {
+            let list = CandidateRows {
+                candidates: finder.candidates(),
+                max_rows: MAX_VISIBLE_FILES,
+                label: |entry: &FileEntry| (entry.display.as_str(), None),
+            };
+            if let Err(err) = list.draw(r, widget, ctx, line_height, theme) {
+                error!("failed to draw file list: {}", err);
+            }
+        }
+        Overlay::CommandPalette(palette) => {
+            let list = CandidateRows {
+                candidates: palette.candidates(),
+                max_rows: MAX_VISIBLE_COMMANDS,
+                label: |entry: &CommandEntry| {
+                    (entry.name.as_str(), entry.keys.as_deref())
+                },
+            };
+            if let Err(err) = list.draw(r, widget, ctx, line_height, theme) {
+                error!("failed to draw command list: {}", err);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A list of candidates drawn one per row below an overlay's input
+/// line. Each row shows a name, optionally followed by dimmer detail
+/// text such as a path.
+struct CandidateRows<'a, T> {
+    candidates: &'a CandidateList<T>,
+    max_rows: usize,
+    label: fn(&T) -> (&str, Option<&str>),
+}
+
+impl<T> CandidateRows<'_, T> {
+    fn draw(
+        &self,
+        r: &Rect,
+        widget: &DrawingArea,
+        ctx: &cairo::Context,
+        line_height: LineHeight,
+        theme: &Theme,
+    ) -> Result<()> {
+        let candidates = self.candidates;
+        let visible = candidates.visible_range(self.max_rows);
+        let padding = line_height.0 / 2.0;
+        let mut y = r.y + line_height.0 * 2.0;
+        for (index, entry) in candidates
+            .matches()
+            .enumerate()
+            .skip(visible.start)
+            .take(visible.len())
+        {
+            if index == candidates.selected_index() {
+                set_source_from_syntect_color(ctx, &theme.selection.background);
+                ctx.rectangle(r.x, y, r.width, line_height.0);
+                ctx.fill()?;
+            }
+
+            let (name, detail) = (self.label)(entry);
+            let name = widget.create_pango_layout(Some(name));
+            set_source_rgb_from_u8(ctx, 220, 220, 204);
+            ctx.move_to(r.x + padding, y);
+            pangocairo::functions::show_layout(ctx, &name);
+
+            if let Some(detail) = detail {
+                let detail = widget.create_pango_layout(Some(detail));
+                set_source_rgb_from_u8(ctx, 150, 150, 150);
+                ctx.move_to(
+                    r.x + padding * 3.0 + pango_unscale(name.size().0),
+                    y,
+                );
+                pangocairo::functions::show_layout(ctx, &detail);
+            }
+
+            y += line_height.0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn match_style() -> Style {
+        Style::default()
+    }
+
+    fn style1() -> Style {
+        let mut style = Style::default();
+        style.foreground.r = 1;
+        style
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_apply_match_style() {
+        let base_spans = StyledLine(vec![StyleSpan {
+            len: 5,
+            style: style1(),
+        }]);
+        let mut matches = LineMatches { spans: vec![] };
+
+        fn label(styled_line: &StyledLine) -> Vec<(&'static str, usize)> {
+            styled_line
+                .0
+                .iter()
+                .map(|span| {
+                    let name = if span.style == match_style() {
+                        "match"
+                    } else if span.style == style1() {
+                        "style1"
+                    } else {
+                        "unknown"
+                    };
+                    (name, span.len)
+                })
+                .collect()
+        }
+
+        fn check(
+            base_spans: &StyledLine,
+            matches: &LineMatches,
+            expected: &[(&str, usize)],
+        ) {
+            let mods = apply_match_style(base_spans, matches, &match_style());
+            assert_eq!(label(&mods), expected);
+        }
+
+        // No matches
+        check(&base_spans, &matches, &[("style1", 5)]);
+
+        // One match, replaces the base span
+        matches.spans = vec![0..5];
+        check(&base_spans, &matches, &[("match", 5)]);
+
+        // One match at the start of the base span
+        matches.spans = vec![0..3];
+        check(&base_spans, &matches, &[("match", 3), ("style1", 2)]);
+
+        // One match at the end of the base span
+        matches.spans = vec![3..5];
+        check(&base_spans, &matches, &[("style1", 3), ("match", 2)]);
     }
 }
@@ -1,4 +1,4 @@
-use crate::{draw, key};
+use crate::{draw, key, mouse};
 use emma_app::config::Config;
 use emma_app::message::{Message, create_message_pipe};
 use emma_app::state::AppState;
@@ -57,6 +57,7 @@ pub fn init(application: &Application) {
 
     let mut state = AppState::load(&persisted_buffers, pane_tree_json);
     state.enable_persistence();
+    state.set_config(config);
     let state = Rc::new(RefCell::new(state));
 
     // Create top-level window.
@@ -92,6 +93,7 @@ pub fn init(application: &Application) {
         }
     ));
     window.set_child(Some(&widget));
+    mouse::add_controllers(&widget, &state);
 
     let message_writer_2 = message_writer.try_clone().unwrap();
 
@@ -103,12 +105,18 @@ pub fn init(application: &Application) {
         #[strong]
         widget,
         move |_self, keyval, _keycode, modifiers| {
+            // Unknown keys are logged by the conversion and
+            // otherwise ignored.
+            let Some(key) = key::key_from_gdk(keyval) else {
+                return Propagation::Stop;
+            };
+
             // Not every action requires redraw, but most do, no harm
             // occasionally redrawing when not needed.
             widget.queue_draw();
 
             state.borrow_mut().handle_key_press(
-                key::key_from_gdk(keyval),
+                key,
                 key::modifiers_from_gdk(modifiers),
                 &message_writer,
             );
@@ -155,6 +163,9 @@ pub fn init(application: &Application) {
     state
         .borrow_mut()
         .set_line_height(draw::calculate_line_height(&widget));
+    state
+        .borrow_mut()
+        .set_char_width(draw::calculate_char_width(&widget));
 
     // Gtk warns if there's no handler for this signal, so add an empty
     // handler.
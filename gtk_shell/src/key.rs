@@ -1,35 +1,76 @@
-use emma_app::key::{Key, Modifier, Modifiers};
+use emma_app::key::{Key, MAX_FUNCTION_KEY, Modifier, Modifiers};
 use gtk4::gdk::{Key as GKey, ModifierType};
+use gtk4::glib::translate::IntoGlib;
+use tracing::warn;
 
-pub fn key_from_gdk(key: gtk4::gdk::Key) -> Key {
-    match key {
+/// Convert a GDK key. Returns `None`, after logging it, for a key
+/// that has no equivalent.
+pub fn key_from_gdk(key: gtk4::gdk::Key) -> Option<Key> {
+    // Function keys have consecutive values.
+    let function = key.into_glib().wrapping_sub(GKey::F1.into_glib());
+    if function < u32::from(MAX_FUNCTION_KEY) {
+        return Some(Key::Function(function as u8 + 1));
+    }
+
+    let key = match key {
         GKey::BackSpace => Key::Backspace,
+        GKey::Delete | GKey::KP_Delete => Key::Delete,
+        GKey::Down | GKey::KP_Down => Key::Down,
+        GKey::End | GKey::KP_End => Key::End,
         GKey::Escape => Key::Escape,
         GKey::greater => Key::Greater,
+        GKey::Home | GKey::KP_Home => Key::Home,
+        GKey::Insert | GKey::KP_Insert => Key::Insert,
+        GKey::Left | GKey::KP_Left => Key::Left,
         GKey::less => Key::Less,
-        GKey::plus => Key::Plus,
-        GKey::Return => Key::Return,
-        GKey::space => Key::Space,
+        GKey::Page_Down | GKey::KP_Page_Down => Key::PageDown,
+        GKey::Page_Up | GKey::KP_Page_Up => Key::PageUp,
+        GKey::plus | GKey::KP_Add => Key::Plus,
+        GKey::Return | GKey::KP_Enter => Key::Return,
+        GKey::Right | GKey::KP_Right => Key::Right,
+        GKey::space | GKey::KP_Space => Key::Space,
+        // Shift+Tab is reported as ISO_Left_Tab.
+        GKey::Tab | GKey::KP_Tab | GKey::ISO_Left_Tab => Key::Tab,
+        GKey::Up | GKey::KP_Up => Key::Up,
 
         GKey::Alt_L | GKey::Alt_R => Key::Modifier(Modifier::Alt),
         GKey::Control_L | GKey::Control_R => Key::Modifier(Modifier::Control),
         GKey::Shift_L | GKey::Shift_R => Key::Modifier(Modifier::Shift),
+        GKey::Super_L | GKey::Super_R => Key::Modifier(Modifier::Super),
+        GKey::Hyper_L | GKey::Hyper_R => Key::Modifier(Modifier::Hyper),
+        // Shift pressed while holding alt is often reported as meta.
+        GKey::Meta_L | GKey::Meta_R => Key::Modifier(Modifier::Meta),
+
+        // Locks and level shifts only change what other keys produce.
+        GKey::Caps_Lock
+        | GKey::Shift_Lock
+        | GKey::Num_Lock
+        | GKey::ISO_Level3_Shift
+        | GKey::ISO_Level5_Shift => return None,
 
         _ => {
             if let Some(c) = key.to_unicode() {
                 Key::Char(c)
             } else {
-                todo!("unhandled key: {key}")
+                warn!("unhandled key: {key}");
+                return None;
             }
         }
-    }
+    };
+    Some(key)
 }
 
 pub fn modifiers_from_gdk(modifiers: ModifierType) -> Modifiers {
+    let alt = modifiers.contains(ModifierType::ALT_MASK);
     Modifiers {
-        alt: modifiers.contains(ModifierType::ALT_MASK),
+        alt,
         control: modifiers.contains(ModifierType::CONTROL_MASK),
         shift: modifiers.contains(ModifierType::SHIFT_MASK),
+        super_: modifiers.contains(ModifierType::SUPER_MASK),
+        hyper: modifiers.contains(ModifierType::HYPER_MASK),
+        // On X11, alt is commonly reported with both masks. Treat
+        // that as just alt so that alt bindings still match.
+        meta: modifiers.contains(ModifierType::META_MASK) && !alt,
     }
 }
 
@@ -39,14 +80,30 @@ mod tests {
 
     #[test]
     fn test_key_conversion() {
-        assert_eq!(key_from_gdk(GKey::a), Key::Char('a'));
-        assert_eq!(key_from_gdk(GKey::Escape), Key::Escape);
-        assert_eq!(key_from_gdk(GKey::Alt_L), Key::Modifier(Modifier::Alt));
+        assert_eq!(key_from_gdk(GKey::a), Some(Key::Char('a')));
+        assert_eq!(key_from_gdk(GKey::Escape), Some(Key::Escape));
+        assert_eq!(
+            key_from_gdk(GKey::Alt_L),
+            Some(Key::Modifier(Modifier::Alt))
+        );
         assert_eq!(
             key_from_gdk(GKey::Control_L),
-            Key::Modifier(Modifier::Control)
+            Some(Key::Modifier(Modifier::Control))
         );
-        assert_eq!(key_from_gdk(GKey::Shift_L), Key::Modifier(Modifier::Shift));
+        assert_eq!(
+            key_from_gdk(GKey::Shift_L),
+            Some(Key::Modifier(Modifier::Shift))
+        );
+        assert_eq!(key_from_gdk(GKey::F1), Some(Key::Function(1)));
+        assert_eq!(key_from_gdk(GKey::F35), Some(Key::Function(35)));
+        assert_eq!(key_from_gdk(GKey::ISO_Left_Tab), Some(Key::Tab));
+        assert_eq!(key_from_gdk(GKey::KP_Page_Up), Some(Key::PageUp));
+        assert_eq!(
+            key_from_gdk(GKey::Meta_L),
+            Some(Key::Modifier(Modifier::Meta))
+        );
+        assert_eq!(key_from_gdk(GKey::Caps_Lock), None);
+        assert_eq!(key_from_gdk(GKey::VoidSymbol), None);
     }
 
     #[test]
@@ -61,7 +118,30 @@ mod tests {
             Modifiers {
                 alt: true,
                 control: true,
-                shift: true
+                shift: true,
+                ..Modifiers::default()
+            }
+        );
+        assert_eq!(
+            modifiers_from_gdk(
+                ModifierType::SUPER_MASK
+                    | ModifierType::HYPER_MASK
+                    | ModifierType::META_MASK
+            ),
+            Modifiers {
+                super_: true,
+                hyper: true,
+                meta: true,
+                ..Modifiers::default()
+            }
+        );
+        assert_eq!(
+            modifiers_from_gdk(
+                ModifierType::ALT_MASK | ModifierType::META_MASK
+            ),
+            Modifiers {
+                alt: true,
+                ..Modifiers::default()
             }
         );
     }
@@ -3,6 +3,7 @@
 mod draw;
 mod init;
 mod key;
+mod mouse;
 
 use gtk4::Application;
 use gtk4::prelude::{ApplicationExt, ApplicationExtManual};
@@ -0,0 +1,82 @@
+use emma_app::mouse::{MouseEvent, Point};
+use emma_app::state::AppState;
+use gtk4::glib::{Propagation, clone};
+use gtk4::prelude::{GestureDragExt, GestureSingleExt, WidgetExt};
+use gtk4::{
+    DrawingArea, EventControllerMotion, EventControllerScroll,
+    EventControllerScrollFlags, GestureClick, GestureDrag, gdk,
+};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Add controllers for clicking, dragging and scrolling in `widget`,
+/// passing the events on to `state`.
+pub fn add_controllers(widget: &DrawingArea, state: &Rc<RefCell<AppState>>) {
+    let handle = Rc::new(clone!(
+        #[strong]
+        state,
+        #[strong]
+        widget,
+        move |event: MouseEvent| {
+            state.borrow_mut().handle_mouse_event(event);
+            widget.queue_draw();
+        }
+    ));
+
+    let click = GestureClick::new();
+    click.set_button(gdk::BUTTON_PRIMARY);
+    click.connect_pressed(clone!(
+        #[strong]
+        handle,
+        move |_self, n_press, x, y| {
+            handle(MouseEvent::Press {
+                point: Point::new(x, y),
+                clicks: n_press.max(1) as u32,
+            });
+        }
+    ));
+    widget.add_controller(click);
+
+    let drag = GestureDrag::new();
+    drag.set_button(gdk::BUTTON_PRIMARY);
+    drag.connect_drag_update(clone!(
+        #[strong]
+        handle,
+        move |gesture, offset_x, offset_y| {
+            if let Some((x, y)) = gesture.start_point() {
+                handle(MouseEvent::Drag(Point::new(
+                    x + offset_x,
+                    y + offset_y,
+                )));
+            }
+        }
+    ));
+    widget.add_controller(drag);
+
+    // Scroll events don't say where the pointer is, so keep track of
+    // it to know which pane to scroll.
+    let pointer = Rc::new(Cell::new(Point::default()));
+    let motion = EventControllerMotion::new();
+    motion.connect_motion(clone!(
+        #[strong]
+        pointer,
+        move |_self, x, y| pointer.set(Point::new(x, y))
+    ));
+    widget.add_controller(motion);
+
+    let scroll = EventControllerScroll::new(
+        EventControllerScrollFlags::VERTICAL
+            | EventControllerScrollFlags::DISCRETE,
+    );
+    scroll.connect_scroll(move |_self, _dx, dy| {
+        let steps = dy.round() as i32;
+        if steps != 0 {
+            handle(MouseEvent::Scroll {
+                point: pointer.get(),
+                steps,
+            });
+        }
+        Propagation::Stop
+    });
+    widget.add_controller(scroll);
+}